@@ -0,0 +1,111 @@
+//! Measures how long large messages take to be decoded, handled and encoded.
+//!
+//! Run with `cargo bench --bench messages`.
+
+use lspower::{
+    jsonrpc::{Incoming, Result},
+    lsp::*,
+    LanguageServer,
+    LspService,
+};
+use serde_json::json;
+use std::time::Instant;
+use tower::Service;
+
+const ITERATIONS: u32 = 50;
+
+#[derive(Debug)]
+struct Backend {
+    items: Vec<CompletionItem>,
+}
+
+#[lspower::async_trait]
+impl LanguageServer for Backend {
+    async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+        Ok(InitializeResult::default())
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn did_open(&self, params: DidOpenTextDocumentParams) {
+        assert!(!params.text_document.text.is_empty());
+    }
+
+    async fn completion(&self, _: CompletionParams) -> Result<Option<CompletionResponse>> {
+        Ok(Some(CompletionResponse::Array(self.items.clone())))
+    }
+}
+
+/// Runs `f` repeatedly, reporting the fastest run to reduce the noise from other processes.
+fn bench<F: FnMut()>(name: &str, mut f: F) {
+    let fastest = (0 .. ITERATIONS)
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed()
+        })
+        .min()
+        .unwrap();
+    println!("{:<36} {:>10.3} ms", name, fastest.as_secs_f64() * 1000.0);
+}
+
+fn main() {
+    let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+
+    let items = (0 .. 10_000)
+        .map(|i| CompletionItem {
+            label: format!("dimension_{}", i),
+            kind: Some(CompletionItemKind::Field),
+            detail: Some("sql: ${TABLE}.column ;;".into()),
+            ..CompletionItem::default()
+        })
+        .collect();
+    let (mut service, _) = LspService::new(|_| Backend { items });
+
+    let initialize = json!({ "jsonrpc": "2.0", "method": "initialize", "params": { "capabilities": {} }, "id": 0 });
+    let initialized = json!({ "jsonrpc": "2.0", "method": "initialized", "params": {} });
+    for message in [initialize, initialized].iter().cloned() {
+        let message = serde_json::from_value(message).unwrap();
+        runtime.block_on(service.call(message)).unwrap();
+    }
+
+    let line = "  dimension: id {\n    sql: ${TABLE}.id ;;\n    type: number\n  }\n";
+    let text = line.repeat(1024 * 1024 / line.len());
+    let did_open = json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/didOpen",
+        "params": {
+            "textDocument": { "uri": "file:///view.lkml", "languageId": "lookml", "version": 0, "text": text },
+        },
+    })
+    .to_string();
+    let completion = json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/completion",
+        "params": {
+            "textDocument": { "uri": "file:///view.lkml" },
+            "position": { "line": 0, "character": 0 },
+        },
+        "id": 1,
+    })
+    .to_string();
+
+    bench("decode didOpen (1 MiB)", || {
+        did_open.parse::<Incoming>().unwrap();
+    });
+
+    bench("handle didOpen (1 MiB)", || {
+        let message = did_open.parse::<Incoming>().unwrap();
+        runtime.block_on(service.call(message)).unwrap();
+    });
+
+    let mut buffer = Vec::new();
+    bench("handle completion (10000 items)", || {
+        let message = completion.parse::<Incoming>().unwrap();
+        let response = runtime.block_on(service.call(message)).unwrap().unwrap();
+        buffer.clear();
+        serde_json::to_writer(&mut buffer, &response).unwrap();
+    });
+}
@@ -0,0 +1,71 @@
+use lspower::{
+    jsonrpc::Result,
+    layers::{ConcurrencyLimitLayer, FilterLayer, LatencyLayer, LoggingLayer, TimeoutLayer},
+    lsp::*,
+    Client,
+    LanguageServer,
+    LspService,
+    Server,
+};
+use std::time::Duration;
+use tower::ServiceBuilder;
+
+#[derive(Debug)]
+struct Backend {
+    client: Client,
+}
+
+#[lspower::async_trait]
+impl LanguageServer for Backend {
+    async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+        Ok(InitializeResult {
+            server_info: None,
+            capabilities: ServerCapabilities {
+                hover_provider: Some(HoverProviderCapability::Simple(true)),
+                ..ServerCapabilities::default()
+            },
+        })
+    }
+
+    async fn initialized(&self, _: InitializedParams) {
+        self.client.log_message(MessageType::Info, "initialized!").await;
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn hover(&self, _: HoverParams) -> Result<Option<Hover>> {
+        Ok(Some(Hover {
+            contents: HoverContents::Scalar(MarkedString::String("hello".to_string())),
+            range: None,
+        }))
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+
+    let (stdin, stdout) = lspower::stdio();
+
+    let latency = LatencyLayer::new();
+    let metrics = latency.metrics();
+
+    let (service, messages) = LspService::new(|client| Backend { client });
+    let service = ServiceBuilder::new()
+        .layer(LoggingLayer::default())
+        .layer(latency)
+        .layer(FilterLayer::deny(vec!["textDocument/semanticTokens/full"]))
+        .layer(TimeoutLayer::new(Duration::from_secs(10)).method("textDocument/hover", Duration::from_secs(1)))
+        .layer(ConcurrencyLimitLayer::new(4))
+        .service(service);
+
+    let outcome = Server::new(stdin, stdout).interleave(messages).serve(service).await;
+
+    for (method, latency) in metrics.snapshot() {
+        log::info!("{}: {} calls, {:?} on average", method, latency.count, latency.mean());
+    }
+
+    std::process::exit(outcome.exit_code());
+}
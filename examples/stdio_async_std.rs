@@ -0,0 +1,51 @@
+//! A language server communicating over standard I/O, running on `async-std`.
+//!
+//! This requires the `runtime-agnostic` feature in place of the default `runtime-tokio`:
+//!
+//! ```sh
+//! cargo run --example stdio_async_std --no-default-features --features runtime-agnostic
+//! ```
+
+use lspower::{jsonrpc::Result, lsp::*, Client, LanguageServer, LspService, Server};
+
+#[derive(Debug)]
+struct Backend {
+    client: Client,
+}
+
+#[lspower::rpc]
+#[lspower::async_trait]
+impl LanguageServer for Backend {
+    async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+        Ok(InitializeResult {
+            server_info: None,
+            capabilities: lspower::capabilities::baseline(self),
+        })
+    }
+
+    async fn initialized(&self, _: InitializedParams) {
+        self.client.log_message(MessageType::Info, "initialized!").await;
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn hover(&self, _: HoverParams) -> Result<Option<Hover>> {
+        Ok(Some(Hover {
+            contents: HoverContents::Scalar(MarkedString::String("served by async-std".to_string())),
+            range: None,
+        }))
+    }
+}
+
+#[async_std::main]
+async fn main() {
+    env_logger::init();
+
+    let (stdin, stdout) = lspower::stdio();
+    let (service, messages) = LspService::new(|client| Backend { client });
+    let outcome = Server::new(stdin, stdout).interleave(messages).serve(service).await;
+
+    std::process::exit(outcome.exit_code());
+}
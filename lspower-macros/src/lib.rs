@@ -1,78 +1,151 @@
-//! Internal procedural macros for [`lspower`](https://docs.rs/lspower).
+//! Procedural macros for [`lspower`](https://docs.rs/lspower).
 //!
-//! This crate should not be used directly.
+//! This crate should not be used directly, its macros are re-exported by `lspower`.
 
 use heck::CamelCase;
 use proc_macro::TokenStream;
-use quote::quote;
+use quote::{format_ident, quote, quote_spanned};
 use syn::{
+    parse::{Parse, ParseStream},
     parse_macro_input,
+    spanned::Spanned,
     AttributeArgs,
     FnArg,
+    ImplItem,
+    Item,
+    ItemImpl,
     ItemTrait,
-    Lit,
     Meta,
-    MetaNameValue,
     NestedMeta,
     ReturnType,
     TraitItem,
 };
 
-/// Macro for generating LSP server implementation from [`lsp-types`](https://docs.rs/lsp-types).
+/// Macro for generating JSON-RPC routers for traits whose methods are annotated with
+/// `#[rpc(name = "...")]`.
 ///
-/// This procedural macro annotates the `lspower::LanguageServer` trait and generates a
-/// corresponding opaque `ServerRequest` struct along with a `handle_request()` function.
+/// Methods handling a request or notification declared by `lsp-types` may instead be annotated
+/// with `#[rpc(request = lsp::request::HoverRequest)]` or
+/// `#[rpc(notification = lsp::notification::DidOpenTextDocument)]`. The name of the method is
+/// then taken from the declaration, and the crate fails to compile if the parameters or result of
+/// the handler disagree with it.
+///
+/// On an extension trait `Foo`, this generates a `FooRouter<T>` implementing
+/// `lspower::extension::Router`, which routes the requests and notifications declared by the
+/// trait to any `T: Foo`.
+///
+/// On an implementation of `lspower::LanguageServer`, this records which handlers it implements,
+/// from which `lspower::capabilities::baseline` infers the capabilities of the server.
+///
+/// On the `lspower::LanguageServer` trait, annotated with `#[rpc(internal)]`, this generates a
+/// corresponding opaque `ServerRequest` struct along with a `handle_request()` function, and the
+/// implementation of the trait for `lspower::workspace::WorkspaceFolders`.
 #[proc_macro_attribute]
 pub fn rpc(attr: TokenStream, item: TokenStream) -> TokenStream {
+    if syn::parse::<RpcArg>(attr.clone()).is_ok() {
+        return item;
+    }
+
     let attr_args = parse_macro_input!(attr as AttributeArgs);
 
-    match attr_args.as_slice() {
-        [] => {},
-        [NestedMeta::Meta(meta)] if meta.path().is_ident("name") => return item,
+    let internal = match attr_args.as_slice() {
+        [] => false,
+        [NestedMeta::Meta(Meta::Path(path))] if path.is_ident("internal") => true,
         _ => panic!("unexpected attribute arguments"),
-    }
+    };
+
+    let lang_server_trait = match parse_macro_input!(item as Item) {
+        Item::Trait(item) => item,
+        Item::Impl(item) if !internal => return gen_implemented_handlers(item).into(),
+        _ => panic!("expected a trait or an implementation of `LanguageServer`"),
+    };
+    let krate = if internal { quote!(crate) } else { quote!(::lspower) };
+    let method_calls = parse_method_calls(&lang_server_trait, &krate);
+    let signature_checks = gen_signature_checks(&method_calls, &krate);
+    let router = if internal {
+        gen_server_router(&lang_server_trait.ident, &method_calls)
+    } else {
+        gen_extension_router(&lang_server_trait, &method_calls)
+    };
 
-    let lang_server_trait = parse_macro_input!(item as ItemTrait);
-    let method_calls = parse_method_calls(&lang_server_trait);
-    let req_types_and_router_fn = gen_server_router(&lang_server_trait.ident, &method_calls);
+    // The method attributes have served their purpose, and would otherwise require `rpc` to be
+    // imported wherever the trait is declared.
+    let mut lang_server_trait = lang_server_trait.clone();
+    for item in &mut lang_server_trait.items {
+        if let TraitItem::Method(method) = item {
+            method.attrs.retain(|attr| !attr.path.is_ident("rpc"));
+        }
+    }
 
     let tokens = quote! {
         #lang_server_trait
-        #req_types_and_router_fn
+        #signature_checks
+        #router
     };
 
     tokens.into()
 }
 
+/// Argument of the `#[rpc(...)]` attribute of a method.
+enum RpcArg {
+    /// `name = "..."`, the name of the method.
+    Name(syn::LitStr),
+    /// `request = ...`, a type implementing `lsp::request::Request`.
+    Request(syn::Path),
+    /// `notification = ...`, a type implementing `lsp::notification::Notification`.
+    Notification(syn::Path),
+}
+
+impl Parse for RpcArg {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let key: syn::Ident = input.parse()?;
+        input.parse::<syn::Token![=]>()?;
+        if key == "name" {
+            input.parse().map(RpcArg::Name)
+        } else if key == "request" {
+            input.parse().map(RpcArg::Request)
+        } else if key == "notification" {
+            input.parse().map(RpcArg::Notification)
+        } else {
+            Err(syn::Error::new(
+                key.span(),
+                "expected `name`, `request` or `notification`",
+            ))
+        }
+    }
+}
+
 struct MethodCall<'a> {
-    rpc_name: String,
+    /// Expression evaluating to the name of the method, which is also valid as a pattern.
+    rpc_name: proc_macro2::TokenStream,
+    rpc_arg: RpcArg,
     handler_name: &'a syn::Ident,
     params: Option<&'a syn::Type>,
     result: Option<&'a syn::Type>,
 }
 
-fn parse_method_calls(lang_server_trait: &ItemTrait) -> Vec<MethodCall> {
+fn parse_method_calls<'a>(lang_server_trait: &'a ItemTrait, krate: &proc_macro2::TokenStream) -> Vec<MethodCall<'a>> {
     let mut calls = Vec::new();
 
     for item in &lang_server_trait.items {
         let method = match item {
-            TraitItem::Method(m) if m.sig.ident == "request_else" => continue,
             TraitItem::Method(m) => m,
             _ => continue,
         };
 
-        let rpc_name = method
-            .attrs
-            .iter()
-            .filter_map(|attr| attr.parse_args::<Meta>().ok())
-            .filter(|meta| meta.path().is_ident("name"))
-            .find_map(|meta| match meta {
-                Meta::NameValue(MetaNameValue { lit: Lit::Str(lit), .. }) => {
-                    Some(lit.value().trim_matches('"').to_owned())
-                },
-                _ => panic!("expected string literal for `#[rpc(name = ???)]` attribute"),
-            })
-            .expect("expected `#[rpc(name = \"foo\")]` attribute");
+        // Methods without an `#[rpc]` attribute (e.g., `request_else`) are not routed.
+        let rpc_arg = match method.attrs.iter().find(|attr| attr.path.is_ident("rpc")) {
+            Some(attr) => attr
+                .parse_args::<RpcArg>()
+                .unwrap_or_else(|e| panic!("invalid `#[rpc]` attribute: {}", e)),
+            None => continue,
+        };
+
+        let rpc_name = match &rpc_arg {
+            RpcArg::Name(name) => quote!(#name),
+            RpcArg::Request(path) => quote!(<#path as #krate::lsp::request::Request>::METHOD),
+            RpcArg::Notification(path) => quote!(<#path as #krate::lsp::notification::Notification>::METHOD),
+        };
 
         let params = method.sig.inputs.iter().nth(1).and_then(|arg| match arg {
             FnArg::Typed(pat) => Some(&*pat.ty),
@@ -86,6 +159,7 @@ fn parse_method_calls(lang_server_trait: &ItemTrait) -> Vec<MethodCall> {
 
         calls.push(MethodCall {
             rpc_name,
+            rpc_arg,
             handler_name: &method.sig.ident,
             params,
             result,
@@ -95,38 +169,93 @@ fn parse_method_calls(lang_server_trait: &ItemTrait) -> Vec<MethodCall> {
     calls
 }
 
+/// Generates the checks that the handlers of methods declared by `lsp-types` agree with the
+/// parameters and result of the declaration.
+///
+/// A handler taking the wrong parameters fails to compile:
+///
+/// ```compile_fail,E0308
+/// use lspower::{jsonrpc::Result, lsp, rpc};
+///
+/// #[rpc]
+/// #[lspower::async_trait]
+/// pub trait Extensions: Send + Sync + 'static {
+///     #[rpc(request = lsp::request::HoverRequest)]
+///     async fn hover(&self, params: lsp::CompletionParams) -> Result<Option<lsp::Hover>>;
+/// }
+/// ```
+///
+/// And so does a handler returning the wrong result:
+///
+/// ```compile_fail,E0308
+/// use lspower::{jsonrpc::Result, lsp, rpc};
+///
+/// #[rpc]
+/// #[lspower::async_trait]
+/// pub trait Extensions: Send + Sync + 'static {
+///     #[rpc(request = lsp::request::HoverRequest)]
+///     async fn hover(&self, params: lsp::HoverParams) -> Result<Option<lsp::CompletionResponse>>;
+/// }
+/// ```
+fn gen_signature_checks(methods: &[MethodCall], krate: &proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+    methods
+        .iter()
+        .filter_map(|method| {
+            let handler = method.handler_name;
+            let declaration = match (&method.rpc_arg, method.result) {
+                (RpcArg::Name(_), _) => return None,
+                (RpcArg::Request(path), Some(_)) => quote!(<#path as #krate::lsp::request::Request>),
+                (RpcArg::Notification(path), None) => quote!(<#path as #krate::lsp::notification::Notification>),
+                (RpcArg::Request(_), None) => panic!("handler `{}` of a request must return a result", handler),
+                (RpcArg::Notification(_), Some(_)) => {
+                    panic!("handler `{}` of a notification must not return a result", handler)
+                },
+            };
+
+            let params = match method.params {
+                Some(p) => quote_spanned!(p.span()=> fn params(params: #p) -> #declaration::Params { params }),
+                None => quote_spanned!(handler.span()=> fn params(params: ()) -> #declaration::Params { params }),
+            };
+            let result = method.result.map(|r| {
+                quote_spanned! {r.span()=>
+                    #[allow(dead_code)]
+                    fn result(result: #r) -> #krate::jsonrpc::Result<#declaration::Result> { result }
+                }
+            });
+
+            // Each handler is checked in its own scope, so that type errors point to its signature.
+            Some(quote! {
+                const _: () = {
+                    #[allow(dead_code)]
+                    #params
+                    #result
+                };
+            })
+        })
+        .collect()
+}
+
 fn gen_server_router(trait_name: &syn::Ident, methods: &[MethodCall]) -> proc_macro2::TokenStream {
+    let rpc_names: Vec<_> = methods.iter().map(|method| &method.rpc_name).collect();
     let variant_names: Vec<syn::Ident> = methods
         .iter()
         .map(|method| syn::parse_str(&method.handler_name.to_string().to_camel_case()).unwrap())
         .collect();
 
-    let variants: proc_macro2::TokenStream = methods
+    let params_variants: proc_macro2::TokenStream = methods
         .iter()
         .zip(variant_names.iter())
-        .map(|(method, var_name)| {
-            let rpc_name = &method.rpc_name;
-            let variant = match (method.result.is_some(), method.params) {
-                (true, Some(p)) => quote!(#var_name { params: Params<#p>, id: Id },),
-                (true, None) => quote!(#var_name { id: Id },),
-                (false, Some(p)) => quote!(#var_name { params: Params<#p> },),
-                (false, None) => quote!(#var_name,),
-            };
-
-            quote! {
-                #[serde(rename = #rpc_name)]
-                #variant
-            }
-        })
+        .filter_map(|(method, var_name)| method.params.map(|p| quote!(#var_name(#p),)))
         .collect();
 
-    let id_match_arms: proc_macro2::TokenStream = methods
+    let params_seed_arms: proc_macro2::TokenStream = methods
         .iter()
         .zip(variant_names.iter())
         .filter_map(|(method, var_name)| {
-            method
-                .result
-                .map(|_| quote!(ServerMethod::#var_name { ref id, .. } => Some(id),))
+            let rpc_name = &method.rpc_name;
+            method.params.map(|p| {
+                quote!(Some(#rpc_name) => Option::<#p>::deserialize(deserializer).map(|p| p.map(Params::#var_name)),)
+            })
         })
         .collect();
 
@@ -134,83 +263,110 @@ fn gen_server_router(trait_name: &syn::Ident, methods: &[MethodCall]) -> proc_ma
         .iter()
         .zip(variant_names.iter())
         .map(|(method, var_name)| {
-            let rpc_name = method.rpc_name.as_str();
+            let rpc_name = &method.rpc_name;
             let handler = &method.handler_name;
-            match (method.result.is_some(), method.params.is_some()) {
-                (true, true) if rpc_name == "initialize" => quote! {
-                    (ServerMethod::#var_name { params: Valid(p), id }, StateKind::Uninitialized) => {
-                        state.set(StateKind::Initializing);
-                        let state = state.clone();
-                        Box::pin(async move {
-                            let res = match server.#handler(p).await {
-                                Ok(result) => {
-                                    let result = serde_json::to_value(result).unwrap();
-                                    info!("language server initialized");
-                                    state.set(StateKind::Initialized);
-                                    Response::ok(id, result)
-                                }
-                                Err(error) => {
-                                    state.set(StateKind::Uninitialized);
-                                    Response::error(Some(id), error)
-                                },
-                            };
+            let typed = quote!(|params| match params {
+                Params::#var_name(p) => Some(p),
+                _ => None,
+            });
+            match (method.result.is_some(), method.params) {
+                (true, Some(p)) if *handler == "initialize" => quote! {
+                    (#rpc_name, Some(id), StateKind::Uninitialized) => match take::<#p>(params, #typed) {
+                        Ok(p) => {
+                            state.set(StateKind::Initializing);
+                            hooks.initialize(&p);
+                            let notebook_document_sync = hooks.notebook_document_sync();
+                            let state = state.clone();
+                            Box::pin(async move {
+                                let res = match server.#handler(p).await {
+                                    Ok(result) => {
+                                        crate::capabilities::check(&server, &result.capabilities);
+                                        let mut result = serde_json::to_value(result).unwrap();
+                                        if let Some(sync) = notebook_document_sync {
+                                            result["capabilities"]["notebookDocumentSync"] = sync;
+                                        }
+                                        info!("language server initialized");
+                                        state.set(StateKind::Initialized);
+                                        Response::ok(id, result)
+                                    }
+                                    Err(error) => {
+                                        state.set(StateKind::Uninitialized);
+                                        Response::error(Some(id), error)
+                                    },
+                                };
 
-                            Ok(Some(Outgoing::Response(res)))
-                        })
-                    }
-                    (ServerMethod::#var_name { params: Invalid(e), id }, StateKind::Uninitialized) => {
-                        error!("invalid parameters for {:?} request", #rpc_name);
-                        let res = Response::error(Some(id), Error::invalid_params(e));
-                        future::ok(Some(Outgoing::Response(res))).boxed()
-                    }
-                    (ServerMethod::#var_name { id, .. }, StateKind::Initializing) => {
+                                Ok(Some(Outgoing::Response(res)))
+                            })
+                        }
+                        Err(e) => {
+                            error!("invalid parameters for {:?} request", #rpc_name);
+                            let res = Response::error(Some(id), Error::invalid_params(e));
+                            future::ok(Some(Outgoing::Response(res))).boxed()
+                        }
+                    },
+                    (#rpc_name, Some(id), StateKind::Initializing) => {
                         warn!("received duplicate `initialize` request, ignoring");
                         let res = Response::error(Some(id), Error::invalid_request());
                         future::ok(Some(Outgoing::Response(res))).boxed()
                     }
                 },
-                (true, false) if rpc_name == "shutdown" => quote! {
-                    (ServerMethod::#var_name { id }, StateKind::Initialized) => {
+                (true, None) if *handler == "shutdown" => quote! {
+                    (#rpc_name, Some(id), StateKind::Initialized) => {
                         info!("shutdown request received, shutting down");
                         state.set(StateKind::ShutDown);
-                        pending
-                            .execute(id, async move { server.#handler().await })
-                            .map(|v| Ok(Some(Outgoing::Response(v))))
-                            .boxed()
+                        let drained = pending.drain(hooks.shutdown_grace_period());
+                        let pending = pending.clone();
+                        Box::pin(async move {
+                            drained.await;
+                            let res = pending.execute(id, async move { server.#handler().await }).await;
+                            Ok(Some(Outgoing::Response(res)))
+                        })
                     }
                 },
-                (true, true) => quote! {
-                    (ServerMethod::#var_name { params: Valid(p), id }, StateKind::Initialized) => {
-                        pending
+                (true, Some(p)) => quote! {
+                    (#rpc_name, Some(id), StateKind::Initialized) => match take::<#p>(params, #typed) {
+                        Ok(p) => pending
                             .execute(id, async move { server.#handler(p).await })
                             .map(|v| Ok(Some(Outgoing::Response(v))))
-                            .boxed()
-                    }
-                    (ServerMethod::#var_name { params: Invalid(e), id }, StateKind::Initialized) => {
-                        error!("invalid parameters for {:?} request", #rpc_name);
-                        let res = Response::error(Some(id), Error::invalid_params(e));
-                        future::ok(Some(Outgoing::Response(res))).boxed()
-                    }
+                            .boxed(),
+                        Err(e) => {
+                            error!("invalid parameters for {:?} request", #rpc_name);
+                            let res = Response::error(Some(id), Error::invalid_params(e));
+                            future::ok(Some(Outgoing::Response(res))).boxed()
+                        }
+                    },
                 },
-                (true, false) => quote! {
-                    (ServerMethod::#var_name { id }, StateKind::Initialized) => {
+                (true, None) => quote! {
+                    (#rpc_name, Some(id), StateKind::Initialized) => {
                         pending
                             .execute(id, async move { server.#handler().await })
                             .map(|v| Ok(Some(Outgoing::Response(v))))
                             .boxed()
                     }
                 },
-                (false, true) => quote! {
-                    (ServerMethod::#var_name { params: Valid(p) }, StateKind::Initialized) => {
-                        Box::pin(async move { server.#handler(p).await; Ok(None) })
-                    }
-                    (ServerMethod::#var_name { .. }, StateKind::Initialized) => {
-                        warn!("invalid parameters for {:?} notification", #rpc_name);
-                        future::ok(None).boxed()
-                    }
+                (false, Some(p)) if *handler == "did_change_configuration" => quote! {
+                    (#rpc_name, _, StateKind::Initialized) => match take::<#p>(params, #typed) {
+                        Ok(p) => {
+                            hooks.did_change_configuration(&p);
+                            Box::pin(async move { server.#handler(p).await; Ok(None) })
+                        }
+                        Err(_) => {
+                            warn!("invalid parameters for {:?} notification", #rpc_name);
+                            future::ok(None).boxed()
+                        }
+                    },
+                },
+                (false, Some(p)) => quote! {
+                    (#rpc_name, _, StateKind::Initialized) => match take::<#p>(params, #typed) {
+                        Ok(p) => Box::pin(async move { server.#handler(p).await; Ok(None) }),
+                        Err(_) => {
+                            warn!("invalid parameters for {:?} notification", #rpc_name);
+                            future::ok(None).boxed()
+                        }
+                    },
                 },
-                (false, false) => quote! {
-                    (ServerMethod::#var_name, StateKind::Initialized) => {
+                (false, None) => quote! {
+                    (#rpc_name, _, StateKind::Initialized) => {
                         Box::pin(async move { server.#handler().await; Ok(None) })
                     }
                 },
@@ -218,13 +374,16 @@ fn gen_server_router(trait_name: &syn::Ident, methods: &[MethodCall]) -> proc_ma
         })
         .collect();
 
+    let handler_names: Vec<String> = methods.iter().map(|method| method.handler_name.to_string()).collect();
+    let workspace_folders_impl = gen_workspace_folders_impl(trait_name, methods);
+
     quote! {
         mod generated_impl {
             use super::{#trait_name};
             use crate::{
-                jsonrpc::{not_initialized_error, Error, ErrorCode, Id, Outgoing, Response, ServerRequests, Version},
+                jsonrpc::{not_initialized_error, Error, Id, Outgoing, Response, ServerRequests, Version},
                 server::{State, StateKind},
-                service::ExitedError,
+                service::{ExitedError, Hooks},
             };
             use futures::{future, FutureExt};
             use log::{error, info, warn};
@@ -232,117 +391,345 @@ fn gen_server_router(trait_name: &syn::Ident, methods: &[MethodCall]) -> proc_ma
                 request::{GotoDeclarationParams, GotoImplementationParams, GotoTypeDefinitionParams},
                 *,
             };
+            use serde_json::value::RawValue;
             use std::{future::Future, pin::Pin, sync::Arc};
 
+            /// Names of the handlers of the `LanguageServer` trait and of the methods they handle.
+            pub(crate) const HANDLERS: &[(&str, &str)] = &[#((#handler_names, #rpc_names)),*];
+
             /// A client-to-server LSP request.
-            #[derive(Clone, Debug, PartialEq, serde::Deserialize)]
-            #[cfg_attr(test, derive(serde::Serialize))]
+            #[derive(Clone, Debug, serde::Serialize)]
             pub struct ServerRequest {
                 jsonrpc: Version,
-                #[serde(flatten)]
-                kind: RequestKind,
+                method: String,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                params: Option<Params>,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                id: Option<Id>,
             }
 
-            #[derive(Clone, Debug, PartialEq, serde::Deserialize)]
-            #[cfg_attr(test, derive(serde::Serialize))]
-            #[serde(untagged)]
-            enum RequestKind {
-                Known(ServerMethod),
-                Other { id: Option<Id>, method: String, params: Option<serde_json::Value> },
-            }
+            impl ServerRequest {
+                pub(crate) fn new(method: String, id: Option<Id>, params: Option<Params>) -> Self {
+                    ServerRequest {
+                        jsonrpc: Version,
+                        method,
+                        params,
+                        id,
+                    }
+                }
 
-            #[derive(Clone, Debug, PartialEq, serde::Deserialize)]
-            #[cfg_attr(test, derive(serde::Serialize))]
-            #[serde(tag = "method")]
-            enum ServerMethod {
-                #variants
-                #[serde(rename = "$/cancelRequest")]
-                CancelRequest { id: Id },
-                #[serde(rename = "exit")]
-                Exit,
+                /// Returns the name of the method.
+                pub fn method(&self) -> &str {
+                    &self.method
+                }
+
+                /// Returns the request ID, or `None` if this is a notification.
+                pub fn id(&self) -> Option<&Id> {
+                    self.id.as_ref()
+                }
+
+                /// Returns the parameters, if any.
+                pub(crate) fn params(&self) -> Option<&Params> {
+                    self.params.as_ref()
+                }
             }
 
-            impl ServerMethod {
-                fn id(&self) -> Option<&Id> {
-                    match *self {
-                        #id_match_arms
-                        _ => None,
-                    }
+            impl PartialEq for ServerRequest {
+                fn eq(&self, other: &Self) -> bool {
+                    let params = |request: &Self| serde_json::to_value(&request.params).ok();
+                    self.method == other.method && self.id == other.id && params(self) == params(other)
                 }
             }
 
-            #[derive(Clone, Debug, PartialEq)]
-            #[cfg_attr(test, derive(serde::Serialize))]
-            enum Params<T> {
-                Valid(T),
-                #[cfg_attr(test, serde(skip_serializing))]
-                Invalid(String),
+            /// Parameters of a request, either deserialized into the type expected by the handler
+            /// of its method or kept as raw JSON.
+            #[derive(Clone, Debug, serde::Serialize)]
+            #[serde(untagged)]
+            pub(crate) enum Params {
+                #params_variants
+                CancelRequest(CancelParams),
+                Raw(Box<RawValue>),
+            }
+
+            /// Deserializes the parameters of a request for the given method.
+            ///
+            /// If `typed` is set, the parameters of known methods are deserialized straight into
+            /// the type expected by their handler. This fails the whole message if they are invalid,
+            /// whereas raw parameters only fail once the request is handled, with an error response.
+            pub(crate) struct ParamsSeed<'a> {
+                pub(crate) method: Option<&'a str>,
+                pub(crate) typed: bool,
             }
 
-            impl<'de, T: serde::Deserialize<'de>> serde::Deserialize<'de> for Params<T> {
-                fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            impl<'de> serde::de::DeserializeSeed<'de> for ParamsSeed<'_> {
+                type Value = Option<Params>;
+
+                fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
                 where
                     D: serde::Deserializer<'de>,
                 {
-                    match serde::Deserialize::deserialize(deserializer) {
-                        Ok(Some(v)) => Ok(Params::Valid(v)),
-                        Ok(None) => Ok(Params::Invalid("Missing params field".to_string())),
-                        Err(e) => Ok(Params::Invalid(e.to_string())),
+                    use serde::Deserialize;
+
+                    match self.method.filter(|_| self.typed) {
+                        #params_seed_arms
+                        Some("$/cancelRequest") => {
+                            Option::<CancelParams>::deserialize(deserializer).map(|p| p.map(Params::CancelRequest))
+                        }
+                        _ => Option::<Box<RawValue>>::deserialize(deserializer).map(|p| p.map(Params::Raw)),
                     }
                 }
             }
 
+            /// Takes the parameters of a request, deserializing them if they were kept as raw JSON.
+            fn take<T>(params: Option<Params>, typed: fn(Params) -> Option<T>) -> Result<T, String>
+            where
+                T: serde::de::DeserializeOwned,
+            {
+                match params {
+                    Some(Params::Raw(params)) => serde_json::from_str(params.get()).map_err(|e| e.to_string()),
+                    Some(params) => typed(params).ok_or_else(|| "Params of another method".to_string()),
+                    None => Err("Missing params field".to_string()),
+                }
+            }
+
             pub(crate) fn handle_request<T: #trait_name>(
                 server: T,
                 state: &Arc<State>,
                 pending: &ServerRequests,
                 request: ServerRequest,
+                hooks: &Hooks,
             ) -> Pin<Box<dyn Future<Output = Result<Option<Outgoing>, ExitedError>> + Send>> {
-                use Params::*;
-
-                let method = match request.kind {
-                    RequestKind::Known(method) => method,
-                    RequestKind::Other { id: Some(id), method, params } => {
-                       return pending
-                            .execute(id, async move { server.request_else(&method, params).await })
-                            .map(|v| Ok(Some(Outgoing::Response(v))))
-                            .boxed();
-                    }
-                    RequestKind::Other { id: None, method, .. } if !method.starts_with("$/") => {
-                        error!("method {:?} not found", method);
-                        return future::ok(None).boxed();
-                    }
-                    RequestKind::Other { id: None, .. } => return future::ok(None).boxed(),
-                };
+                let ServerRequest { method, params, id, .. } = request;
 
-                match (method, state.get()) {
+                match (method.as_str(), id, state.get()) {
                     #route_match_arms
-                    (ServerMethod::CancelRequest { id }, StateKind::Initialized) => {
-                        pending.cancel(&id);
+                    ("$/cancelRequest", _, StateKind::Initialized) => {
+                        let typed = |params| match params {
+                            Params::CancelRequest(p) => Some(p),
+                            _ => None,
+                        };
+                        match take::<CancelParams>(params, typed) {
+                            Ok(CancelParams { id: NumberOrString::Number(id) }) => pending.cancel(&Id::Number(id as u64)),
+                            Ok(CancelParams { id: NumberOrString::String(id) }) => pending.cancel(&Id::String(id)),
+                            Err(e) => warn!("invalid parameters for \"$/cancelRequest\" notification: {}", e),
+                        }
                         future::ok(None).boxed()
                     }
-                    (ServerMethod::Exit, _) => {
+                    ("exit", _, _) => {
                         info!("exit notification received, stopping");
                         state.set(StateKind::Exited);
                         pending.cancel_all();
                         future::ok(None).boxed()
                     }
-                    (other, StateKind::Uninitialized) => Box::pin(match other.id().cloned() {
+                    (#(#rpc_names)|* | "$/cancelRequest", id, kind) => Box::pin(match id {
                         None => future::ok(None),
-                        Some(id) => {
+                        Some(id) if kind == StateKind::Uninitialized => {
                             let res = Response::error(Some(id), not_initialized_error());
                             future::ok(Some(Outgoing::Response(res)))
                         }
-                    }),
-                    (other, _) => Box::pin(match other.id().cloned() {
-                        None => future::ok(None),
                         Some(id) => {
                             let res = Response::error(Some(id), Error::invalid_request());
                             future::ok(Some(Outgoing::Response(res)))
                         }
                     }),
+                    (_, id, _) if hooks.handles_extension(&method) => {
+                        let params = params.and_then(|params| match params {
+                            Params::Raw(params) => Some(params),
+                            params => serde_json::value::to_raw_value(&params).ok(),
+                        });
+                        hooks.route_extension(&method, id, params)
+                    }
+                    (_, Some(id), _) => {
+                        let params = params.and_then(|params| serde_json::to_value(params).ok());
+                        pending
+                            .execute(id, async move { server.request_else(&method, params).await })
+                            .map(|v| Ok(Some(Outgoing::Response(v))))
+                            .boxed()
+                    }
+                    (_, None, _) if !method.starts_with("$/") => {
+                        error!("method {:?} not found", method);
+                        future::ok(None).boxed()
+                    }
+                    (_, None, _) => future::ok(None).boxed(),
                 }
             }
+
+            #workspace_folders_impl
         }
     }
 }
+
+/// Generates the implementation of the `LanguageServer` trait for `WorkspaceFolders`, which routes
+/// every handler to the instances of the workspace folders it concerns.
+fn gen_workspace_folders_impl(trait_name: &syn::Ident, methods: &[MethodCall]) -> proc_macro2::TokenStream {
+    let handlers: proc_macro2::TokenStream = methods
+        .iter()
+        .map(|method| {
+            let rpc_name = &method.rpc_name;
+            let handler = method.handler_name;
+            let (params, param, arg) = match method.params {
+                Some(p) => (quote!(params: #p), quote!(params), quote!(params)),
+                None => (quote!(), quote!(()), quote!()),
+            };
+            match method.result {
+                Some(r) if handler == "initialize" => quote! {
+                    async fn #handler(&self, #params) -> #r {
+                        self.initialize_folders(params).await
+                    }
+                },
+                Some(r) if handler == "shutdown" => quote! {
+                    async fn #handler(&self) -> #r {
+                        self.shutdown_folders().await
+                    }
+                },
+                Some(r) => quote! {
+                    async fn #handler(&self, #params) -> #r {
+                        self.route_request(#rpc_name, #param, |server, #param| {
+                            Box::pin(async move { server.#handler(#arg).await })
+                        })
+                        .await
+                    }
+                },
+                None if handler == "did_change_workspace_folders" => quote! {
+                    async fn #handler(&self, #params) {
+                        self.change_folders(params).await
+                    }
+                },
+                None => quote! {
+                    async fn #handler(&self, #params) {
+                        self.route_notification(#rpc_name, #param, |server, #param| {
+                            Box::pin(async move { server.#handler(#arg).await })
+                        })
+                        .await
+                    }
+                },
+            }
+        })
+        .collect();
+
+    quote! {
+        #[async_trait::async_trait]
+        impl<S: #trait_name> #trait_name for crate::workspace::WorkspaceFolders<S> {
+            #handlers
+
+            async fn request_else(
+                &self,
+                method: &str,
+                params: Option<serde_json::Value>,
+            ) -> crate::jsonrpc::Result<Option<serde_json::Value>> {
+                let handler = |server: Arc<S>, params| {
+                    let method = method.to_owned();
+                    Box::pin(async move { server.request_else(&method, params).await }) as future::BoxFuture<_>
+                };
+                self.route_request(method, params, handler).await
+            }
+        }
+    }
+}
+
+fn gen_extension_router(extension_trait: &ItemTrait, methods: &[MethodCall]) -> proc_macro2::TokenStream {
+    let vis = &extension_trait.vis;
+    let trait_name = &extension_trait.ident;
+    let router_name = format_ident!("{}Router", trait_name);
+    let doc = format!(
+        "Routes the requests and notifications declared in [`{}`] to a server implementing it.",
+        trait_name
+    );
+    let rpc_names: Vec<_> = methods.iter().map(|method| &method.rpc_name).collect();
+
+    let route_match_arms: proc_macro2::TokenStream = methods
+        .iter()
+        .map(|method| {
+            let rpc_name = &method.rpc_name;
+            let handler = &method.handler_name;
+            match (method.result.is_some(), method.params) {
+                (true, Some(p)) => quote! {
+                    #rpc_name => {
+                        let params = params.parse::<#p>()?;
+                        Ok(Call::request(async move { #trait_name::#handler(&*server, params).await }))
+                    }
+                },
+                (true, None) => quote! {
+                    #rpc_name => Ok(Call::request(async move { #trait_name::#handler(&*server).await })),
+                },
+                (false, Some(p)) => quote! {
+                    #rpc_name => {
+                        let params = params.parse::<#p>()?;
+                        Ok(Call::notification(async move { #trait_name::#handler(&*server, params).await }))
+                    }
+                },
+                (false, None) => quote! {
+                    #rpc_name => Ok(Call::notification(async move { #trait_name::#handler(&*server).await })),
+                },
+            }
+        })
+        .collect();
+
+    quote! {
+        #[doc = #doc]
+        #vis struct #router_name<T>(::std::sync::Arc<T>);
+
+        impl<T> #router_name<T> {
+            /// Creates a router calling the handlers of the given server.
+            #vis fn new(server: ::std::sync::Arc<T>) -> Self {
+                #router_name(server)
+            }
+        }
+
+        impl<T> ::std::fmt::Debug for #router_name<T> {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                f.debug_struct(stringify!(#router_name)).finish()
+            }
+        }
+
+        impl<T> ::lspower::extension::Router for #router_name<T>
+        where
+            T: #trait_name + Send + Sync + 'static,
+        {
+            fn methods(&self) -> &'static [&'static str] {
+                const METHODS: &[&str] = &[#(#rpc_names),*];
+                METHODS
+            }
+
+            #[allow(unused_variables)]
+            fn call(
+                &self,
+                method: &str,
+                params: ::lspower::extension::Params,
+            ) -> ::std::result::Result<::lspower::extension::Call, ::std::string::String> {
+                use ::lspower::extension::Call;
+
+                let server = self.0.clone();
+                match method {
+                    #route_match_arms
+                    _ => Err(format!("method {:?} not found", method)),
+                }
+            }
+        }
+    }
+}
+
+fn gen_implemented_handlers(mut server_impl: ItemImpl) -> proc_macro2::TokenStream {
+    match &server_impl.trait_ {
+        Some((None, path, _))
+            if path.segments.last().map(|segment| segment.ident.to_string()).as_deref() == Some("LanguageServer") => {},
+        _ => panic!("expected an implementation of `LanguageServer`"),
+    }
+
+    let handler_names: Vec<String> = server_impl
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            ImplItem::Method(method) => Some(method.sig.ident.to_string()),
+            _ => None,
+        })
+        .collect();
+
+    server_impl.items.push(syn::parse_quote! {
+        fn implemented_handlers(&self) -> ::std::option::Option<&'static [&'static str]> {
+            ::std::option::Option::Some(&[#(#handler_names),*])
+        }
+    });
+
+    quote!(#server_impl)
+}
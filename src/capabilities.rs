@@ -0,0 +1,313 @@
+//! Server capabilities inferred from the handlers implemented by a server.
+//!
+//! Annotating the implementation of [`LanguageServer`] with [`rpc`](crate::rpc) records which of
+//! its handlers are overridden, from which [`baseline`] infers the capabilities to advertise:
+//!
+//! ```rust
+//! use lspower::{capabilities, jsonrpc::Result, lsp::*, LanguageServer};
+//!
+//! struct Backend;
+//!
+//! #[lspower::rpc]
+//! #[lspower::async_trait]
+//! impl LanguageServer for Backend {
+//!     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+//!         Ok(InitializeResult {
+//!             capabilities: ServerCapabilities {
+//!                 completion_provider: Some(CompletionOptions {
+//!                     trigger_characters: Some(vec![".".into()]),
+//!                     ..Default::default()
+//!                 }),
+//!                 ..capabilities::baseline(self)
+//!             },
+//!             server_info: None,
+//!         })
+//!     }
+//!
+//!     async fn shutdown(&self) -> Result<()> {
+//!         Ok(())
+//!     }
+//!
+//!     async fn completion(&self, _: CompletionParams) -> Result<Option<CompletionResponse>> {
+//!         Ok(None)
+//!     }
+//!
+//!     async fn hover(&self, _: HoverParams) -> Result<Option<Hover>> {
+//!         Ok(None)
+//!     }
+//! }
+//! ```
+//!
+//! In debug builds, the capabilities returned by [`LanguageServer::initialize`] are also checked
+//! against the implemented handlers, logging a warning for every capability which is advertised
+//! without its handler being implemented, or the other way around.
+
+use crate::LanguageServer;
+use serde_json::{json, Map, Value};
+
+/// Returns the baseline capabilities of a server, enabling the features whose handlers it
+/// implements.
+///
+/// Features which can't be enabled without further options (i.e., semantic tokens, formatting on
+/// type and executing commands) are left disabled, and documents are synchronized in full if
+/// [`LanguageServer::did_change`] is implemented. If the implemented handlers are unknown, because
+/// the implementation has not been annotated with [`rpc`](crate::rpc), no feature is enabled.
+pub fn baseline<S: LanguageServer + ?Sized>(server: &S) -> lsp::ServerCapabilities {
+    let handlers = match server.implemented_handlers() {
+        Some(handlers) => handlers,
+        None => {
+            log::warn!("implemented handlers are unknown, annotate the `LanguageServer` implementation with `#[rpc]`");
+            return lsp::ServerCapabilities::default();
+        },
+    };
+
+    let mut capabilities = Value::Object(Map::new());
+    for (path, baseline) in methods(handlers).filter_map(|(_, method)| capability(method)) {
+        if !baseline.is_null() {
+            enable(&mut capabilities, path, baseline);
+        }
+    }
+
+    serde_json::from_value(capabilities).unwrap_or_else(|e| {
+        log::error!("failed to infer server capabilities: {}", e);
+        lsp::ServerCapabilities::default()
+    })
+}
+
+/// Logs a warning for every capability which disagrees with the handlers implemented by the
+/// server, in debug builds only.
+pub(crate) fn check<S: LanguageServer + ?Sized>(server: &S, capabilities: &lsp::ServerCapabilities) {
+    if cfg!(debug_assertions) {
+        if let Some(handlers) = server.implemented_handlers() {
+            mismatches(handlers, capabilities)
+                .iter()
+                .for_each(|mismatch| log::warn!("{}", mismatch));
+        }
+    }
+}
+
+/// Describes every capability which disagrees with the given implemented handlers.
+fn mismatches(handlers: &[&str], capabilities: &lsp::ServerCapabilities) -> Vec<String> {
+    let mut capabilities = serde_json::to_value(capabilities).unwrap_or(Value::Null);
+    if let Some(sync) = capabilities.get_mut("textDocumentSync") {
+        if let Some(kind) = sync.as_u64() {
+            *sync = json!({ "openClose": kind > 0, "change": kind, "save": kind > 0 });
+        }
+    }
+
+    let mut mismatches = Vec::new();
+    for &(handler, method) in crate::generated_impl::HANDLERS {
+        let path = match capability(method) {
+            Some((path, _)) => path.join("."),
+            None => continue,
+        };
+        match (handlers.contains(&handler), advertised(&capabilities, method)) {
+            (true, false) => mismatches.push(format!(
+                "`LanguageServer::{}` is implemented, but `{}` is not advertised",
+                handler, path
+            )),
+            (false, true) => mismatches.push(format!(
+                "`{}` is advertised, but `LanguageServer::{}` is not implemented",
+                path, handler
+            )),
+            _ => {},
+        }
+    }
+    mismatches
+}
+
+/// Returns the methods of the given handlers.
+fn methods<'a>(handlers: &'a [&str]) -> impl Iterator<Item = (&'static str, &'static str)> + 'a {
+    crate::generated_impl::HANDLERS
+        .iter()
+        .copied()
+        .filter(move |(handler, _)| handlers.contains(handler))
+}
+
+/// Returns whether the capability of the given method is advertised.
+fn advertised(capabilities: &Value, method: &str) -> bool {
+    let path = match capability(method) {
+        Some((path, _)) => path,
+        None => return false,
+    };
+    let value = path.iter().try_fold(capabilities, |value, key| value.get(key));
+    !matches!(value, None | Some(Value::Null) | Some(Value::Bool(false)))
+}
+
+/// Sets the value at the given path unless it is already set, replacing any value in between
+/// which is not an object (e.g., `true` when enabling `codeActionProvider.resolveProvider`).
+fn enable(capabilities: &mut Value, path: &[&str], baseline: Value) {
+    let (key, parents) = match path.split_last() {
+        Some(split) => split,
+        None => return,
+    };
+    let mut value = capabilities;
+    for parent in parents {
+        if !value.is_object() {
+            *value = Value::Object(Map::new());
+        }
+        value = &mut value[*parent];
+    }
+    if !value.is_object() {
+        *value = Value::Object(Map::new());
+    }
+    if let Value::Object(map) = value {
+        map.entry(*key).or_insert(baseline);
+    }
+}
+
+/// Returns the path of the capability advertising support for the given method, along with the
+/// value it is set to in the baseline, or `null` if it can't be inferred.
+fn capability(method: &str) -> Option<(&'static [&'static str], Value)> {
+    let capability: (&'static [&'static str], Value) = match method {
+        "workspace/didChangeWorkspaceFolders" => (
+            &["workspace", "workspaceFolders"],
+            json!({ "supported": true, "changeNotifications": true }),
+        ),
+        "workspace/symbol" => (&["workspaceSymbolProvider"], json!(true)),
+        "workspace/executeCommand" => (&["executeCommandProvider"], Value::Null),
+        "textDocument/didOpen" | "textDocument/didClose" => (&["textDocumentSync", "openClose"], json!(true)),
+        "textDocument/didChange" => (&["textDocumentSync", "change"], json!(lsp::TextDocumentSyncKind::Full)),
+        "textDocument/willSave" => (&["textDocumentSync", "willSave"], json!(true)),
+        "textDocument/willSaveWaitUntil" => (&["textDocumentSync", "willSaveWaitUntil"], json!(true)),
+        "textDocument/didSave" => (&["textDocumentSync", "save"], json!(true)),
+        "textDocument/completion" => (&["completionProvider"], json!({})),
+        "completionItem/resolve" => (&["completionProvider", "resolveProvider"], json!(true)),
+        "textDocument/hover" => (&["hoverProvider"], json!(true)),
+        "textDocument/signatureHelp" => (&["signatureHelpProvider"], json!({})),
+        "textDocument/declaration" => (&["declarationProvider"], json!(true)),
+        "textDocument/definition" => (&["definitionProvider"], json!(true)),
+        "textDocument/typeDefinition" => (&["typeDefinitionProvider"], json!(true)),
+        "textDocument/implementation" => (&["implementationProvider"], json!(true)),
+        "textDocument/references" => (&["referencesProvider"], json!(true)),
+        "textDocument/documentHighlight" => (&["documentHighlightProvider"], json!(true)),
+        "textDocument/documentSymbol" => (&["documentSymbolProvider"], json!(true)),
+        "textDocument/codeAction" => (&["codeActionProvider"], json!(true)),
+        "codeAction/resolve" => (&["codeActionProvider", "resolveProvider"], json!(true)),
+        "textDocument/codeLens" => (&["codeLensProvider"], json!({})),
+        "codeLens/resolve" => (&["codeLensProvider", "resolveProvider"], json!(true)),
+        "textDocument/documentLink" => (&["documentLinkProvider"], json!({})),
+        "documentLink/resolve" => (&["documentLinkProvider", "resolveProvider"], json!(true)),
+        "textDocument/documentColor" | "textDocument/colorPresentation" => (&["colorProvider"], json!(true)),
+        "textDocument/formatting" => (&["documentFormattingProvider"], json!(true)),
+        "textDocument/rangeFormatting" => (&["documentRangeFormattingProvider"], json!(true)),
+        "textDocument/onTypeFormatting" => (&["documentOnTypeFormattingProvider"], Value::Null),
+        "textDocument/rename" => (&["renameProvider"], json!(true)),
+        "textDocument/prepareRename" => (&["renameProvider", "prepareProvider"], json!(true)),
+        "textDocument/foldingRange" => (&["foldingRangeProvider"], json!(true)),
+        "textDocument/selectionRange" => (&["selectionRangeProvider"], json!(true)),
+        "textDocument/prepareCallHierarchy" | "callHierarchy/incomingCalls" | "callHierarchy/outgoingCalls" => {
+            (&["callHierarchyProvider"], json!(true))
+        },
+        "textDocument/semanticTokens/full" => (&["semanticTokensProvider", "full"], Value::Null),
+        "textDocument/semanticTokens/full/delta" => (&["semanticTokensProvider", "full", "delta"], Value::Null),
+        "textDocument/semanticTokens/range" => (&["semanticTokensProvider", "range"], Value::Null),
+        "textDocument/linkedEditingRange" => (&["linkedEditingRangeProvider"], json!(true)),
+        "textDocument/moniker" => (&["monikerProvider"], json!(true)),
+        // `workspaceSymbolProvider.resolveProvider` can't be expressed with `lsp-types` yet.
+        _ => return None,
+    };
+    Some(capability)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jsonrpc::Result;
+    use async_trait::async_trait;
+
+    #[derive(Debug)]
+    struct Mock;
+
+    #[crate::rpc]
+    #[async_trait]
+    impl LanguageServer for Mock {
+        async fn initialize(&self, _: lsp::InitializeParams) -> Result<lsp::InitializeResult> {
+            Ok(lsp::InitializeResult::default())
+        }
+
+        async fn shutdown(&self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn did_open(&self, _: lsp::DidOpenTextDocumentParams) {
+        }
+
+        async fn did_change(&self, _: lsp::DidChangeTextDocumentParams) {
+        }
+
+        async fn code_action_resolve(&self, params: lsp::CodeAction) -> Result<lsp::CodeAction> {
+            Ok(params)
+        }
+
+        async fn code_action(&self, _: lsp::CodeActionParams) -> Result<Option<lsp::CodeActionResponse>> {
+            Ok(None)
+        }
+
+        async fn semantic_tokens_full(
+            &self,
+            _: lsp::SemanticTokensParams,
+        ) -> Result<Option<lsp::SemanticTokensResult>> {
+            Ok(None)
+        }
+    }
+
+    #[derive(Debug)]
+    struct Unknown;
+
+    #[async_trait]
+    impl LanguageServer for Unknown {
+        async fn initialize(&self, _: lsp::InitializeParams) -> Result<lsp::InitializeResult> {
+            Ok(lsp::InitializeResult::default())
+        }
+
+        async fn shutdown(&self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn implemented_handlers() {
+        let handlers = [
+            "initialize",
+            "shutdown",
+            "did_open",
+            "did_change",
+            "code_action_resolve",
+        ];
+        assert_eq!(&Mock.implemented_handlers().unwrap()[.. 5], &handlers[..]);
+        assert_eq!(Unknown.implemented_handlers(), None);
+    }
+
+    #[test]
+    fn baseline() {
+        let capabilities = super::baseline(&Mock);
+        let value = serde_json::to_value(&capabilities).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "textDocumentSync": { "openClose": true, "change": 1 },
+                "codeActionProvider": { "resolveProvider": true },
+            })
+        );
+
+        assert_eq!(super::baseline(&Unknown), lsp::ServerCapabilities::default());
+    }
+
+    #[test]
+    fn mismatches() {
+        let handlers = Mock.implemented_handlers().unwrap();
+        let capabilities = lsp::ServerCapabilities {
+            text_document_sync: Some(lsp::TextDocumentSyncKind::Incremental.into()),
+            hover_provider: Some(true.into()),
+            ..super::baseline(&Mock)
+        };
+
+        assert_eq!(super::mismatches(handlers, &capabilities), vec![
+            "`textDocumentSync.save` is advertised, but `LanguageServer::did_save` is not implemented",
+            "`textDocumentSync.openClose` is advertised, but `LanguageServer::did_close` is not implemented",
+            "`hoverProvider` is advertised, but `LanguageServer::hover` is not implemented",
+            "`LanguageServer::semantic_tokens_full` is implemented, but `semanticTokensProvider.full` is not advertised",
+        ]);
+    }
+}
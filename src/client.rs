@@ -1,23 +1,36 @@
 //! Types for sending data to and from the language client.
 
+mod capabilities;
+mod diagnostics;
+mod registration;
+mod request_id;
+mod settings;
+
+pub(crate) use self::request_id::{cancel_params, RequestIds};
+pub use self::{diagnostics::DiagnosticsPublisher, registration::RegistrationGuard, request_id::RequestIdStrategy};
+
 use futures::{
-    channel::{mpsc, oneshot},
+    channel::oneshot,
     future::{self, Shared},
     select,
-    sink::SinkExt,
     FutureExt,
 };
 use std::{
-    convert::TryFrom,
+    any::{Any, TypeId},
+    collections::HashMap,
     fmt::{self, Debug, Formatter},
     future::Future,
     pin::Pin,
     sync::{
         atomic::{AtomicBool, AtomicU64, Ordering},
         Arc,
+        PoisonError,
+        RwLock,
     },
+    time::Duration,
 };
 
+type AnyArc = Arc<dyn Any + Send + Sync>;
 type TokenFuture = Shared<Pin<Box<dyn Future<Output = Result<(), oneshot::Canceled>> + Send>>>;
 
 /// A structure used to construct and cancel [`CancellationToken`].
@@ -102,10 +115,16 @@ impl Default for CancellationToken {
 }
 
 struct ClientInner {
-    sender: mpsc::Sender<crate::jsonrpc::Outgoing>,
-    request_id: AtomicU64,
+    sender: crate::queue::Sender,
+    request_ids: RequestIds,
+    registration_id: AtomicU64,
     pending_requests: Arc<crate::jsonrpc::ClientRequests>,
     state: Arc<crate::server::State>,
+    initialize_params: RwLock<Option<Arc<lsp::InitializeParams>>>,
+    settings: settings::SettingsCache,
+    request_timeout: RwLock<Option<Duration>>,
+    shared_state: RwLock<Option<AnyArc>>,
+    data: RwLock<HashMap<TypeId, AnyArc>>,
 }
 
 /// Handle for communicating with the language client.
@@ -117,24 +136,175 @@ struct ClientInner {
 #[derive(Clone)]
 pub struct Client {
     inner: Arc<ClientInner>,
+    request_timeout: Option<Option<Duration>>,
 }
 
 impl Client {
     pub(super) fn new(
-        sender: mpsc::Sender<crate::jsonrpc::Outgoing>,
+        sender: crate::queue::Sender,
         pending_requests: Arc<crate::jsonrpc::ClientRequests>,
         state: Arc<crate::server::State>,
     ) -> Self {
         Client {
             inner: Arc::new(ClientInner {
                 sender,
-                request_id: AtomicU64::new(0),
+                request_ids: RequestIds::new(),
+                registration_id: AtomicU64::new(0),
                 pending_requests,
                 state,
+                initialize_params: RwLock::new(None),
+                settings: settings::SettingsCache::default(),
+                request_timeout: RwLock::new(None),
+                shared_state: RwLock::new(None),
+                data: RwLock::new(HashMap::new()),
             }),
+            request_timeout: None,
+        }
+    }
+
+    pub(crate) fn set_request_timeout(&self, timeout: Option<Duration>) {
+        let mut request_timeout = self
+            .inner
+            .request_timeout
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        *request_timeout = timeout;
+    }
+
+    pub(crate) fn set_request_id_strategy(&self, strategy: RequestIdStrategy) {
+        self.inner.request_ids.set_strategy(strategy);
+    }
+
+    /// Returns a handle whose requests to the client time out after the given duration.
+    ///
+    /// This overrides the default timeout set with [`LspService::client_request_timeout`] for the
+    /// requests sent through the returned handle, e.g.
+    /// `client.with_timeout(Duration::from_secs(60)).apply_edit(edit, None)`. Once a request
+    /// times out, it is cancelled with a [`$/cancelRequest`] notification and returns `Err` with
+    /// [`Error::request_timed_out`].
+    ///
+    /// [`LspService::client_request_timeout`]: crate::LspService::client_request_timeout
+    /// [`$/cancelRequest`]: https://microsoft.github.io/language-server-protocol/specification#cancelRequest
+    /// [`Error::request_timed_out`]: crate::jsonrpc::Error::request_timed_out
+    pub fn with_timeout(&self, timeout: Duration) -> Client {
+        Client {
+            inner: self.inner.clone(),
+            request_timeout: Some(Some(timeout)),
+        }
+    }
+
+    /// Returns a handle whose requests to the client never time out.
+    ///
+    /// This overrides the default timeout set with [`LspService::client_request_timeout`] for the
+    /// requests sent through the returned handle.
+    ///
+    /// [`LspService::client_request_timeout`]: crate::LspService::client_request_timeout
+    pub fn without_timeout(&self) -> Client {
+        Client {
+            inner: self.inner.clone(),
+            request_timeout: Some(None),
         }
     }
 
+    fn request_timeout(&self) -> Option<Duration> {
+        self.request_timeout.unwrap_or_else(|| {
+            *self
+                .inner
+                .request_timeout
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+        })
+    }
+
+    pub(crate) fn set_initialize_params(&self, params: lsp::InitializeParams) {
+        let mut initialize_params = self
+            .inner
+            .initialize_params
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        *initialize_params = Some(Arc::new(params));
+    }
+
+    pub(crate) fn set_shared_state(&self, state: AnyArc) {
+        let mut shared_state = self.inner.shared_state.write().unwrap_or_else(PoisonError::into_inner);
+        *shared_state = Some(state);
+    }
+
+    /// Returns the state shared by all connections, which was passed to [`LspService::with_state`].
+    ///
+    /// Returns `None` if the service was created without shared state, or if it is not an `S`.
+    ///
+    /// [`LspService::with_state`]: crate::LspService::with_state
+    pub fn state<S: Send + Sync + 'static>(&self) -> Option<Arc<S>> {
+        let shared_state = self.inner.shared_state.read().unwrap_or_else(PoisonError::into_inner);
+        shared_state.clone()?.downcast().ok()
+    }
+
+    /// Stores a value of type `T` for the lifetime of this connection, returning the previous one.
+    ///
+    /// Values are keyed by type, and are visible through every clone of this `Client`, but not to
+    /// other connections. Use [`LspService::with_state`] for data shared between connections.
+    ///
+    /// [`LspService::with_state`]: crate::LspService::with_state
+    pub fn insert_data<T: Send + Sync + 'static>(&self, value: T) -> Option<Arc<T>> {
+        let mut data = self.inner.data.write().unwrap_or_else(PoisonError::into_inner);
+        let previous = data.insert(TypeId::of::<T>(), Arc::new(value))?;
+        previous.downcast().ok()
+    }
+
+    /// Returns the value of type `T` stored with [`Client::insert_data`], if any.
+    pub fn data<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        let data = self.inner.data.read().unwrap_or_else(PoisonError::into_inner);
+        data.get(&TypeId::of::<T>())?.clone().downcast().ok()
+    }
+
+    /// Returns the parameters the client sent with the [`initialize`] request.
+    ///
+    /// Returns `None` if the server has not received an `initialize` request yet. The parameters
+    /// are already available from within [`LanguageServer::initialize`].
+    ///
+    /// [`initialize`]: https://microsoft.github.io/language-server-protocol/specification#initialize
+    /// [`LanguageServer::initialize`]: crate::LanguageServer::initialize
+    pub fn initialize_params(&self) -> Option<Arc<lsp::InitializeParams>> {
+        let initialize_params = self
+            .inner
+            .initialize_params
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        initialize_params.clone()
+    }
+
+    /// Returns the capabilities advertised by the client in the [`initialize`] request.
+    ///
+    /// [`initialize`]: https://microsoft.github.io/language-server-protocol/specification#initialize
+    pub fn capabilities(&self) -> Option<lsp::ClientCapabilities> {
+        self.initialize_params().map(|params| params.capabilities.clone())
+    }
+
+    /// Returns the name and version of the client sent in the [`initialize`] request, if any.
+    ///
+    /// [`initialize`]: https://microsoft.github.io/language-server-protocol/specification#initialize
+    pub fn client_info(&self) -> Option<lsp::ClientInfo> {
+        self.initialize_params().and_then(|params| params.client_info.clone())
+    }
+
+    /// Returns the root URI of the workspace sent in the [`initialize`] request, if any.
+    ///
+    /// [`initialize`]: https://microsoft.github.io/language-server-protocol/specification#initialize
+    pub fn root_uri(&self) -> Option<lsp::Url> {
+        self.initialize_params().and_then(|params| params.root_uri.clone())
+    }
+
+    /// Returns the workspace folders sent in the [`initialize`] request, if any.
+    ///
+    /// Use [`Client::workspace_folders`] to fetch the current list of workspace folders instead.
+    ///
+    /// [`initialize`]: https://microsoft.github.io/language-server-protocol/specification#initialize
+    pub fn initial_workspace_folders(&self) -> Option<Vec<lsp::WorkspaceFolder>> {
+        self.initialize_params()
+            .and_then(|params| params.workspace_folders.clone())
+    }
+
     /// Notifies the client to log a particular message.
     ///
     /// This corresponds to the [`window/logMessage`] notification.
@@ -207,13 +377,113 @@ impl Client {
     /// immediately return `Err` with JSON-RPC error code `-32002` ([read more]).
     ///
     /// [read more]: https://microsoft.github.io/language-server-protocol/specification#initialize
+    ///
+    /// # Capabilities
+    ///
+    /// If the client did not advertise support for dynamically registering one of the given
+    /// methods, this will immediately return `Err` with JSON-RPC error code `-32803`.
     #[rustfmt::skip]
     pub async fn register_capability(&self, registrations: Vec<lsp::Registration>) -> crate::jsonrpc::Result<()> {
+        if let Some(initialize_params) = self.initialize_params() {
+            let capabilities = &initialize_params.capabilities;
+            let unsupported = registrations
+                .iter()
+                .find(|r| capabilities::dynamic_registration(capabilities, &r.method) == Some(false));
+            if let Some(registration) = unsupported {
+                let method = format!("dynamic registration of {}", registration.method);
+                return Err(crate::jsonrpc::not_supported_error(&method));
+            }
+        }
+
         let token = CancellationToken::default();
         let params = lsp::RegistrationParams { registrations };
         self.send_request_initialized::<lsp::request::RegisterCapability>(params, token).await
     }
 
+    /// Registers the given request with the client, returning a guard which unregisters it again
+    /// once dropped.
+    ///
+    /// The registration is assigned a unique ID and `options` are sent as its registration
+    /// options, e.g. [`TextDocumentRegistrationOptions`] to limit it to certain documents.
+    ///
+    /// [`TextDocumentRegistrationOptions`]: lsp::TextDocumentRegistrationOptions
+    ///
+    /// This corresponds to the [`client/registerCapability`] request.
+    ///
+    /// [`client/registerCapability`]: https://microsoft.github.io/language-server-protocol/specification#client_registerCapability
+    ///
+    /// # Errors
+    ///
+    /// Fails just like [`Client::register_capability`], or with JSON-RPC error code `-32602` if
+    /// the options can't be serialized.
+    pub async fn register<R, O>(&self, options: O) -> crate::jsonrpc::Result<RegistrationGuard>
+    where
+        R: lsp::request::Request,
+        O: serde::Serialize,
+    {
+        self.register_method(R::METHOD, options).await
+    }
+
+    /// Registers the given notification with the client, returning a guard which unregisters it
+    /// again once dropped.
+    ///
+    /// See [`Client::register`] for details.
+    pub async fn register_notification<N, O>(&self, options: O) -> crate::jsonrpc::Result<RegistrationGuard>
+    where
+        N: lsp::notification::Notification,
+        O: serde::Serialize,
+    {
+        self.register_method(N::METHOD, options).await
+    }
+
+    /// Asks the client to watch files matching the given glob patterns (e.g., `**/*.lkml`) and to
+    /// report changes to them through [`workspace/didChangeWatchedFiles`].
+    ///
+    /// Changes are delivered to [`LanguageServer::did_change_watched_files`] until the returned
+    /// guard is dropped.
+    ///
+    /// [`workspace/didChangeWatchedFiles`]: https://microsoft.github.io/language-server-protocol/specification#workspace_didChangeWatchedFiles
+    /// [`LanguageServer::did_change_watched_files`]: crate::LanguageServer::did_change_watched_files
+    pub async fn watch_files<I>(&self, globs: I) -> crate::jsonrpc::Result<RegistrationGuard>
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        let watchers = globs
+            .into_iter()
+            .map(|glob| lsp::FileSystemWatcher {
+                glob_pattern: glob.into(),
+                kind: None,
+            })
+            .collect();
+        let options = lsp::DidChangeWatchedFilesRegistrationOptions { watchers };
+        self.register_notification::<lsp::notification::DidChangeWatchedFiles, _>(options)
+            .await
+    }
+
+    async fn register_method<O>(&self, method: &str, options: O) -> crate::jsonrpc::Result<RegistrationGuard>
+    where
+        O: serde::Serialize,
+    {
+        let register_options = match serde_json::to_value(options) {
+            Ok(serde_json::Value::Null) => None,
+            Ok(value) => Some(value),
+            Err(e) => return Err(crate::jsonrpc::Error::invalid_params(e.to_string())),
+        };
+
+        let id = self.inner.registration_id.fetch_add(1, Ordering::Relaxed);
+        let id = format!("lspower-{}-{}", method, id);
+        let method = method.to_string();
+        let registration = lsp::Registration {
+            id: id.clone(),
+            method: method.clone(),
+            register_options,
+        };
+        self.register_capability(vec![registration]).await?;
+
+        Ok(RegistrationGuard::new(self.clone(), id, method))
+    }
+
     /// Unregisters a capability with the client.
     ///
     /// This corresponds to the [`client/unregisterCapability`] request.
@@ -255,10 +525,16 @@ impl Client {
     /// # Compatibility
     ///
     /// This request was introduced in specification version 3.6.0.
+    ///
+    /// # Capabilities
+    ///
+    /// If the client did not advertise support for this request, this will immediately return
+    /// `Err` with JSON-RPC error code `-32803`.
     #[rustfmt::skip]
     pub async fn workspace_folders(&self) -> crate::jsonrpc::Result<Option<Vec<lsp::WorkspaceFolder>>> {
         let token = CancellationToken::default();
-        self.send_request_initialized::<lsp::request::WorkspaceFoldersRequest>((), token).await
+        let supported = |c: &lsp::ClientCapabilities| c.workspace.as_ref()?.workspace_folders;
+        self.send_request_supported::<lsp::request::WorkspaceFoldersRequest>((), token, supported).await
     }
 
     /// Fetches configuration settings from the client.
@@ -284,6 +560,11 @@ impl Client {
     /// # Compatibility
     ///
     /// This request was introduced in specification version 3.6.0.
+    ///
+    /// # Capabilities
+    ///
+    /// If the client did not advertise support for this request, this will immediately return
+    /// `Err` with JSON-RPC error code `-32803`.
     #[rustfmt::skip]
     pub async fn configuration(
         &self,
@@ -291,7 +572,77 @@ impl Client {
     ) -> crate::jsonrpc::Result<Vec<serde_json::Value>> {
         let token = CancellationToken::default();
         let params = lsp::ConfigurationParams { items };
-        self.send_request_initialized::<lsp::request::WorkspaceConfiguration>(params, token).await
+        let supported = |c: &lsp::ClientCapabilities| c.workspace.as_ref()?.configuration;
+        self.send_request_supported::<lsp::request::WorkspaceConfiguration>(params, token, supported).await
+    }
+
+    /// Returns the configuration section with the given name, deserialized into `T`.
+    ///
+    /// This is a shorthand for [`Client::scoped_settings`] without a scope URI.
+    pub async fn settings<T>(&self, section: &str) -> crate::jsonrpc::Result<T>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        self.scoped_settings(None, section).await
+    }
+
+    /// Returns the configuration section with the given name for a resource, deserialized into `T`.
+    ///
+    /// If the client supports the [`workspace/configuration`] request, the section is fetched from
+    /// the client. Otherwise, it is looked up in the settings the client last sent with a
+    /// [`workspace/didChangeConfiguration`] notification, where dots in `section` separate nested
+    /// keys. A missing section is deserialized from `null`, so use `Option<T>` or a type which
+    /// accepts `null` if the section may be absent.
+    ///
+    /// Sections are cached per scope URI until the next `workspace/didChangeConfiguration`
+    /// notification, so this can be called whenever the settings are needed.
+    ///
+    /// [`workspace/configuration`]: https://microsoft.github.io/language-server-protocol/specification#workspace_configuration
+    /// [`workspace/didChangeConfiguration`]: https://microsoft.github.io/language-server-protocol/specification#workspace_didChangeConfiguration
+    ///
+    /// # Initialization
+    ///
+    /// If the settings are fetched from the client before the server has been initialized, this
+    /// will immediately return `Err` with JSON-RPC error code `-32002` ([read more]).
+    ///
+    /// [read more]: https://microsoft.github.io/language-server-protocol/specification#initialize
+    pub async fn scoped_settings<T>(&self, scope_uri: Option<lsp::Url>, section: &str) -> crate::jsonrpc::Result<T>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let cache = &self.inner.settings;
+        let value = match cache.get(&scope_uri, section) {
+            Some(value) => value,
+            None => {
+                let generation = cache.generation();
+                let pull = self
+                    .capabilities()
+                    .and_then(|c| c.workspace?.configuration)
+                    .unwrap_or(false);
+                let value = if pull {
+                    let item = lsp::ConfigurationItem {
+                        scope_uri: scope_uri.clone(),
+                        section: Some(section.into()),
+                    };
+                    self.configuration(vec![item]).await?.pop().unwrap_or_default()
+                } else {
+                    cache.pushed(section)
+                };
+                cache.insert(generation, scope_uri, section, value.clone());
+                value
+            },
+        };
+
+        serde_json::from_value(value).map_err(|e| crate::jsonrpc::Error {
+            code: crate::jsonrpc::ErrorCode::ParseError,
+            message: e.to_string(),
+            data: None,
+        })
+    }
+
+    /// Invalidates cached settings upon a `workspace/didChangeConfiguration` notification.
+    pub(crate) fn did_change_configuration(&self, settings: serde_json::Value) {
+        self.inner.settings.invalidate(settings);
     }
 
     /// Requests a workspace resource be edited on the client side and returns whether the edit was
@@ -307,6 +658,11 @@ impl Client {
     /// immediately return `Err` with JSON-RPC error code `-32002` ([read more]).
     ///
     /// [read more]: https://microsoft.github.io/language-server-protocol/specification#initialize
+    ///
+    /// # Capabilities
+    ///
+    /// If the client did not advertise support for this request, this will immediately return
+    /// `Err` with JSON-RPC error code `-32803`.
     #[rustfmt::skip]
     pub async fn apply_edit(
         &self,
@@ -315,7 +671,8 @@ impl Client {
     ) -> crate::jsonrpc::Result<lsp::ApplyWorkspaceEditResponse> {
         let token = CancellationToken::default();
         let params = lsp::ApplyWorkspaceEditParams { label, edit };
-        self.send_request_initialized::<lsp::request::ApplyWorkspaceEdit>(params, token).await
+        let supported = |c: &lsp::ClientCapabilities| c.workspace.as_ref()?.apply_edit;
+        self.send_request_supported::<lsp::request::ApplyWorkspaceEdit>(params, token, supported).await
     }
 
     /// Submits validation diagnostics for an open file with the given URI.
@@ -349,10 +706,9 @@ impl Client {
     where
         N: lsp::notification::Notification,
     {
-        let mut sender = self.inner.sender.clone();
         let message = crate::jsonrpc::Outgoing::Request(crate::jsonrpc::ClientRequest::notification::<N>(params));
-        if sender.send(message).await.is_err() {
-            log::error!("failed to send notification")
+        if let Err(e) = self.inner.sender.send(message).await {
+            log::error!("failed to send notification: {}", e)
         }
     }
 
@@ -388,28 +744,37 @@ impl Client {
     where
         R: lsp::request::Request,
     {
-        let id = self.inner.request_id.fetch_add(1, Ordering::Relaxed);
-        let message = crate::jsonrpc::Outgoing::Request(crate::jsonrpc::ClientRequest::request::<R>(id, params));
-
-        let response_waiter = self.inner.pending_requests.wait(crate::jsonrpc::Id::Number(id));
-
-        if self.inner.sender.clone().send(message).await.is_err() {
-            log::error!("failed to send request");
-            return Err(crate::jsonrpc::Error::internal_error());
+        let id = self.inner.request_ids.next();
+        let message =
+            crate::jsonrpc::Outgoing::Request(crate::jsonrpc::ClientRequest::request::<R>(id.clone(), params));
+
+        let response_waiter = self.inner.pending_requests.wait(id.clone())?;
+
+        if let Err(e) = self.inner.sender.send(message).await {
+            log::error!("failed to send request: {}", e);
+            self.inner.pending_requests.0.remove(&id);
+            return Err(crate::jsonrpc::Error {
+                code: crate::jsonrpc::ErrorCode::InternalError,
+                message: e.to_string(),
+                data: None,
+            });
         }
 
+        let timeout = match self.request_timeout() {
+            Some(timeout) => futures_timer::Delay::new(timeout).left_future(),
+            None => future::pending().right_future(),
+        };
+
         select! {
             _ = token.wait() => {
-                if self.inner.pending_requests.0.remove(&crate::jsonrpc::Id::Number(id)).is_none() {
-                    log::warn!("received response with unknown request ID: {}", id);
-                }
-                let params = {
-                    let id = i32::try_from(id).expect("error converting u64 to i32");
-                    lsp::CancelParams { id: lsp::NumberOrString::Number(id) }
-                };
-                self.send_notification::<lsp::notification::Cancel>(params).await;
+                self.cancel_request(&id).await;
                 Err(crate::jsonrpc::Error::request_cancelled())
             },
+            _ = timeout.fuse() => {
+                log::warn!("request {} to the client timed out, cancelling it", id);
+                self.cancel_request(&id).await;
+                Err(crate::jsonrpc::Error::request_timed_out())
+            },
             response = response_waiter.fuse() => {
                 let (_, result) = response.into_parts();
                 result.and_then(|v| {
@@ -423,6 +788,40 @@ impl Client {
         }
     }
 
+    /// Stops waiting for the response to a request and notifies the client to cancel it.
+    async fn cancel_request(&self, id: &crate::jsonrpc::Id) {
+        if self.inner.pending_requests.0.remove(id).is_none() {
+            log::warn!("received response with unknown request ID: {}", id);
+        }
+        if let Some(params) = cancel_params(id) {
+            self.send_notification::<lsp::notification::Cancel>(params).await;
+        }
+    }
+
+    /// Sends a request to the client without waiting for the response.
+    ///
+    /// This is meant for contexts which can't `.await` (e.g., `Drop` implementations), so the
+    /// request is dropped with a warning if it can't be queued right away.
+    fn send_request_detached<R>(&self, params: R::Params)
+    where
+        R: lsp::request::Request,
+    {
+        if let crate::server::StateKind::Initialized | crate::server::StateKind::ShutDown = self.inner.state.get() {
+            let id = self.inner.request_ids.next();
+            let message =
+                crate::jsonrpc::Outgoing::Request(crate::jsonrpc::ClientRequest::request::<R>(id.clone(), params));
+            // Mark the request as pending, so that the response is silently discarded on arrival.
+            if let Err(e) = self.inner.pending_requests.wait(id.clone()) {
+                log::warn!("failed to send request: {}", e);
+                return;
+            }
+            if let Err(e) = self.inner.sender.push(message) {
+                self.inner.pending_requests.0.remove(&id);
+                log::warn!("failed to send request: {}", e);
+            }
+        }
+    }
+
     async fn send_request_initialized<R>(
         &self,
         params: R::Params,
@@ -434,18 +833,37 @@ impl Client {
         if let crate::server::StateKind::Initialized | crate::server::StateKind::ShutDown = self.inner.state.get() {
             self.send_request::<R>(params, token).await
         } else {
-            let id = self.inner.request_id.load(Ordering::SeqCst) + 1;
+            let id = self.inner.request_ids.peek();
             let msg = crate::jsonrpc::ClientRequest::request::<R>(id, params);
             log::trace!("server not initialized, supressing message: {}", msg);
             Err(crate::jsonrpc::not_initialized_error())
         }
     }
+
+    async fn send_request_supported<R>(
+        &self,
+        params: R::Params,
+        token: CancellationToken,
+        supported: impl FnOnce(&lsp::ClientCapabilities) -> Option<bool>,
+    ) -> crate::jsonrpc::Result<R::Result>
+    where
+        R: lsp::request::Request,
+    {
+        if let Some(initialize_params) = self.initialize_params() {
+            if !supported(&initialize_params.capabilities).unwrap_or(false) {
+                log::error!("client does not support {}, not sending request", R::METHOD);
+                return Err(crate::jsonrpc::not_supported_error(R::METHOD));
+            }
+        }
+        self.send_request_initialized::<R>(params, token).await
+    }
 }
 
 impl Debug for Client {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         f.debug_struct(stringify!(Client))
-            .field("request_id", &self.inner.request_id)
+            .field("request_id", &self.inner.request_ids)
+            .field("request_timeout", &self.request_timeout())
             .field("pending_requests", &self.inner.pending_requests)
             .field("state", &self.inner.state)
             .finish()
@@ -477,19 +895,40 @@ mod tests {
 
         mod helper {
             use super::*;
-            use crate::jsonrpc::Outgoing;
-            use futures::channel::mpsc;
+            use crate::queue::Receiver;
+            use serde_json::json;
 
-            pub(super) fn client(initialize: bool) -> (Client, mpsc::Receiver<Outgoing>) {
+            pub(super) fn client(initialize: bool) -> (Client, Receiver) {
                 let state = Arc::new(crate::server::State::new());
-                let (tx, rx) = mpsc::channel(4);
+                let (tx, rx) = crate::queue::channel(4, crate::OverflowPolicy::Block);
                 let pending_client = Arc::new(crate::jsonrpc::ClientRequests::new());
                 let client = crate::client::Client::new(tx, pending_client, state);
                 if initialize {
+                    let params = serde_json::from_value(json!({
+                        "capabilities": {
+                            "workspace": {
+                                "applyEdit": true,
+                                "configuration": true,
+                                "didChangeWatchedFiles": { "dynamicRegistration": true },
+                                "workspaceFolders": true,
+                            },
+                            "textDocument": {
+                                "hover": { "dynamicRegistration": true },
+                            },
+                        },
+                    }));
+                    client.set_initialize_params(params.unwrap());
                     client.inner.state.set(crate::server::StateKind::Initialized);
                 }
                 (client, rx)
             }
+
+            pub(super) fn unsupported_client() -> (Client, Receiver) {
+                let (client, rx) = self::client(true);
+                let params = serde_json::from_value(json!({ "capabilities": {} }));
+                client.set_initialize_params(params.unwrap());
+                (client, rx)
+            }
         }
 
         #[tokio::test]
@@ -536,6 +975,47 @@ mod tests {
             Ok(())
         }
 
+        #[test]
+        fn capabilities() {
+            let client = helper::client(false).0;
+            assert!(client.initialize_params().is_none());
+            assert!(client.capabilities().is_none());
+
+            let params = serde_json::from_value(json!({
+                "capabilities": { "workspace": { "configuration": true } },
+                "clientInfo": { "name": "test" },
+                "rootUri": "inmemory::///test",
+                "workspaceFolders": [{ "uri": "inmemory::///test", "name": "test" }],
+            }))
+            .unwrap();
+            client.set_initialize_params(params);
+
+            let capabilities = client.capabilities().unwrap();
+            assert_eq!(capabilities.workspace.unwrap().configuration, Some(true));
+            assert_eq!(client.client_info().unwrap().name, "test");
+            assert_eq!(client.root_uri(), Some(lsp::Url::parse("inmemory::///test").unwrap()));
+            assert_eq!(client.initial_workspace_folders().unwrap().len(), 1);
+        }
+
+        #[tokio::test]
+        async fn configuration_unsupported() {
+            let (client, _rx) = helper::unsupported_client();
+            let result = client.configuration(Default::default()).await;
+            let error = crate::jsonrpc::Error::request_failed("Client does not support workspace/configuration");
+            assert_eq!(result, Err(error));
+        }
+
+        #[test]
+        fn data() {
+            let (client, _rx) = helper::client(false);
+            assert_eq!(client.data::<u32>(), None);
+            assert_eq!(client.insert_data(1u32), None);
+            assert_eq!(client.clone().insert_data(2u32), Some(Arc::new(1)));
+            assert_eq!(client.data::<u32>(), Some(Arc::new(2)));
+            assert_eq!(client.data::<u64>(), None);
+            assert_eq!(client.state::<u32>(), None);
+        }
+
         #[test]
         fn display() {
             let client = helper::client(true).0;
@@ -600,6 +1080,21 @@ mod tests {
             Ok(())
         }
 
+        #[tokio::test]
+        async fn register_capability_unsupported() {
+            let (client, _rx) = helper::unsupported_client();
+            let registrations = vec![lsp::Registration {
+                id: "lookml".into(),
+                method: "workspace/didChangeWatchedFiles".into(),
+                register_options: None,
+            }];
+            let result = client.register_capability(registrations).await;
+            let error = crate::jsonrpc::Error::request_failed(
+                "Client does not support dynamic registration of workspace/didChangeWatchedFiles",
+            );
+            assert_eq!(result, Err(error));
+        }
+
         #[tokio::test]
         async fn send_notification_initialized_when_uninitialized() {
             let (client, _rx) = helper::client(false);
@@ -813,6 +1308,226 @@ mod tests {
             Ok(())
         }
 
+        #[tokio::test]
+        async fn watch_files() {
+            let (client, mut rx) = helper::client(true);
+
+            let req = client.watch_files(vec!["**/*.lkml"]);
+            let rsp = async {
+                let id = Id::Number(0);
+                client.inner.pending_requests.insert(Response::ok(id, json!(null)));
+            };
+            let (result, ()) = futures::future::join(req, rsp).await;
+            let guard = result.unwrap();
+            assert_eq!(guard.method(), "workspace/didChangeWatchedFiles");
+
+            let registration = lsp::Registration {
+                id: guard.id().into(),
+                method: guard.method().into(),
+                register_options: Some(json!({ "watchers": [{ "globPattern": "**/*.lkml" }] })),
+            };
+            let params = lsp::RegistrationParams {
+                registrations: vec![registration],
+            };
+            let request = ClientRequest::request::<lsp::request::RegisterCapability>(Id::Number(0), params);
+            assert_eq!(rx.next().await, Some(Outgoing::Request(request)));
+
+            let unregistration = lsp::Unregistration {
+                id: guard.id().into(),
+                method: guard.method().into(),
+            };
+            drop(guard);
+            let params = lsp::UnregistrationParams {
+                unregisterations: vec![unregistration],
+            };
+            let request = ClientRequest::request::<lsp::request::UnregisterCapability>(Id::Number(1), params);
+            assert_eq!(rx.next().await, Some(Outgoing::Request(request)));
+        }
+
+        #[tokio::test]
+        async fn register_forget() {
+            let (client, mut rx) = helper::client(true);
+
+            let req = client.register::<lsp::request::HoverRequest, _>(());
+            let rsp = async {
+                let id = Id::Number(0);
+                client.inner.pending_requests.insert(Response::ok(id, json!(null)));
+            };
+            let (result, ()) = futures::future::join(req, rsp).await;
+            let guard = result.unwrap();
+            assert_eq!(guard.method(), "textDocument/hover");
+            assert!(rx.next().await.is_some());
+
+            guard.forget();
+            assert!(rx.next().now_or_never().is_none());
+        }
+
+        #[tokio::test]
+        async fn request_id_wraps() {
+            let (client, mut rx) = helper::client(true);
+            client
+                .inner
+                .request_ids
+                .counter
+                .store(i32::MAX as u64, Ordering::Relaxed);
+
+            let mut canceller = TokenCanceller::new();
+            let req = client.send_custom_request::<lsp::request::WorkspaceFoldersRequest>((), canceller.token());
+            canceller.cancel();
+            assert_eq!(req.await, Err(crate::jsonrpc::Error::request_cancelled()));
+
+            let request =
+                ClientRequest::request::<lsp::request::WorkspaceFoldersRequest>(Id::Number(i32::MAX as u64), ());
+            assert_eq!(rx.next().await, Some(Outgoing::Request(request)));
+            let params = lsp::CancelParams {
+                id: lsp::NumberOrString::Number(i32::MAX),
+            };
+            let cancel = ClientRequest::notification::<lsp::notification::Cancel>(params);
+            assert_eq!(rx.next().await, Some(Outgoing::Request(cancel)));
+
+            assert_eq!(client.inner.request_ids.next(), Id::Number(0));
+            client
+                .inner
+                .request_ids
+                .counter
+                .store(u64::from(u32::MAX), Ordering::Relaxed);
+            assert!(matches!(client.inner.request_ids.next(), Id::Number(id) if id <= i32::MAX as u64));
+        }
+
+        #[tokio::test]
+        async fn request_id_strategy() {
+            let (client, mut rx) = helper::client(true);
+            client.set_request_id_strategy(RequestIdStrategy::Prefixed("lookml-".into()));
+
+            let mut canceller = TokenCanceller::new();
+            let req = client.send_custom_request::<lsp::request::WorkspaceFoldersRequest>((), canceller.token());
+            canceller.cancel();
+            assert_eq!(req.await, Err(crate::jsonrpc::Error::request_cancelled()));
+
+            let id = Id::String("lookml-0".into());
+            let request = ClientRequest::request::<lsp::request::WorkspaceFoldersRequest>(id, ());
+            assert_eq!(rx.next().await, Some(Outgoing::Request(request)));
+            let params = lsp::CancelParams {
+                id: lsp::NumberOrString::String("lookml-0".into()),
+            };
+            let cancel = ClientRequest::notification::<lsp::notification::Cancel>(params);
+            assert_eq!(rx.next().await, Some(Outgoing::Request(cancel)));
+
+            let req = client.workspace_folders();
+            let rsp = async {
+                let id = Id::String("lookml-1".into());
+                client.inner.pending_requests.insert(Response::ok(id, json!(null)));
+            };
+            let (result, ()) = futures::future::join(req, rsp).await;
+            assert_eq!(result, Ok(None));
+        }
+
+        #[tokio::test]
+        async fn request_id_pending() {
+            let (client, mut rx) = helper::client(true);
+            let _pending = client.inner.pending_requests.wait(Id::Number(0)).unwrap();
+
+            let result = client.workspace_folders().await;
+            assert_eq!(result.unwrap_err().code, crate::jsonrpc::ErrorCode::InternalError);
+            assert!(rx.next().now_or_never().is_none());
+
+            let req = client.workspace_folders();
+            let rsp = async {
+                let id = Id::Number(1);
+                client.inner.pending_requests.insert(Response::ok(id, json!(null)));
+            };
+            let (result, ()) = futures::future::join(req, rsp).await;
+            assert_eq!(result, Ok(None));
+        }
+
+        #[tokio::test]
+        async fn request_timeout() {
+            let (client, mut rx) = helper::client(true);
+            client.set_request_timeout(Some(Duration::from_millis(10)));
+
+            let result = client.workspace_folders().await;
+            assert_eq!(result, Err(crate::jsonrpc::Error::request_timed_out()));
+            assert!(client.inner.pending_requests.0.is_empty());
+
+            let request = ClientRequest::request::<lsp::request::WorkspaceFoldersRequest>(Id::Number(0), ());
+            assert_eq!(rx.next().await, Some(Outgoing::Request(request)));
+            let params = lsp::CancelParams {
+                id: lsp::NumberOrString::Number(0),
+            };
+            let cancel = ClientRequest::notification::<lsp::notification::Cancel>(params);
+            assert_eq!(rx.next().await, Some(Outgoing::Request(cancel)));
+        }
+
+        #[tokio::test]
+        async fn request_timeout_override() {
+            let (client, _rx) = helper::client(true);
+            client.set_request_timeout(Some(Duration::from_secs(60)));
+
+            let result = client.with_timeout(Duration::from_millis(10)).workspace_folders().await;
+            assert_eq!(result, Err(crate::jsonrpc::Error::request_timed_out()));
+
+            let client = client.without_timeout();
+            let req = client.workspace_folders();
+            let rsp = async {
+                let id = Id::Number(1);
+                client.inner.pending_requests.insert(Response::ok(id, json!(null)));
+            };
+            let (result, ()) = futures::future::join(req, rsp).await;
+            assert_eq!(result, Ok(None));
+        }
+
+        #[tokio::test]
+        async fn settings() {
+            let (client, mut rx) = helper::client(true);
+
+            let req = client.settings::<Option<bool>>("lookml.enabled");
+            let rsp = async {
+                let id = Id::Number(0);
+                client.inner.pending_requests.insert(Response::ok(id, json!([true])));
+            };
+            let (result, ()) = futures::future::join(req, rsp).await;
+            assert_eq!(result, Ok(Some(true)));
+            assert!(rx.next().await.is_some());
+
+            let result = client.settings::<Option<bool>>("lookml.enabled").await;
+            assert_eq!(result, Ok(Some(true)));
+            assert!(rx.next().now_or_never().is_none());
+
+            client.did_change_configuration(serde_json::Value::Null);
+            let req = client.settings::<Option<bool>>("lookml.enabled");
+            let rsp = async {
+                let id = Id::Number(1);
+                client.inner.pending_requests.insert(Response::ok(id, json!([null])));
+            };
+            let (result, ()) = futures::future::join(req, rsp).await;
+            assert_eq!(result, Ok(None));
+            assert!(rx.next().await.is_some());
+        }
+
+        #[tokio::test]
+        async fn settings_pushed() {
+            let (client, mut rx) = helper::unsupported_client();
+
+            let result = client.settings::<Option<bool>>("lookml.enabled").await;
+            assert_eq!(result, Ok(None));
+
+            client.did_change_configuration(json!({ "lookml": { "enabled": true } }));
+            let result = client.settings::<Option<bool>>("lookml.enabled").await;
+            assert_eq!(result, Ok(Some(true)));
+
+            let result = client.settings::<bool>("lookml").await;
+            assert_eq!(result.unwrap_err().code, crate::jsonrpc::ErrorCode::ParseError);
+            assert!(rx.next().now_or_never().is_none());
+        }
+
+        #[tokio::test]
+        async fn workspace_folders_unsupported() {
+            let (client, _rx) = helper::unsupported_client();
+            let result = client.workspace_folders().await;
+            let error = crate::jsonrpc::Error::request_failed("Client does not support workspace/workspaceFolders");
+            assert_eq!(result, Err(error));
+        }
+
         #[tokio::test]
         async fn workspace_folders() -> anyhow::Result<()> {
             let (client, _rx) = helper::client(true);
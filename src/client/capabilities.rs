@@ -0,0 +1,138 @@
+//! Lookup of client capabilities relevant to server-to-client requests.
+
+/// Returns whether the client supports dynamic registration for the given method.
+///
+/// Returns `None` if the method does not correspond to a capability known to `lsp-types`, in which
+/// case support can't be determined.
+pub(crate) fn dynamic_registration(capabilities: &lsp::ClientCapabilities, method: &str) -> Option<bool> {
+    let workspace = capabilities.workspace.as_ref();
+    let text_document = capabilities.text_document.as_ref();
+
+    let supported = match method {
+        "workspace/didChangeConfiguration" => workspace
+            .and_then(|c| c.did_change_configuration.as_ref())
+            .and_then(|c| c.dynamic_registration),
+        "workspace/didChangeWatchedFiles" => workspace
+            .and_then(|c| c.did_change_watched_files.as_ref())
+            .and_then(|c| c.dynamic_registration),
+        "workspace/symbol" => workspace
+            .and_then(|c| c.symbol.as_ref())
+            .and_then(|c| c.dynamic_registration),
+        "workspace/executeCommand" => workspace
+            .and_then(|c| c.execute_command.as_ref())
+            .and_then(|c| c.dynamic_registration),
+        "textDocument/didOpen"
+        | "textDocument/didChange"
+        | "textDocument/willSave"
+        | "textDocument/willSaveWaitUntil"
+        | "textDocument/didSave"
+        | "textDocument/didClose" => text_document
+            .and_then(|c| c.synchronization.as_ref())
+            .and_then(|c| c.dynamic_registration),
+        "textDocument/completion" => text_document
+            .and_then(|c| c.completion.as_ref())
+            .and_then(|c| c.dynamic_registration),
+        "textDocument/hover" => text_document
+            .and_then(|c| c.hover.as_ref())
+            .and_then(|c| c.dynamic_registration),
+        "textDocument/signatureHelp" => text_document
+            .and_then(|c| c.signature_help.as_ref())
+            .and_then(|c| c.dynamic_registration),
+        "textDocument/declaration" => text_document
+            .and_then(|c| c.declaration.as_ref())
+            .and_then(|c| c.dynamic_registration),
+        "textDocument/definition" => text_document
+            .and_then(|c| c.definition.as_ref())
+            .and_then(|c| c.dynamic_registration),
+        "textDocument/typeDefinition" => text_document
+            .and_then(|c| c.type_definition.as_ref())
+            .and_then(|c| c.dynamic_registration),
+        "textDocument/implementation" => text_document
+            .and_then(|c| c.implementation.as_ref())
+            .and_then(|c| c.dynamic_registration),
+        "textDocument/references" => text_document
+            .and_then(|c| c.references.as_ref())
+            .and_then(|c| c.dynamic_registration),
+        "textDocument/documentHighlight" => text_document
+            .and_then(|c| c.document_highlight.as_ref())
+            .and_then(|c| c.dynamic_registration),
+        "textDocument/documentSymbol" => text_document
+            .and_then(|c| c.document_symbol.as_ref())
+            .and_then(|c| c.dynamic_registration),
+        "textDocument/codeAction" => text_document
+            .and_then(|c| c.code_action.as_ref())
+            .and_then(|c| c.dynamic_registration),
+        "textDocument/codeLens" => text_document
+            .and_then(|c| c.code_lens.as_ref())
+            .and_then(|c| c.dynamic_registration),
+        "textDocument/documentLink" => text_document
+            .and_then(|c| c.document_link.as_ref())
+            .and_then(|c| c.dynamic_registration),
+        "textDocument/documentColor" => text_document
+            .and_then(|c| c.color_provider.as_ref())
+            .and_then(|c| c.dynamic_registration),
+        "textDocument/formatting" => text_document
+            .and_then(|c| c.formatting.as_ref())
+            .and_then(|c| c.dynamic_registration),
+        "textDocument/rangeFormatting" => text_document
+            .and_then(|c| c.range_formatting.as_ref())
+            .and_then(|c| c.dynamic_registration),
+        "textDocument/onTypeFormatting" => text_document
+            .and_then(|c| c.on_type_formatting.as_ref())
+            .and_then(|c| c.dynamic_registration),
+        "textDocument/rename" => text_document
+            .and_then(|c| c.rename.as_ref())
+            .and_then(|c| c.dynamic_registration),
+        "textDocument/foldingRange" => text_document
+            .and_then(|c| c.folding_range.as_ref())
+            .and_then(|c| c.dynamic_registration),
+        "textDocument/selectionRange" => text_document
+            .and_then(|c| c.selection_range.as_ref())
+            .and_then(|c| c.dynamic_registration),
+        "textDocument/linkedEditingRange" => text_document
+            .and_then(|c| c.linked_editing_range.as_ref())
+            .and_then(|c| c.dynamic_registration),
+        "textDocument/prepareCallHierarchy" => text_document
+            .and_then(|c| c.call_hierarchy.as_ref())
+            .and_then(|c| c.dynamic_registration),
+        "textDocument/semanticTokens" => text_document
+            .and_then(|c| c.semantic_tokens.as_ref())
+            .and_then(|c| c.dynamic_registration),
+        "textDocument/moniker" => text_document
+            .and_then(|c| c.moniker.as_ref())
+            .and_then(|c| c.dynamic_registration),
+        _ => return None,
+    };
+
+    Some(supported.unwrap_or(false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn dynamic_registration_supported() {
+        let capabilities = serde_json::from_value(json!({
+            "workspace": { "didChangeWatchedFiles": { "dynamicRegistration": true } }
+        }))
+        .unwrap();
+        let supported = dynamic_registration(&capabilities, "workspace/didChangeWatchedFiles");
+        assert_eq!(supported, Some(true));
+    }
+
+    #[test]
+    fn dynamic_registration_unsupported() {
+        let capabilities = lsp::ClientCapabilities::default();
+        let supported = dynamic_registration(&capabilities, "textDocument/hover");
+        assert_eq!(supported, Some(false));
+    }
+
+    #[test]
+    fn dynamic_registration_unknown() {
+        let capabilities = lsp::ClientCapabilities::default();
+        let supported = dynamic_registration(&capabilities, "custom/request");
+        assert_eq!(supported, None);
+    }
+}
@@ -0,0 +1,291 @@
+//! Debounced publishing of diagnostics.
+
+use super::Client;
+use futures_timer::Delay;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::{self, Debug, Formatter},
+    future::Future,
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+
+/// Publishes diagnostics to the client, debounced per document.
+///
+/// Diagnostics are published under a source key (e.g., `"syntax"` or `"lint"`), and the client
+/// receives the diagnostics of all sources for a document merged together. Publishing under a key
+/// only replaces the diagnostics previously published under the same key.
+///
+/// Updates for a document are held back until no further updates for it arrive within the delay
+/// given to [`DiagnosticsPublisher::new`], so that only the latest diagnostics are sent when they
+/// are recomputed on every keystroke. Diagnostics computed for an older version of a document
+/// than the latest one published are dropped, and those computed for a newer version replace the
+/// diagnostics of all sources.
+///
+/// This type provides a very cheap implementation of [`Clone`], sharing the state of all documents
+/// between the clones.
+///
+/// [`Clone`]: trait@std::clone::Clone
+#[derive(Clone)]
+pub struct DiagnosticsPublisher {
+    client: Client,
+    delay: Duration,
+    documents: Arc<Mutex<Documents>>,
+}
+
+#[derive(Debug, Default)]
+struct Documents {
+    generation: u64,
+    entries: HashMap<lsp::Url, Entry>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    generation: u64,
+    version: Option<i32>,
+    sources: BTreeMap<String, Vec<lsp::Diagnostic>>,
+}
+
+impl DiagnosticsPublisher {
+    /// Creates a new `DiagnosticsPublisher` which waits for `delay` before publishing an update.
+    pub fn new(client: Client, delay: Duration) -> Self {
+        DiagnosticsPublisher {
+            client,
+            delay,
+            documents: Default::default(),
+        }
+    }
+
+    /// Publishes the diagnostics from `source` for the given version of a document.
+    ///
+    /// The returned future completes once the diagnostics have been sent to the client, or as soon
+    /// as they have been superseded by a later update for the same document. It does not borrow the
+    /// publisher, so it can be spawned to avoid delaying the caller by the debounce delay.
+    ///
+    /// # Initialization
+    ///
+    /// The diagnostics will only be sent if the server is initialized.
+    pub fn publish(
+        &self,
+        uri: lsp::Url,
+        source: impl Into<String>,
+        diagnostics: Vec<lsp::Diagnostic>,
+        version: Option<i32>,
+    ) -> impl Future<Output = ()> + Send + 'static {
+        let generation = self.update(&uri, source.into(), diagnostics, version);
+        let publisher = self.clone();
+
+        async move {
+            let generation = match generation {
+                Some(generation) => generation,
+                None => return,
+            };
+
+            Delay::new(publisher.delay).await;
+
+            let (diagnostics, version) = {
+                let documents = publisher.documents.lock().unwrap_or_else(PoisonError::into_inner);
+                match documents.entries.get(&uri) {
+                    Some(entry) if entry.generation == generation => {
+                        let diagnostics = entry.sources.values().flatten().cloned().collect();
+                        (diagnostics, entry.version)
+                    },
+                    _ => return,
+                }
+            };
+
+            publisher.client.publish_diagnostics(uri, diagnostics, version).await;
+        }
+    }
+
+    /// Forgets all diagnostics for a document and clears them on the client.
+    ///
+    /// This should be called from [`LanguageServer::did_close`]. Pending updates for the document
+    /// are discarded, and the next update is treated as one for a newly opened document.
+    ///
+    /// [`LanguageServer::did_close`]: crate::LanguageServer::did_close
+    ///
+    /// # Initialization
+    ///
+    /// The diagnostics will only be cleared if the server is initialized.
+    pub async fn clear(&self, uri: lsp::Url) {
+        let removed = {
+            let mut documents = self.documents.lock().unwrap_or_else(PoisonError::into_inner);
+            documents.generation += 1;
+            documents.entries.remove(&uri)
+        };
+
+        if removed.is_some() {
+            self.client.publish_diagnostics(uri, Vec::new(), None).await;
+        }
+    }
+
+    /// Records an update, returning its generation or `None` if its version is outdated.
+    fn update(
+        &self,
+        uri: &lsp::Url,
+        source: String,
+        diagnostics: Vec<lsp::Diagnostic>,
+        version: Option<i32>,
+    ) -> Option<u64> {
+        let mut documents = self.documents.lock().unwrap_or_else(PoisonError::into_inner);
+        documents.generation += 1;
+        let generation = documents.generation;
+
+        let entry = documents.entries.entry(uri.clone()).or_insert_with(|| Entry {
+            generation,
+            version,
+            sources: BTreeMap::new(),
+        });
+
+        match (version, entry.version) {
+            (Some(version), Some(latest)) if version < latest => {
+                log::debug!("dropping diagnostics for {} version {} < {}", uri, version, latest);
+                return None;
+            },
+            (Some(version), Some(latest)) if version > latest => {
+                entry.sources.clear();
+                entry.version = Some(version);
+            },
+            (Some(_), _) => entry.version = version,
+            (None, _) => {},
+        }
+
+        entry.generation = generation;
+        entry.sources.insert(source, diagnostics);
+        Some(generation)
+    }
+}
+
+impl Debug for DiagnosticsPublisher {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct(stringify!(DiagnosticsPublisher))
+            .field("delay", &self.delay)
+            .field("documents", &self.documents)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jsonrpc::{ClientRequest, Outgoing};
+    use futures::{FutureExt, StreamExt};
+
+    const DELAY: Duration = Duration::from_millis(20);
+
+    fn publisher() -> (DiagnosticsPublisher, crate::queue::Receiver) {
+        let state = Arc::new(crate::server::State::new());
+        state.set(crate::server::StateKind::Initialized);
+        let (tx, rx) = crate::queue::channel(4, crate::OverflowPolicy::Block);
+        let pending_client = Arc::new(crate::jsonrpc::ClientRequests::new());
+        let client = Client::new(tx, pending_client, state);
+        (DiagnosticsPublisher::new(client, DELAY), rx)
+    }
+
+    fn uri() -> lsp::Url {
+        lsp::Url::parse("inmemory::///test").unwrap()
+    }
+
+    fn diagnostic(message: &str) -> lsp::Diagnostic {
+        lsp::Diagnostic::new_simple(Default::default(), message.into())
+    }
+
+    fn published(diagnostics: Vec<lsp::Diagnostic>, version: Option<i32>) -> Outgoing {
+        let params = lsp::PublishDiagnosticsParams::new(uri(), diagnostics, version);
+        let notification = ClientRequest::notification::<lsp::notification::PublishDiagnostics>(params);
+        Outgoing::Request(notification)
+    }
+
+    #[tokio::test]
+    async fn clear() {
+        let (publisher, mut rx) = publisher();
+
+        publisher.clear(uri()).await;
+        assert!(rx.next().now_or_never().is_none());
+
+        let publish = publisher.publish(uri(), "syntax", vec![diagnostic("a")], Some(1));
+        let clear = async {
+            tokio::time::sleep(DELAY / 2).await;
+            publisher.clear(uri()).await;
+        };
+        futures::future::join(publish, clear).await;
+        assert_eq!(rx.next().await, Some(published(vec![], None)));
+        assert!(rx.next().now_or_never().is_none());
+    }
+
+    #[tokio::test]
+    async fn publishes_after_clear() {
+        let (publisher, mut rx) = publisher();
+
+        publisher
+            .publish(uri(), "syntax", vec![diagnostic("a")], Some(57))
+            .await;
+        assert_eq!(rx.next().await, Some(published(vec![diagnostic("a")], Some(57))));
+        publisher.clear(uri()).await;
+        assert_eq!(rx.next().await, Some(published(vec![], None)));
+
+        // The document is reopened with a version counting up from the start again.
+        publisher.publish(uri(), "syntax", vec![diagnostic("b")], Some(1)).await;
+        assert_eq!(rx.next().await, Some(published(vec![diagnostic("b")], Some(1))));
+        publisher.clear(uri()).await;
+        assert_eq!(rx.next().await, Some(published(vec![], None)));
+
+        publisher.publish(uri(), "workspace", vec![diagnostic("c")], None).await;
+        assert_eq!(rx.next().await, Some(published(vec![diagnostic("c")], None)));
+    }
+
+    #[tokio::test]
+    async fn debounce() {
+        let (publisher, mut rx) = publisher();
+
+        let first = publisher.publish(uri(), "syntax", vec![diagnostic("a")], Some(1));
+        let second = publisher.publish(uri(), "syntax", vec![diagnostic("b")], Some(2));
+        futures::future::join(first, second).await;
+
+        assert_eq!(rx.next().await, Some(published(vec![diagnostic("b")], Some(2))));
+        assert!(rx.next().now_or_never().is_none());
+    }
+
+    #[tokio::test]
+    async fn drops_outdated_versions() {
+        let (publisher, mut rx) = publisher();
+
+        publisher.publish(uri(), "syntax", vec![diagnostic("a")], Some(2)).await;
+        assert_eq!(rx.next().await, Some(published(vec![diagnostic("a")], Some(2))));
+
+        publisher.publish(uri(), "syntax", vec![diagnostic("b")], Some(1)).await;
+        assert!(rx.next().now_or_never().is_none());
+    }
+
+    #[tokio::test]
+    async fn merges_sources() {
+        let (publisher, mut rx) = publisher();
+
+        let syntax = publisher.publish(uri(), "syntax", vec![diagnostic("a")], Some(1));
+        let lint = publisher.publish(uri(), "lint", vec![diagnostic("b")], Some(1));
+        futures::future::join(syntax, lint).await;
+
+        let diagnostics = vec![diagnostic("b"), diagnostic("a")];
+        assert_eq!(rx.next().await, Some(published(diagnostics, Some(1))));
+
+        publisher.publish(uri(), "lint", vec![], Some(1)).await;
+        assert_eq!(rx.next().await, Some(published(vec![diagnostic("a")], Some(1))));
+    }
+
+    #[tokio::test]
+    async fn replaces_sources_of_older_versions() {
+        let (publisher, mut rx) = publisher();
+
+        let syntax = publisher.publish(uri(), "syntax", vec![diagnostic("a")], Some(1));
+        let lint = publisher.publish(uri(), "lint", vec![diagnostic("b")], Some(1));
+        futures::future::join(syntax, lint).await;
+        assert_eq!(
+            rx.next().await,
+            Some(published(vec![diagnostic("b"), diagnostic("a")], Some(1)))
+        );
+
+        publisher.publish(uri(), "syntax", vec![diagnostic("c")], Some(2)).await;
+        assert_eq!(rx.next().await, Some(published(vec![diagnostic("c")], Some(2))));
+    }
+}
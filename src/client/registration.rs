@@ -0,0 +1,71 @@
+//! Guards for capabilities registered dynamically with the language client.
+
+use super::Client;
+use std::fmt::{self, Debug, Formatter};
+
+/// A capability dynamically registered with the client through [`Client::register`].
+///
+/// The capability is unregistered when the guard is dropped. Use [`RegistrationGuard::unregister`]
+/// to wait for the client to acknowledge the unregistration instead, or
+/// [`RegistrationGuard::forget`] to keep the capability registered for the rest of the session.
+#[must_use = "the capability is unregistered as soon as the guard is dropped"]
+pub struct RegistrationGuard {
+    client: Client,
+    unregistration: Option<lsp::Unregistration>,
+}
+
+impl RegistrationGuard {
+    pub(super) fn new(client: Client, id: String, method: String) -> Self {
+        RegistrationGuard {
+            client,
+            unregistration: Some(lsp::Unregistration { id, method }),
+        }
+    }
+
+    /// Returns the unique ID generated for this registration.
+    pub fn id(&self) -> &str {
+        self.unregistration.as_ref().map_or("", |u| u.id.as_str())
+    }
+
+    /// Returns the method this registration applies to.
+    pub fn method(&self) -> &str {
+        self.unregistration.as_ref().map_or("", |u| u.method.as_str())
+    }
+
+    /// Unregisters the capability, waiting for the client to respond.
+    ///
+    /// This corresponds to the [`client/unregisterCapability`] request.
+    ///
+    /// [`client/unregisterCapability`]: https://microsoft.github.io/language-server-protocol/specification#client_unregisterCapability
+    pub async fn unregister(mut self) -> crate::jsonrpc::Result<()> {
+        match self.unregistration.take() {
+            Some(unregistration) => self.client.unregister_capability(vec![unregistration]).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Keeps the capability registered once the guard is dropped.
+    pub fn forget(mut self) {
+        self.unregistration = None;
+    }
+}
+
+impl Drop for RegistrationGuard {
+    fn drop(&mut self) {
+        if let Some(unregistration) = self.unregistration.take() {
+            let params = lsp::UnregistrationParams {
+                unregisterations: vec![unregistration],
+            };
+            self.client
+                .send_request_detached::<lsp::request::UnregisterCapability>(params);
+        }
+    }
+}
+
+impl Debug for RegistrationGuard {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct(stringify!(RegistrationGuard))
+            .field("unregistration", &self.unregistration)
+            .finish()
+    }
+}
@@ -0,0 +1,147 @@
+//! Generation of the IDs of requests sent to the language client.
+
+use crate::jsonrpc::Id;
+use std::{
+    convert::TryFrom,
+    fmt::{self, Debug, Formatter},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        PoisonError,
+        RwLock,
+    },
+};
+
+/// Source of the namespaces of [`RequestIdStrategy::Namespaced`], shared by all connections.
+static NAMESPACES: AtomicU64 = AtomicU64::new(0);
+
+/// How the [`Client`] generates the IDs of the requests it sends to the language client.
+///
+/// String IDs can be told apart from the client's own numeric IDs, e.g. when correlating the
+/// requests passing through a proxy or written to logs. See
+/// [`LspService::client_request_ids`](crate::LspService::client_request_ids).
+///
+/// [`Client`]: crate::Client
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum RequestIdStrategy {
+    /// Numbers counting up from `0`, which wrap around to `0` after `i32::MAX` so that every
+    /// request can be cancelled with a `$/cancelRequest` notification. This is the default.
+    Number,
+    /// Strings made of the given prefix followed by a counter, e.g. `"lookml-42"` for the prefix
+    /// `"lookml-"`.
+    Prefixed(String),
+    /// Like [`RequestIdStrategy::Prefixed`], with a namespace unique to the connection inserted
+    /// after the prefix, e.g. `"lookml-3-42"` for the prefix `"lookml-"`. Namespaces are counted
+    /// up from `0` by each service configured with this strategy in the process.
+    Namespaced(String),
+}
+
+/// Generator of request IDs following a [`RequestIdStrategy`].
+pub(crate) struct RequestIds {
+    /// Prefix of string IDs, or `None` for numeric IDs.
+    prefix: RwLock<Option<String>>,
+    pub(super) counter: AtomicU64,
+}
+
+impl RequestIds {
+    pub(crate) fn new() -> Self {
+        RequestIds {
+            prefix: RwLock::new(None),
+            counter: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn set_strategy(&self, strategy: RequestIdStrategy) {
+        let prefix = match strategy {
+            RequestIdStrategy::Number => None,
+            RequestIdStrategy::Prefixed(prefix) => Some(prefix),
+            RequestIdStrategy::Namespaced(prefix) => {
+                let namespace = NAMESPACES.fetch_add(1, Ordering::Relaxed);
+                Some(format!("{}{}-", prefix, namespace))
+            },
+        };
+        *self.prefix.write().unwrap_or_else(PoisonError::into_inner) = prefix;
+    }
+
+    /// Returns the ID for the next request.
+    pub(crate) fn next(&self) -> Id {
+        let prefix = self.prefix.read().unwrap_or_else(PoisonError::into_inner);
+        match *prefix {
+            Some(ref prefix) => Id::String(format!("{}{}", prefix, self.counter.fetch_add(1, Ordering::Relaxed))),
+            None => {
+                let modulus = i32::MAX as u64 + 1;
+                let id = self
+                    .counter
+                    .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |id| Some((id + 1) % modulus))
+                    .unwrap_or_else(|id| id);
+                Id::Number(id % modulus)
+            },
+        }
+    }
+
+    /// Returns the ID the next request would get, without using it up.
+    pub(crate) fn peek(&self) -> Id {
+        let prefix = self.prefix.read().unwrap_or_else(PoisonError::into_inner);
+        let id = self.counter.load(Ordering::Relaxed);
+        match *prefix {
+            Some(ref prefix) => Id::String(format!("{}{}", prefix, id)),
+            None => Id::Number(id % (i32::MAX as u64 + 1)),
+        }
+    }
+}
+
+/// Returns the parameters of the `$/cancelRequest` notification cancelling the request with the
+/// given ID, or `None` if the ID does not fit in the notification.
+pub(crate) fn cancel_params(id: &Id) -> Option<lsp::CancelParams> {
+    let id = match *id {
+        Id::Number(number) => match i32::try_from(number) {
+            Ok(number) => lsp::NumberOrString::Number(number),
+            Err(_) => {
+                log::error!(
+                    "request ID {} does not fit in a cancel notification, not sending it",
+                    id
+                );
+                return None;
+            },
+        },
+        Id::String(ref string) => lsp::NumberOrString::String(string.clone()),
+    };
+    Some(lsp::CancelParams { id })
+}
+
+impl Debug for RequestIds {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        Debug::fmt(&self.peek(), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strategies() {
+        let ids = RequestIds::new();
+        assert_eq!(ids.next(), Id::Number(0));
+        assert_eq!(ids.next(), Id::Number(1));
+
+        ids.set_strategy(RequestIdStrategy::Prefixed("lookml-".into()));
+        assert_eq!(ids.peek(), Id::String("lookml-2".into()));
+        assert_eq!(ids.next(), Id::String("lookml-2".into()));
+
+        let other = RequestIds::new();
+        ids.set_strategy(RequestIdStrategy::Namespaced("lookml-".into()));
+        other.set_strategy(RequestIdStrategy::Namespaced("lookml-".into()));
+        match (ids.next(), other.next()) {
+            (Id::String(id), Id::String(other_id)) => {
+                assert!(id.starts_with("lookml-") && id.ends_with("-3"), "{}", id);
+                assert!(
+                    other_id.starts_with("lookml-") && other_id.ends_with("-0"),
+                    "{}",
+                    other_id
+                );
+                assert_ne!(id.trim_end_matches("-3"), other_id.trim_end_matches("-0"));
+            },
+            ids => panic!("expected string IDs, got {:?}", ids),
+        }
+    }
+}
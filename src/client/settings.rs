@@ -0,0 +1,107 @@
+//! Caching of workspace configuration settings.
+
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    sync::{Mutex, PoisonError},
+};
+
+/// Cache of configuration sections fetched from the client, keyed by scope URI and section.
+///
+/// Every `workspace/didChangeConfiguration` notification clears the cache and bumps its generation,
+/// so that values fetched concurrently with the change are not cached.
+#[derive(Debug, Default)]
+pub(crate) struct SettingsCache {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    generation: u64,
+    pushed: Option<Value>,
+    sections: HashMap<(Option<lsp::Url>, String), Value>,
+}
+
+impl SettingsCache {
+    /// Returns the cached value of a section, if any.
+    pub(crate) fn get(&self, scope_uri: &Option<lsp::Url>, section: &str) -> Option<Value> {
+        let inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        inner.sections.get(&(scope_uri.clone(), section.into())).cloned()
+    }
+
+    /// Returns the current generation of the cache, to be passed to [`SettingsCache::insert`].
+    pub(crate) fn generation(&self) -> u64 {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner).generation
+    }
+
+    /// Caches the value of a section, unless the configuration changed since `generation`.
+    pub(crate) fn insert(&self, generation: u64, scope_uri: Option<lsp::Url>, section: &str, value: Value) {
+        let mut inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        if inner.generation == generation {
+            inner.sections.insert((scope_uri, section.into()), value);
+        }
+    }
+
+    /// Looks up a section in the settings last pushed by the client.
+    ///
+    /// Dots in `section` separate nested keys, so `"lookml.lint"` refers to `settings.lookml.lint`.
+    /// Returns `Value::Null` if the client has not pushed any settings or the section is missing.
+    pub(crate) fn pushed(&self, section: &str) -> Value {
+        let inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        inner
+            .pushed
+            .as_ref()
+            .and_then(|settings| {
+                section
+                    .split('.')
+                    .filter(|key| !key.is_empty())
+                    .try_fold(settings, |value, key| value.get(key))
+            })
+            .cloned()
+            .unwrap_or(Value::Null)
+    }
+
+    /// Clears the cache, retaining the given settings pushed by the client unless they are `null`.
+    pub(crate) fn invalidate(&self, settings: Value) {
+        let mut inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        inner.generation += 1;
+        inner.sections.clear();
+        if !settings.is_null() {
+            inner.pushed = Some(settings);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn insert_after_invalidate() {
+        let cache = SettingsCache::default();
+        let generation = cache.generation();
+        cache.insert(generation, None, "lookml", json!(1));
+        assert_eq!(cache.get(&None, "lookml"), Some(json!(1)));
+
+        let generation = cache.generation();
+        cache.invalidate(Value::Null);
+        assert_eq!(cache.get(&None, "lookml"), None);
+        cache.insert(generation, None, "lookml", json!(2));
+        assert_eq!(cache.get(&None, "lookml"), None);
+    }
+
+    #[test]
+    fn pushed() {
+        let cache = SettingsCache::default();
+        assert_eq!(cache.pushed("lookml"), Value::Null);
+
+        cache.invalidate(json!({ "lookml": { "lint": { "enabled": true } } }));
+        assert_eq!(cache.pushed("lookml.lint"), json!({ "enabled": true }));
+        assert_eq!(cache.pushed("lookml.format"), Value::Null);
+        assert_eq!(cache.pushed(""), json!({ "lookml": { "lint": { "enabled": true } } }));
+
+        cache.invalidate(Value::Null);
+        assert_eq!(cache.pushed("lookml.lint"), json!({ "enabled": true }));
+    }
+}
@@ -52,16 +52,54 @@ impl From<std::str::Utf8Error> for ParseError {
     }
 }
 
+/// Messages which can be decoded from the JSON body of a Language Server Protocol message.
+pub(crate) trait Decode: Sized {
+    /// Decodes a message from its JSON body.
+    fn decode(body: &str) -> serde_json::Result<Self>;
+
+    /// Decodes a message from its JSON body, keeping the parameters of requests as raw JSON.
+    fn decode_raw(body: &str) -> serde_json::Result<Self> {
+        Self::decode(body)
+    }
+}
+
+impl Decode for crate::jsonrpc::Incoming {
+    fn decode(body: &str) -> serde_json::Result<Self> {
+        body.parse()
+    }
+
+    fn decode_raw(body: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(body)
+    }
+}
+
 /// Encodes and decodes Language Server Protocol messages.
 #[derive(Clone, Debug)]
 pub struct LanguageServerCodec<T> {
     http_error: Option<httparse::Error>,
     headers_len: Option<usize>,
     content_len: Option<usize>,
+    metrics: Option<crate::metrics::Metrics>,
+    raw_params: bool,
+    buffer: Vec<u8>,
     _marker: PhantomData<T>,
 }
 
 impl<T> LanguageServerCodec<T> {
+    /// Counts the encoded and decoded messages and their sizes in the given metrics.
+    pub(crate) fn with_metrics(mut self, metrics: Option<crate::metrics::Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Keeps the parameters of decoded requests as raw JSON, see [`Server::raw_params`].
+    ///
+    /// [`Server::raw_params`]: crate::Server::raw_params
+    pub(crate) fn with_raw_params(mut self, raw_params: bool) -> Self {
+        self.raw_params = raw_params;
+        self
+    }
+
     fn reset(&mut self) {
         self.http_error = None;
         self.headers_len = None;
@@ -75,6 +113,9 @@ impl<T> Default for LanguageServerCodec<T> {
             http_error: None,
             headers_len: None,
             content_len: None,
+            metrics: None,
+            raw_params: false,
+            buffer: Vec::new(),
             _marker: PhantomData,
         }
     }
@@ -86,16 +127,27 @@ impl<T: serde::Serialize> Encoder for LanguageServerCodec<T> {
     type Item = T;
 
     fn encode(&mut self, item: Self::Item, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        let msg = serde_json::to_string(&item)?;
-        log::trace!("-> {}", msg);
+        // Serialize into a buffer reused across messages, since the length is needed up front.
+        self.buffer.clear();
+        serde_json::to_writer(&mut self.buffer, &item)?;
+        let msg = &self.buffer[..];
+        if log::log_enabled!(log::Level::Trace) {
+            log::trace!("-> {}", String::from_utf8_lossy(msg));
+        }
 
         // Reserve just enough space to hold the `Content-Length: ` and `\r\n\r\n` constants,
         // the length of the message, and the message body.
         dst.reserve(msg.len() + number_of_digits(msg.len()) + 20);
+        let start = dst.len();
         let mut writer = dst.writer();
-        write!(writer, "Content-Length: {}\r\n\r\n{}", msg.len(), msg)?;
+        write!(writer, "Content-Length: {}\r\n\r\n", msg.len())?;
+        writer.write_all(msg)?;
         writer.flush()?;
 
+        if let Some(ref metrics) = self.metrics {
+            metrics.record_sent(writer.get_ref().len() - start);
+        }
+
         Ok(())
     }
 }
@@ -105,16 +157,27 @@ impl<T: serde::Serialize> Encoder<T> for LanguageServerCodec<T> {
     type Error = ParseError;
 
     fn encode(&mut self, item: T, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        let msg = serde_json::to_string(&item)?;
-        log::trace!("-> {}", msg);
+        // Serialize into a buffer reused across messages, since the length is needed up front.
+        self.buffer.clear();
+        serde_json::to_writer(&mut self.buffer, &item)?;
+        let msg = &self.buffer[..];
+        if log::log_enabled!(log::Level::Trace) {
+            log::trace!("-> {}", String::from_utf8_lossy(msg));
+        }
 
         // Reserve just enough space to hold the `Content-Length: ` and `\r\n\r\n` constants,
         // the length of the message, and the message body.
         dst.reserve(msg.len() + number_of_digits(msg.len()) + 20);
+        let start = dst.len();
         let mut writer = dst.writer();
-        write!(writer, "Content-Length: {}\r\n\r\n{}", msg.len(), msg)?;
+        write!(writer, "Content-Length: {}\r\n\r\n", msg.len())?;
+        writer.write_all(msg)?;
         writer.flush()?;
 
+        if let Some(ref metrics) = self.metrics {
+            metrics.record_sent(writer.get_ref().len() - start);
+        }
+
         Ok(())
     }
 }
@@ -131,7 +194,7 @@ fn number_of_digits(mut n: usize) -> usize {
     num_digits
 }
 
-impl<T: serde::de::DeserializeOwned> Decoder for LanguageServerCodec<T> {
+impl<T: Decode> Decoder for LanguageServerCodec<T> {
     type Error = ParseError;
     type Item = T;
 
@@ -184,7 +247,12 @@ impl<T: serde::de::DeserializeOwned> Decoder for LanguageServerCodec<T> {
             log::trace!("<- {}", message);
 
             // Deserialize the JSON-RPC message JSON as data
-            let data = match serde_json::from_str(message) {
+            let decoded = if self.raw_params {
+                T::decode_raw(message)
+            } else {
+                T::decode(message)
+            };
+            let data = match decoded {
                 Ok(parsed) => Ok(Some(parsed)),
                 Err(err) => Err(err.into()),
             };
@@ -195,6 +263,10 @@ impl<T: serde::de::DeserializeOwned> Decoder for LanguageServerCodec<T> {
             // Advance the buffer
             src.advance(delta);
 
+            if let Some(ref metrics) = self.metrics {
+                metrics.record_received(delta);
+            }
+
             // Return the deserialized data
             data
 
@@ -227,13 +299,38 @@ mod tests {
 
     use super::*;
 
+    impl Decode for Value {
+        fn decode(body: &str) -> serde_json::Result<Self> {
+            serde_json::from_str(body)
+        }
+    }
+
+    #[test]
+    fn decode_raw_params() {
+        use crate::{generated_impl::Params, jsonrpc::Incoming};
+
+        let decoded = r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#;
+        let encoded = format!("Content-Length: {}\r\n\r\n{}", decoded.len(), decoded);
+
+        for &raw_params in &[false, true] {
+            let mut codec = LanguageServerCodec::<Incoming>::default().with_raw_params(raw_params);
+            let mut buffer = BytesMut::from(encoded.as_str());
+            match codec.decode(&mut buffer).unwrap() {
+                Some(Incoming::Request(request)) => {
+                    assert_eq!(matches!(request.params(), Some(Params::Raw(_))), raw_params)
+                },
+                message => panic!("unexpected message: {:?}", message),
+            }
+        }
+    }
+
     #[test]
     fn decodes_invalid_content_length() {
         let decoded = r#"{"jsonrpc":"2.0","method":"exit"}"#.to_string();
         let content_len = "Content-Length: foo".to_string();
         let encoded = format!("{}\r\n\r\n{}", content_len, decoded);
 
-        let mut codec = LanguageServerCodec::<()>::default();
+        let mut codec = LanguageServerCodec::<Value>::default();
         let mut buffer = BytesMut::from(encoded.as_str());
         let message = codec.decode(&mut buffer);
         if let Err(ParseError::InvalidLength) = message {
@@ -277,7 +374,7 @@ mod tests {
         let content_len = "Content-Length: 42".to_string();
         let encoded = format!("{}\r\n\r\n", content_len);
 
-        let mut codec = LanguageServerCodec::<()>::default();
+        let mut codec = LanguageServerCodec::<Value>::default();
         let mut buffer = BytesMut::from(encoded.as_str());
         let message = codec.decode(&mut buffer);
         if let Ok(None) = message {
@@ -0,0 +1,352 @@
+//! Routing of custom requests and notifications declared in extension traits.
+//!
+//! Methods beyond the Language Server Protocol (e.g., `lookml/compileSql`) can be declared in a
+//! trait of their own, annotated with [`rpc`](crate::rpc) just like [`LanguageServer`]:
+//!
+//! ```rust
+//! use lspower::{jsonrpc::Result, rpc};
+//!
+//! #[rpc]
+//! #[lspower::async_trait]
+//! pub trait LookmlExtensions: Send + Sync + 'static {
+//!     #[rpc(name = "lookml/compileSql")]
+//!     async fn compile_sql(&self, params: lspower::lsp::TextDocumentIdentifier) -> Result<String>;
+//!
+//!     #[rpc(name = "lookml/didChangeModel")]
+//!     async fn did_change_model(&self, params: serde_json::Value);
+//! }
+//! ```
+//!
+//! Methods may also be declared by a type implementing [`lsp::request::Request`] or
+//! [`lsp::notification::Notification`], as in `#[rpc(request = CompileSql)]`, in which case the
+//! handler must agree with its parameters and result.
+//!
+//! This generates a `LookmlExtensionsRouter` implementing [`Router`], which is registered with
+//! [`LspService::extension`]. The methods of a trait are routed only once the server has been
+//! initialized, and after those of [`LanguageServer`], which take precedence. Their parameters are
+//! validated, and requests can be cancelled with [`$/cancelRequest`].
+//!
+//! [`LanguageServer`]: crate::LanguageServer
+//! [`LspService::extension`]: crate::LspService::extension
+//! [`$/cancelRequest`]: https://microsoft.github.io/language-server-protocol/specification#cancelRequest
+
+use crate::{
+    jsonrpc::{not_initialized_error, Error, ErrorCode, Id, Outgoing, Response, ServerRequests},
+    server::StateKind,
+};
+use futures::{
+    future::{self, BoxFuture},
+    FutureExt,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::value::RawValue;
+use std::fmt::{self, Debug, Formatter};
+
+/// Routes the requests and notifications of an extension trait to their handlers.
+///
+/// This trait is implemented by the routers generated with [`rpc`](crate::rpc), and is not
+/// meant to be implemented manually.
+pub trait Router: Send + Sync + 'static {
+    /// Returns the names of the methods handled by this router.
+    fn methods(&self) -> &'static [&'static str];
+
+    /// Calls the handler of the given method with the given parameters.
+    ///
+    /// Returns `Err` with a description of the problem if the parameters are invalid.
+    fn call(&self, method: &str, params: Params) -> Result<Call, String>;
+}
+
+impl Debug for dyn Router {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct(stringify!(Router))
+            .field("methods", &self.methods())
+            .finish()
+    }
+}
+
+/// Parameters of a request or notification passed to a [`Router`].
+#[derive(Debug)]
+pub struct Params(Option<Box<RawValue>>);
+
+impl Params {
+    /// Deserializes the parameters into the type expected by the handler.
+    ///
+    /// Missing parameters are deserialized from `null`, which succeeds for types such as `()` and
+    /// `Option<T>`.
+    pub fn parse<P: DeserializeOwned>(self) -> Result<P, String> {
+        match self.0 {
+            Some(params) => serde_json::from_str(params.get()).map_err(|e| e.to_string()),
+            None => serde_json::from_str("null").map_err(|_| "Missing params field".to_string()),
+        }
+    }
+}
+
+/// Pending call of a request or notification handler, returned by [`Router::call`].
+pub struct Call(Kind);
+
+enum Kind {
+    Request(BoxFuture<'static, crate::jsonrpc::Result<Box<RawValue>>>),
+    Notification(BoxFuture<'static, ()>),
+}
+
+impl Call {
+    /// Creates a call of a request handler, whose result is sent back to the client.
+    pub fn request<F, T>(future: F) -> Self
+    where
+        F: std::future::Future<Output = crate::jsonrpc::Result<T>> + Send + 'static,
+        T: Serialize,
+    {
+        let future = future.map(|result| {
+            result.and_then(|v| {
+                serde_json::value::to_raw_value(&v).map_err(|e| Error {
+                    code: ErrorCode::InternalError,
+                    message: e.to_string(),
+                    data: None,
+                })
+            })
+        });
+        Call(Kind::Request(future.boxed()))
+    }
+
+    /// Creates a call of a notification handler.
+    pub fn notification<F>(future: F) -> Self
+    where
+        F: std::future::Future<Output = ()> + Send + 'static,
+    {
+        Call(Kind::Notification(future.boxed()))
+    }
+}
+
+impl Debug for Call {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.0 {
+            Kind::Request(_) => f.write_str("Call::Request"),
+            Kind::Notification(_) => f.write_str("Call::Notification"),
+        }
+    }
+}
+
+/// Routers of the extension traits registered with a service.
+#[derive(Debug, Default)]
+pub(crate) struct Extensions(Vec<Box<dyn Router>>);
+
+impl Extensions {
+    pub(crate) fn push(&mut self, router: Box<dyn Router>) {
+        self.0.push(router);
+    }
+
+    /// Returns whether any of the routers handles the given method.
+    pub(crate) fn handles(&self, method: &str) -> bool {
+        self.router(method).is_some()
+    }
+
+    /// Routes a request or notification to the router handling its method.
+    pub(crate) fn route(
+        &self,
+        method: &str,
+        id: Option<Id>,
+        params: Option<Box<RawValue>>,
+        state: StateKind,
+        pending: &ServerRequests,
+    ) -> BoxFuture<'static, Option<Outgoing>> {
+        let respond = |id, error| future::ready(Some(Outgoing::Response(Response::error(Some(id), error)))).boxed();
+
+        let router = match self.router(method) {
+            Some(router) => router,
+            None => return future::ready(None).boxed(),
+        };
+
+        match (state, id) {
+            (StateKind::Initialized, id) => match (router.call(method, Params(params)), id) {
+                (Ok(Call(Kind::Request(future))), Some(id)) => {
+                    pending.execute(id, future).map(Outgoing::Response).map(Some).boxed()
+                },
+                (Ok(Call(Kind::Request(_))), None) => {
+                    log::warn!("received {:?} request without an ID, ignoring", method);
+                    future::ready(None).boxed()
+                },
+                (Ok(Call(Kind::Notification(future))), _) => future.map(|()| None).boxed(),
+                (Err(e), Some(id)) => {
+                    log::error!("invalid parameters for {:?} request", method);
+                    respond(id, Error::invalid_params(e))
+                },
+                (Err(e), None) => {
+                    log::warn!("invalid parameters for {:?} notification: {}", method, e);
+                    future::ready(None).boxed()
+                },
+            },
+            (StateKind::Uninitialized, Some(id)) => respond(id, not_initialized_error()),
+            (_, Some(id)) => respond(id, Error::invalid_request()),
+            (_, None) => future::ready(None).boxed(),
+        }
+    }
+
+    fn router(&self, method: &str) -> Option<&dyn Router> {
+        self.0
+            .iter()
+            .find(|router| router.methods().contains(&method))
+            .map(|router| &**router)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        jsonrpc::{Id, Incoming, Outgoing, Response, Result},
+        rpc,
+        LanguageServer,
+        LspService,
+    };
+    use async_trait::async_trait;
+    use serde_json::json;
+    use std::{
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc,
+        },
+        task::Poll,
+        time::Duration,
+    };
+    use tower_test::mock::Spawn;
+
+    enum Add {}
+
+    impl lsp::request::Request for Add {
+        type Params = (u64, u64);
+        type Result = u64;
+
+        const METHOD: &'static str = "test/add";
+    }
+
+    #[rpc]
+    #[async_trait]
+    pub trait Extensions: Send + Sync + 'static {
+        #[rpc(request = Add)]
+        async fn add(&self, params: (u64, u64)) -> Result<u64>;
+
+        #[rpc(name = "test/sleep")]
+        async fn sleep(&self) -> Result<()>;
+
+        #[rpc(name = "test/increment")]
+        async fn increment(&self, params: Option<u64>);
+    }
+
+    #[derive(Debug, Default)]
+    struct Mock {
+        count: AtomicU64,
+    }
+
+    #[async_trait]
+    impl LanguageServer for Mock {
+        async fn initialize(&self, _: lsp::InitializeParams) -> Result<lsp::InitializeResult> {
+            Ok(lsp::InitializeResult::default())
+        }
+
+        async fn shutdown(&self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl Extensions for Mock {
+        async fn add(&self, (a, b): (u64, u64)) -> Result<u64> {
+            Ok(a + b)
+        }
+
+        async fn sleep(&self) -> Result<()> {
+            futures_timer::Delay::new(Duration::from_secs(10)).await;
+            Ok(())
+        }
+
+        async fn increment(&self, by: Option<u64>) {
+            self.count.fetch_add(by.unwrap_or(1), Ordering::SeqCst);
+        }
+    }
+
+    fn message(value: serde_json::Value) -> Incoming {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn response(value: serde_json::Value) -> Option<Outgoing> {
+        Some(Outgoing::Response(serde_json::from_value(value).unwrap()))
+    }
+
+    #[tokio::test]
+    async fn routes_extension_methods() {
+        let (service, _) = LspService::new(|_| Mock::default());
+        let service = service.extension(ExtensionsRouter::<Mock>::new);
+        let mut service = Spawn::new(service);
+
+        let add = message(json!({ "jsonrpc": "2.0", "method": "test/add", "params": [1, 2], "id": 1 }));
+        let not_initialized = json!({
+            "jsonrpc": "2.0",
+            "error": { "code": -32002, "message": "Server not initialized" },
+            "id": 1,
+        });
+        assert_eq!(service.poll_ready(), Poll::Ready(Ok(())));
+        assert_eq!(service.call(add.clone()).await, Ok(response(not_initialized)));
+
+        let initialize = json!({ "jsonrpc": "2.0", "method": "initialize", "params": { "capabilities": {} }, "id": 1 });
+        assert!(service.call(message(initialize)).await.is_ok());
+
+        let sum = json!({ "jsonrpc": "2.0", "result": 3, "id": 1 });
+        assert_eq!(service.call(add).await, Ok(response(sum)));
+
+        let add = message(json!({ "jsonrpc": "2.0", "method": "test/add", "params": [1], "id": 2 }));
+        match service.call(add).await {
+            Ok(Some(Outgoing::Response(response))) => {
+                assert_eq!(response.into_parts().1.unwrap_err().code.code(), -32602)
+            },
+            other => panic!("unexpected response: {:?}", other),
+        }
+
+        let unknown = message(json!({ "jsonrpc": "2.0", "method": "test/unknown", "id": 3 }));
+        let not_found = Response::error(Some(Id::Number(3)), crate::jsonrpc::Error::method_not_found());
+        assert_eq!(service.call(unknown).await, Ok(Some(Outgoing::Response(not_found))));
+    }
+
+    #[tokio::test]
+    async fn routes_notifications() {
+        let mut server = None;
+        let (service, _) = LspService::new(|_| Mock::default());
+        let service = service.extension(|mock: Arc<Mock>| {
+            server = Some(mock.clone());
+            ExtensionsRouter::new(mock)
+        });
+        let server = server.unwrap();
+        let mut service = Spawn::new(service);
+
+        let initialize = json!({ "jsonrpc": "2.0", "method": "initialize", "params": { "capabilities": {} }, "id": 1 });
+        assert_eq!(service.poll_ready(), Poll::Ready(Ok(())));
+        assert!(service.call(message(initialize)).await.is_ok());
+
+        let increment = json!({ "jsonrpc": "2.0", "method": "test/increment" });
+        assert_eq!(service.call(message(increment)).await, Ok(None));
+        let increment = json!({ "jsonrpc": "2.0", "method": "test/increment", "params": 2 });
+        assert_eq!(service.call(message(increment)).await, Ok(None));
+        let increment = json!({ "jsonrpc": "2.0", "method": "test/increment", "params": "three" });
+        assert_eq!(service.call(message(increment)).await, Ok(None));
+        assert_eq!(server.count.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn cancels_requests() {
+        let (service, _) = LspService::new(|_| Mock::default());
+        let mut service = Spawn::new(service.extension(ExtensionsRouter::<Mock>::new));
+
+        let initialize = json!({ "jsonrpc": "2.0", "method": "initialize", "params": { "capabilities": {} }, "id": 1 });
+        assert_eq!(service.poll_ready(), Poll::Ready(Ok(())));
+        assert!(service.call(message(initialize)).await.is_ok());
+
+        let sleep = service.call(message(json!({ "jsonrpc": "2.0", "method": "test/sleep", "id": 2 })));
+        let cancel = json!({ "jsonrpc": "2.0", "method": "$/cancelRequest", "params": { "id": 2 } });
+        assert_eq!(service.call(message(cancel)).await, Ok(None));
+
+        let cancelled = json!({
+            "jsonrpc": "2.0",
+            "error": { "code": -32800, "message": "Canceled" },
+            "id": 2,
+        });
+        assert_eq!(sleep.await, Ok(response(cancelled)));
+    }
+}
@@ -11,10 +11,11 @@ use serde::{
     Deserialize,
     Serialize,
 };
-use serde_json::Value;
+use serde_json::{value::RawValue, Value};
 use std::{
     borrow::Cow,
     fmt::{self, Debug, Display, Formatter},
+    str::FromStr,
 };
 
 /// A specialized [`Result`] error type for JSON-RPC handlers.
@@ -54,7 +55,24 @@ impl Response {
     pub const fn ok(id: Id, result: Value) -> Self {
         Response {
             jsonrpc: Version,
-            kind: ResponseKind::Ok { result, id },
+            kind: ResponseKind::Ok {
+                result: Body::Value(result),
+                id,
+            },
+        }
+    }
+
+    /// Creates a new response from a request ID and a result which has already been serialized.
+    pub(crate) fn from_raw_parts(id: Id, body: Result<Box<RawValue>>) -> Self {
+        match body {
+            Ok(result) => Response {
+                jsonrpc: Version,
+                kind: ResponseKind::Ok {
+                    result: Body::Raw(result),
+                    id,
+                },
+            },
+            Err(error) => Response::error(Some(id), error),
         }
     }
 
@@ -78,7 +96,7 @@ impl Response {
     /// signify whether the response is a success or failure.
     pub fn into_parts(self) -> (Option<Id>, Result<Value>) {
         match self.kind {
-            ResponseKind::Ok { id, result } => (Some(id), Ok(result)),
+            ResponseKind::Ok { id, result } => (Some(id), Ok(result.into_value())),
             ResponseKind::Err { id, error } => (id, Err(error)),
         }
     }
@@ -90,20 +108,76 @@ impl Response {
             ResponseKind::Err { ref id, .. } => id.as_ref(),
         }
     }
+
+    /// Returns `true` if this is an error response.
+    pub fn is_error(&self) -> bool {
+        matches!(self.kind, ResponseKind::Err { .. })
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 #[serde(untagged)]
 enum ResponseKind {
-    Ok { result: Value, id: Id },
+    Ok { result: Body, id: Id },
     Err { error: Error, id: Option<Id> },
 }
 
+/// Result of a successful response.
+///
+/// Results returned by request handlers are serialized once into raw JSON, which is then copied
+/// verbatim into the encoded message, instead of being converted into a [`Value`] first.
+#[derive(Clone, Debug)]
+enum Body {
+    Value(Value),
+    Raw(Box<RawValue>),
+}
+
+impl Body {
+    fn into_value(self) -> Value {
+        match self {
+            Body::Value(value) => value,
+            // Raw results are produced by `serde_json` itself, so they always parse.
+            Body::Raw(raw) => serde_json::from_str(raw.get()).unwrap_or(Value::Null),
+        }
+    }
+}
+
+impl PartialEq for Body {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Body::Value(a), Body::Value(b)) => a == b,
+            (Body::Raw(a), Body::Raw(b)) if a.get() == b.get() => true,
+            _ => self.clone().into_value() == other.clone().into_value(),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Body {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Value::deserialize(deserializer).map(Body::Value)
+    }
+}
+
+impl Serialize for Body {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            Body::Value(value) => value.serialize(serializer),
+            Body::Raw(raw) => raw.serialize(serializer),
+        }
+    }
+}
+
 /// An incoming JSON-RPC message.
-#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[derive(Clone, Debug, PartialEq)]
 #[cfg_attr(test, derive(Serialize))]
-#[serde(untagged)]
+#[cfg_attr(test, serde(untagged))]
 pub enum Incoming {
     /// Request intended for the language server.
     Request(crate::generated_impl::ServerRequest),
@@ -111,6 +185,129 @@ pub enum Incoming {
     Response(Response),
 }
 
+impl Incoming {
+    /// Returns the name of the method if this is a request or notification.
+    pub fn method(&self) -> Option<&str> {
+        match self {
+            Incoming::Request(request) => Some(request.method()),
+            Incoming::Response(_) => None,
+        }
+    }
+
+    /// Returns the request ID if this is a request, or the ID of the answered request if this is a
+    /// response.
+    pub fn id(&self) -> Option<&Id> {
+        match self {
+            Incoming::Request(request) => request.id(),
+            Incoming::Response(response) => response.id(),
+        }
+    }
+}
+
+impl FromStr for Incoming {
+    type Err = serde_json::Error;
+
+    /// Decodes a message from its JSON body.
+    ///
+    /// This is how the transport decodes messages. The parameters of requests are deserialized
+    /// straight into the type expected by the handler of their method if the method comes first,
+    /// as is the case with most clients. Otherwise, or if the parameters turn out to be invalid,
+    /// they are kept as raw JSON for the handler to parse, as with [`Deserialize`].
+    fn from_str(body: &str) -> serde_json::Result<Self> {
+        let decode = |typed| {
+            let mut deserializer = serde_json::Deserializer::from_str(body);
+            let message = deserializer.deserialize_map(IncomingVisitor { typed })?;
+            deserializer.end()?;
+            Ok(message)
+        };
+
+        decode(true).or_else(|error: serde_json::Error| if error.is_data() { decode(false) } else { Err(error) })
+    }
+}
+
+impl<'de> Deserialize<'de> for Incoming {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(IncomingVisitor { typed: false })
+    }
+}
+
+/// Visits the fields of an incoming message in a single pass, without buffering the message as an
+/// untagged enum would.
+struct IncomingVisitor {
+    typed: bool,
+}
+
+impl<'de> de::Visitor<'de> for IncomingVisitor {
+    type Value = Incoming;
+
+    fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str("a JSON-RPC request, notification or response")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> std::result::Result<Self::Value, A::Error>
+    where
+        A: de::MapAccess<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(field_identifier, rename_all = "lowercase")]
+        enum Field {
+            Jsonrpc,
+            Method,
+            Id,
+            Params,
+            Result,
+            Error,
+            #[serde(other)]
+            Other,
+        }
+
+        let mut version = None;
+        let mut method = None;
+        let mut id = None;
+        let mut params = None;
+        let mut result = None;
+        let mut error = None;
+
+        while let Some(field) = map.next_key()? {
+            match field {
+                Field::Jsonrpc => version = Some(map.next_value::<Version>()?),
+                Field::Method => method = Some(map.next_value::<String>()?),
+                Field::Id => id = map.next_value::<Option<Id>>()?,
+                Field::Params => {
+                    params = map.next_value_seed(crate::generated_impl::ParamsSeed {
+                        method: method.as_deref(),
+                        typed: self.typed,
+                    })?
+                },
+                Field::Result => result = Some(map.next_value::<Value>()?),
+                Field::Error => error = map.next_value::<Option<Error>>()?,
+                Field::Other => {
+                    map.next_value::<de::IgnoredAny>()?;
+                },
+            }
+        }
+
+        if version.is_none() {
+            return Err(de::Error::missing_field("jsonrpc"));
+        }
+
+        match (method, result, error, id) {
+            (Some(method), None, None, id) => {
+                let request = crate::generated_impl::ServerRequest::new(method, id, params);
+                Ok(Incoming::Request(request))
+            },
+            (None, Some(result), None, Some(id)) => Ok(Incoming::Response(Response::ok(id, result))),
+            (None, None, Some(error), id) => Ok(Incoming::Response(Response::error(id, error))),
+            _ => Err(de::Error::custom(
+                "expected a JSON-RPC request, notification or response",
+            )),
+        }
+    }
+}
+
 /// A server-to-client LSP request.
 #[derive(Clone, Debug, PartialEq, Serialize)]
 #[cfg_attr(test, derive(Deserialize))]
@@ -123,7 +320,7 @@ pub struct ClientRequest {
 
 impl ClientRequest {
     /// Constructs a JSON-RPC request from its corresponding LSP type.
-    pub(crate) fn request<R: lsp::request::Request>(id: u64, params: R::Params) -> Self {
+    pub(crate) fn request<R: lsp::request::Request>(id: Id, params: R::Params) -> Self {
         // Since `R::Params` come from the `lsp-types` crate and validity is enforced via the
         // `Request` trait, the `unwrap()` call below should never fail.
         ClientRequest {
@@ -131,7 +328,7 @@ impl ClientRequest {
             method: R::METHOD.into(),
             kind: ClientMethod::Request {
                 params: serde_json::to_value(params).unwrap(),
-                id: Id::Number(id),
+                id,
             },
         }
     }
@@ -148,6 +345,29 @@ impl ClientRequest {
             },
         }
     }
+
+    /// Constructs a JSON-RPC request from a method name and untyped parameters.
+    pub(crate) fn raw_request<M: Into<Cow<'static, str>>>(id: Id, method: M, params: Value) -> Self {
+        ClientRequest {
+            jsonrpc: Version,
+            method: method.into(),
+            kind: ClientMethod::Request { params, id },
+        }
+    }
+
+    /// Constructs a JSON-RPC notification from a method name and untyped parameters.
+    pub(crate) fn raw_notification<M: Into<Cow<'static, str>>>(method: M, params: Value) -> Self {
+        ClientRequest {
+            jsonrpc: Version,
+            method: method.into(),
+            kind: ClientMethod::Notification { params },
+        }
+    }
+
+    /// Returns the name of the method to be invoked.
+    pub(crate) fn method(&self) -> &str {
+        &self.method
+    }
 }
 
 impl Display for ClientRequest {
@@ -233,11 +453,12 @@ impl Serialize for Version {
 /// See [here](https://microsoft.github.io/language-server-protocol/specification#initialize)
 /// for reference.
 pub(crate) fn not_initialized_error() -> Error {
-    Error {
-        code: ErrorCode::ServerError(-32002),
-        message: "Server not initialized".to_string(),
-        data: None,
-    }
+    Error::server_not_initialized()
+}
+
+/// Error returned for every server-to-client request the client did not advertise support for.
+pub(crate) fn not_supported_error(method: &str) -> Error {
+    Error::request_failed(format!("Client does not support {}", method))
 }
 
 #[cfg(test)]
@@ -250,7 +471,7 @@ mod tests {
         #[test]
         fn display() {
             let id = 0;
-            let request = ClientRequest::request::<lsp::request::Shutdown>(id, ());
+            let request = ClientRequest::request::<lsp::request::Shutdown>(Id::Number(id), ());
             format!("{}", request);
         }
     }
@@ -271,6 +492,55 @@ mod tests {
         }
     }
 
+    mod incoming {
+        use super::*;
+        use serde_json::json;
+
+        #[test]
+        fn method_and_id() {
+            let raw = json!({ "jsonrpc": "2.0", "method": "shutdown", "id": 1 });
+            let incoming: Incoming = serde_json::from_value(raw).unwrap();
+            assert_eq!(incoming.method(), Some("shutdown"));
+            assert_eq!(incoming.id(), Some(&Id::Number(1)));
+
+            let raw = json!({ "jsonrpc": "2.0", "method": "initialized", "params": {} });
+            let incoming: Incoming = serde_json::from_value(raw).unwrap();
+            assert_eq!(incoming.method(), Some("initialized"));
+            assert_eq!(incoming.id(), None);
+
+            let raw = json!({ "jsonrpc": "2.0", "method": "lookml/custom", "id": "a" });
+            let incoming: Incoming = serde_json::from_value(raw).unwrap();
+            assert_eq!(incoming.method(), Some("lookml/custom"));
+            assert_eq!(incoming.id(), Some(&Id::String("a".into())));
+
+            let raw = json!({ "jsonrpc": "2.0", "result": null, "id": 2 });
+            let incoming: Incoming = serde_json::from_value(raw).unwrap();
+            assert_eq!(incoming.method(), None);
+            assert_eq!(incoming.id(), Some(&Id::Number(2)));
+        }
+
+        #[test]
+        fn from_str() {
+            let body = r#"{"jsonrpc":"2.0","id":1,"method":"textDocument/hover","params":{
+                "textDocument":{"uri":"inmemory::///test"},"position":{"line":0,"character":0}}}"#;
+            let incoming = Incoming::from_str(body).unwrap();
+            assert_eq!(incoming, serde_json::from_str(body).unwrap());
+
+            let body = r#"{"jsonrpc":"2.0","id":2,"method":"textDocument/hover","params":{"position":0}}"#;
+            let incoming = Incoming::from_str(body).unwrap();
+            assert_eq!(incoming.method(), Some("textDocument/hover"));
+            assert_eq!(incoming.id(), Some(&Id::Number(2)));
+
+            let body = r#"{"jsonrpc":"2.0","result":null,"id":3}"#;
+            let incoming = Incoming::from_str(body).unwrap();
+            assert_eq!(incoming, Incoming::Response(Response::ok(Id::Number(3), Value::Null)));
+
+            assert!(Incoming::from_str(r#"{"jsonrpc":"2.0","id":4}"#).is_err());
+            assert!(Incoming::from_str(r#"{"method":"exit"}"#).is_err());
+            assert!(Incoming::from_str(r#"{"jsonrpc":"2.0","method":"exit""#).is_err());
+        }
+    }
+
     mod outgoing {
         use super::*;
         use serde_json::json;
@@ -293,7 +563,10 @@ mod tests {
                 let actions = Default::default();
                 lsp::ShowMessageRequestParams { typ, message, actions }
             };
-            let outgoing = Outgoing::Request(ClientRequest::request::<lsp::request::ShowMessageRequest>(id, params));
+            let outgoing = Outgoing::Request(ClientRequest::request::<lsp::request::ShowMessageRequest>(
+                Id::Number(id),
+                params,
+            ));
             let json = json!({
                 "jsonrpc": "2.0",
                 "method": "window/showMessageRequest",
@@ -328,6 +601,19 @@ mod tests {
             assert_eq!(response.into_parts(), (Some(id), body));
         }
 
+        #[test]
+        fn from_raw_parts() {
+            let id = Id::Number(1);
+            let result = serde_json::value::to_raw_value(&json!({ "a": [1, 2] })).unwrap();
+            let response = Response::from_raw_parts(id.clone(), Ok(result));
+            assert_eq!(response, Response::ok(id.clone(), json!({ "a": [1, 2] })));
+            assert_eq!(
+                serde_json::to_string(&response).unwrap(),
+                r#"{"jsonrpc":"2.0","result":{"a":[1,2]},"id":1}"#
+            );
+            assert_eq!(response.into_parts(), (Some(id), Ok(json!({ "a": [1, 2] }))));
+        }
+
         #[test]
         fn id_ok() {
             let id = Id::Number(1);
@@ -335,6 +621,13 @@ mod tests {
             assert_eq!(response.id(), Some(&id));
         }
 
+        #[test]
+        fn is_error() {
+            let id = Id::Number(1);
+            assert!(!Response::ok(id.clone(), json!({})).is_error());
+            assert!(Response::error(Some(id), Error::internal_error()).is_error());
+        }
+
         #[test]
         fn id_err() {
             let id = Id::Number(1);
@@ -19,6 +19,18 @@ pub enum ErrorCode {
     InternalError,
     /// Reserved for implementation-defined server errors.
     ServerError(i64),
+    /// The server received a request before the `initialize` request.
+    ///
+    /// # Compatibility
+    ///
+    /// This error code is defined by the Language Server Protocol.
+    ServerNotInitialized,
+    /// Reserved for errors which don't have a more specific code.
+    ///
+    /// # Compatibility
+    ///
+    /// This error code is defined by the Language Server Protocol.
+    UnknownErrorCode,
     /// The request was cancelled by the client.
     ///
     /// # Compatibility
@@ -31,6 +43,19 @@ pub enum ErrorCode {
     ///
     /// This error code is specific to the Language Server Protocol.
     ContentModified,
+    /// The request was cancelled by the server.
+    ///
+    /// # Compatibility
+    ///
+    /// This error code is defined by the Language Server Protocol since version 3.17.0.
+    ServerCancelled,
+    /// The request failed although its parameters were valid, e.g. because the server could not
+    /// compute a result.
+    ///
+    /// # Compatibility
+    ///
+    /// This error code is defined by the Language Server Protocol since version 3.17.0.
+    RequestFailed,
 }
 
 impl ErrorCode {
@@ -42,8 +67,12 @@ impl ErrorCode {
             ErrorCode::MethodNotFound => -32601,
             ErrorCode::InvalidParams => -32602,
             ErrorCode::InternalError => -32603,
+            ErrorCode::ServerNotInitialized => -32002,
+            ErrorCode::UnknownErrorCode => -32001,
             ErrorCode::RequestCancelled => -32800,
             ErrorCode::ContentModified => -32801,
+            ErrorCode::ServerCancelled => -32802,
+            ErrorCode::RequestFailed => -32803,
             ErrorCode::ServerError(code) => code,
         }
     }
@@ -56,8 +85,12 @@ impl ErrorCode {
             ErrorCode::MethodNotFound => "Method not found",
             ErrorCode::InvalidParams => "Invalid params",
             ErrorCode::InternalError => "Internal error",
+            ErrorCode::ServerNotInitialized => "Server not initialized",
+            ErrorCode::UnknownErrorCode => "Unknown error",
             ErrorCode::RequestCancelled => "Canceled",
             ErrorCode::ContentModified => "Content modified",
+            ErrorCode::ServerCancelled => "Server cancelled",
+            ErrorCode::RequestFailed => "Request failed",
             ErrorCode::ServerError(_) => "Server error",
         }
     }
@@ -71,8 +104,12 @@ impl From<i64> for ErrorCode {
             -32601 => ErrorCode::MethodNotFound,
             -32602 => ErrorCode::InvalidParams,
             -32603 => ErrorCode::InternalError,
+            -32002 => ErrorCode::ServerNotInitialized,
+            -32001 => ErrorCode::UnknownErrorCode,
             -32800 => ErrorCode::RequestCancelled,
             -32801 => ErrorCode::ContentModified,
+            -32802 => ErrorCode::ServerCancelled,
+            -32803 => ErrorCode::RequestFailed,
             code => ErrorCode::ServerError(code),
         }
     }
@@ -126,6 +163,30 @@ impl Error {
         }
     }
 
+    /// Creates a new error from the given `ErrorCode` and message, with additional data.
+    ///
+    /// The data is serialized to JSON so clients can inspect it. If serialization fails, the error
+    /// is created without data.
+    pub fn with_data<M, T>(code: ErrorCode, message: M, data: T) -> Self
+    where
+        M: Into<String>,
+        T: Serialize,
+    {
+        let data = match serde_json::to_value(data) {
+            Ok(data) => Some(data),
+            Err(e) => {
+                log::error!("failed to serialize error data: {}", e);
+                None
+            },
+        };
+
+        Error {
+            code,
+            message: message.into(),
+            data,
+        }
+    }
+
     /// Creates a new parse error (`-32700`).
     pub fn parse_error() -> Self {
         Error::new(ErrorCode::ParseError)
@@ -158,6 +219,24 @@ impl Error {
         Error::new(ErrorCode::InternalError)
     }
 
+    /// Creates a new "server not initialized" error (`-32002`).
+    ///
+    /// # Compatibility
+    ///
+    /// This error code is defined by the Language Server Protocol.
+    pub fn server_not_initialized() -> Self {
+        Error::new(ErrorCode::ServerNotInitialized)
+    }
+
+    /// Creates a new "unknown error" error (`-32001`).
+    ///
+    /// # Compatibility
+    ///
+    /// This error code is defined by the Language Server Protocol.
+    pub fn unknown_error() -> Self {
+        Error::new(ErrorCode::UnknownErrorCode)
+    }
+
     /// Creates a new "request cancelled" error (`-32800`).
     ///
     /// # Compatibility
@@ -175,6 +254,43 @@ impl Error {
     pub fn content_modified() -> Self {
         Error::new(ErrorCode::ContentModified)
     }
+
+    /// Creates a new "request timed out" error (`-32000`).
+    ///
+    /// This is returned by [`Client`](crate::Client) requests which the client did not answer in
+    /// time. Its code lies in the range reserved for implementation-defined server errors.
+    pub fn request_timed_out() -> Self {
+        Error {
+            code: ErrorCode::ServerError(-32000),
+            message: "Request timed out".to_string(),
+            data: None,
+        }
+    }
+
+    /// Creates a new "server cancelled" error (`-32802`).
+    ///
+    /// # Compatibility
+    ///
+    /// This error code is defined by the Language Server Protocol since version 3.17.0.
+    pub fn server_cancelled() -> Self {
+        Error::new(ErrorCode::ServerCancelled)
+    }
+
+    /// Creates a new "request failed" error (`-32803`).
+    ///
+    /// # Compatibility
+    ///
+    /// This error code is defined by the Language Server Protocol since version 3.17.0.
+    pub fn request_failed<M>(message: M) -> Self
+    where
+        M: Into<String>,
+    {
+        Error {
+            code: ErrorCode::RequestFailed,
+            message: message.into(),
+            data: None,
+        }
+    }
 }
 
 impl Display for Error {
@@ -265,6 +381,51 @@ mod tests {
         assert_eq!(code.description(), error.message);
     }
 
+    #[test]
+    fn server_cancelled() {
+        let code = ErrorCode::ServerCancelled;
+        assert_eq!(code, code.code().into());
+        let error = Error::server_cancelled();
+        assert_eq!(code, error.code);
+        assert_eq!(code.description(), error.message);
+    }
+
+    #[test]
+    fn request_failed() {
+        let code = ErrorCode::RequestFailed;
+        assert_eq!(code, code.code().into());
+        let error = Error::request_failed(code.description());
+        assert_eq!(code, error.code);
+        assert_eq!(code.description(), error.message);
+    }
+
+    #[test]
+    fn unknown_error() {
+        let code = ErrorCode::UnknownErrorCode;
+        assert_eq!(code, code.code().into());
+        let error = Error::unknown_error();
+        assert_eq!(code, error.code);
+        assert_eq!(code.description(), error.message);
+    }
+
+    #[test]
+    fn with_data() {
+        let error = Error::with_data(ErrorCode::RequestFailed, "failed", vec!["view.lkml"]);
+        assert_eq!(ErrorCode::RequestFailed, error.code);
+        assert_eq!("failed", error.message);
+        assert_eq!(Some(serde_json::json!(["view.lkml"])), error.data);
+
+        let raw = serde_json::json!({ "code": -32803, "message": "failed", "data": ["view.lkml"] });
+        assert_eq!(error, serde_json::from_value(raw).unwrap());
+    }
+
+    #[test]
+    fn with_data_unserializable() {
+        let data: std::collections::HashMap<(i32, i32), i32> = vec![((1, 2), 3)].into_iter().collect();
+        let error = Error::with_data(ErrorCode::RequestFailed, "failed", data);
+        assert_eq!(None, error.data);
+    }
+
     #[test]
     fn server_error() {
         let code = ErrorCode::ServerError(42);
@@ -276,9 +437,11 @@ mod tests {
 
     #[test]
     fn server_not_initialized() {
-        let code = ErrorCode::ServerError(-32002);
-        let error = crate::jsonrpc::not_initialized_error();
+        let code = ErrorCode::ServerNotInitialized;
+        assert_eq!(code, code.code().into());
+        let error = Error::server_not_initialized();
         assert_eq!(code, error.code);
-        assert_eq!("Server not initialized", error.message);
+        assert_eq!(code.description(), error.message);
+        assert_eq!(error, crate::jsonrpc::not_initialized_error());
     }
 }
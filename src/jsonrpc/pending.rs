@@ -1,45 +1,65 @@
 //! Hashmaps for tracking pending JSON-RPC requests.
 
-use super::{Error, Id, Response, Result};
+use super::{Error, ErrorCode, Id, Response, Result};
 use dashmap::{mapref::entry::Entry, DashMap};
-use futures::{channel::oneshot, future};
+use futures::{channel::oneshot, future, task::AtomicWaker};
+use futures_timer::Delay;
 use serde::Serialize;
 use std::{
     fmt::{self, Debug, Formatter},
     future::Future,
     sync::Arc,
+    task::Poll,
+    time::Duration,
 };
 
 /// A hashmap containing pending server requests, keyed by request ID.
-pub struct ServerRequests(Arc<DashMap<Id, future::AbortHandle>>);
+#[derive(Clone)]
+pub struct ServerRequests {
+    requests: Arc<DashMap<Id, future::AbortHandle>>,
+    removed: Arc<AtomicWaker>,
+}
 
 impl ServerRequests {
     /// Creates a new pending server requests map.
     pub fn new() -> Self {
-        ServerRequests(Arc::new(DashMap::new()))
+        ServerRequests {
+            requests: Arc::new(DashMap::new()),
+            removed: Arc::new(AtomicWaker::new()),
+        }
     }
 
     /// Executes the given async request handler, keyed by the given request ID.
     ///
     /// If a cancel request is issued before the future is finished resolving, this will resolve to
-    /// a "canceled" error response, and the pending request handler future will be dropped.
+    /// a "canceled" error response, and the pending request handler future will be dropped. The
+    /// request is no longer considered pending once the returned future completes or is dropped.
     pub fn execute<F, T>(&self, id: Id, fut: F) -> impl Future<Output = Response> + Send + 'static
     where
         F: Future<Output = Result<T>> + Send + 'static,
         T: Serialize,
     {
-        if let Entry::Vacant(entry) = self.0.entry(id.clone()) {
+        if let Entry::Vacant(entry) = self.requests.entry(id.clone()) {
             let (handler_fut, abort_handle) = future::abortable(fut);
             entry.insert(abort_handle);
 
-            let requests = self.0.clone();
+            let guard = RemoveOnDrop {
+                requests: self.clone(),
+                id: id.clone(),
+            };
             future::Either::Left(async move {
                 let abort_result = handler_fut.await;
-                requests.remove(&id); // Remove abort handle now to avoid double cancellation.
+                drop(guard); // Remove abort handle now to avoid double cancellation.
 
                 if let Ok(handler_result) = abort_result {
-                    let result = handler_result.map(|v| serde_json::to_value(v).unwrap());
-                    Response::from_parts(id, result)
+                    let result = handler_result.and_then(|v| {
+                        serde_json::value::to_raw_value(&v).map_err(|e| Error {
+                            code: ErrorCode::InternalError,
+                            message: e.to_string(),
+                            data: None,
+                        })
+                    });
+                    Response::from_raw_parts(id, result)
                 } else {
                     Response::error(Some(id), Error::request_cancelled())
                 }
@@ -54,7 +74,7 @@ impl ServerRequests {
     /// This will force the future to resolve to a "canceled" error response. If the future has
     /// already completed, this method call will do nothing.
     pub fn cancel(&self, id: &Id) {
-        if let Some((_, handle)) = self.0.remove(id) {
+        if let Some(handle) = self.remove(id) {
             handle.abort();
             log::info!("successfully cancelled request with ID: {}", id);
         } else {
@@ -67,17 +87,67 @@ impl ServerRequests {
 
     /// Cancels all pending request handlers, if any.
     pub fn cancel_all(&self) {
-        self.0.retain(|_, handle| {
+        self.requests.retain(|_, handle| {
             handle.abort();
             false
         });
+        self.removed.wake();
+    }
+
+    /// Returns the number of request handlers which are still pending.
+    pub(crate) fn len(&self) -> usize {
+        self.requests.len()
+    }
+
+    /// Waits for all pending request handlers to finish, giving up after the given grace period.
+    ///
+    /// Any request handlers still pending once the grace period has elapsed are cancelled, just as
+    /// with [`ServerRequests::cancel_all`].
+    pub fn drain(&self, grace_period: Duration) -> impl Future<Output = ()> + Send + 'static {
+        let requests = self.clone();
+        async move {
+            let drained = future::poll_fn(|cx| {
+                requests.removed.register(cx.waker());
+                if requests.requests.is_empty() {
+                    Poll::Ready(())
+                } else {
+                    Poll::Pending
+                }
+            });
+
+            if let future::Either::Right(_) = future::select(drained, Delay::new(grace_period)).await {
+                log::warn!(
+                    "pending requests did not finish within {:?}, cancelling them",
+                    grace_period
+                );
+                requests.cancel_all();
+            }
+        }
+    }
+
+    fn remove(&self, id: &Id) -> Option<future::AbortHandle> {
+        let removed = self.requests.remove(id).map(|(_, handle)| handle);
+        self.removed.wake();
+        removed
+    }
+}
+
+/// Removes a request from [`ServerRequests`] when dropped, even if its handler never completes.
+struct RemoveOnDrop {
+    requests: ServerRequests,
+    id: Id,
+}
+
+impl Drop for RemoveOnDrop {
+    fn drop(&mut self) {
+        self.requests.remove(&self.id);
     }
 }
 
 impl Debug for ServerRequests {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         f.debug_set()
-            .entries(self.0.iter().map(|entry| entry.key().clone()))
+            .entries(self.requests.iter().map(|entry| entry.key().clone()))
             .finish()
     }
 }
@@ -106,20 +176,36 @@ impl ClientRequests {
         }
     }
 
-    /// Marks the given request ID as pending and waits for its corresponding response to arrive.
+    /// Marks the given request ID as pending and returns a future waiting for its corresponding
+    /// response to arrive.
     ///
-    /// # Panics
+    /// If the request ID is removed from the hashmap before a response arrives, the future resolves
+    /// to a "request cancelled" error response.
     ///
-    /// Panics if the request ID is already in the hashmap and is pending a matching response. This
-    /// should never happen provided that a monotonically increasing `id` value is used.
-    pub fn wait(&self, id: Id) -> impl Future<Output = Response> + Send + 'static {
+    /// # Errors
+    ///
+    /// Returns `Err` if the request ID is already in the hashmap and is pending a matching
+    /// response. This can only happen if request IDs are reused before the requests are
+    /// answered.
+    pub fn wait(&self, id: Id) -> Result<impl Future<Output = Response> + Send + 'static> {
         match self.0.entry(id) {
             Entry::Vacant(entry) => {
+                let id = entry.key().clone();
                 let (tx, rx) = oneshot::channel();
                 entry.insert(tx);
-                async { rx.await.expect("sender already dropped") }
+                Ok(async move {
+                    rx.await
+                        .unwrap_or_else(|_| Response::error(Some(id), Error::request_cancelled()))
+                })
+            },
+            Entry::Occupied(entry) => {
+                log::error!("request ID {} is already pending a response", entry.key());
+                Err(Error {
+                    code: ErrorCode::InternalError,
+                    message: format!("Request ID {} is already pending a response", entry.key()),
+                    data: None,
+                })
             },
-            _ => panic!("concurrent waits for the same request ID can't happen, this is a bug"),
         }
     }
 }
@@ -147,12 +233,28 @@ mod tests {
         }
 
         #[tokio::test]
-        #[should_panic]
-        async fn wait_current() {
+        async fn wait_duplicate() {
             let pending = ClientRequests::new();
             let id = Id::Number(1);
-            tokio::spawn(pending.wait(id.clone()));
-            tokio::spawn(pending.wait(id));
+            let wait_fut = pending.wait(id.clone()).unwrap();
+
+            let error = pending.wait(id.clone()).err().unwrap();
+            assert_eq!(error.code, crate::jsonrpc::ErrorCode::InternalError);
+
+            let expected = Response::ok(id, json!({}));
+            pending.insert(expected.clone());
+            assert_eq!(expected, wait_fut.await);
+        }
+
+        #[tokio::test]
+        async fn wait_removed() {
+            let pending = ClientRequests::new();
+            let id = Id::Number(1);
+            let wait_fut = pending.wait(id.clone()).unwrap();
+
+            pending.0.remove(&id);
+            let expected = Response::error(Some(id), Error::request_cancelled());
+            assert_eq!(expected, wait_fut.await);
         }
 
         #[tokio::test]
@@ -160,7 +262,7 @@ mod tests {
             let pending = ClientRequests::new();
 
             let id = Id::Number(1);
-            let wait_fut = tokio::spawn(pending.wait(id.clone()));
+            let wait_fut = tokio::spawn(pending.wait(id.clone()).unwrap());
 
             let expected = Response::ok(id.clone(), json!({}));
             pending.insert(expected.clone());
@@ -209,6 +311,17 @@ mod tests {
             assert_eq!(fut1.await, Response::error(Some(id.clone()), Error::invalid_request()));
         }
 
+        #[tokio::test]
+        async fn execute_dropped() {
+            let pending = ServerRequests::new();
+            let id = Id::Number(1);
+            let fut = pending.execute(id.clone(), future::pending::<Result<()>>());
+            assert!(pending.requests.contains_key(&id));
+
+            drop(fut);
+            assert!(pending.requests.is_empty());
+        }
+
         #[tokio::test]
         async fn cancel() {
             let pending = ServerRequests::new();
@@ -233,6 +346,40 @@ mod tests {
             pending.cancel(&id);
         }
 
+        #[tokio::test]
+        async fn drain() {
+            let pending = ServerRequests::new();
+
+            let id = Id::Number(1);
+            let handler_fut = tokio::spawn(pending.execute(id.clone(), async {
+                tokio::time::sleep(Duration::from_millis(30)).await;
+                Ok(json!({}))
+            }));
+
+            pending.drain(Duration::from_secs(50)).await;
+            assert!(pending.requests.is_empty());
+
+            let res = handler_fut.await.expect("task panicked");
+            assert_eq!(res, Response::ok(id, json!({})));
+        }
+
+        #[tokio::test]
+        async fn drain_elapsed() {
+            let pending = ServerRequests::new();
+
+            let id = Id::Number(1);
+            let handler_fut = tokio::spawn(pending.execute(id.clone(), async {
+                tokio::time::sleep(Duration::from_secs(50)).await;
+                Ok(json!({}))
+            }));
+
+            pending.drain(Duration::from_millis(30)).await;
+            assert!(pending.requests.is_empty());
+
+            let res = handler_fut.await.expect("task panicked");
+            assert_eq!(res, Response::error(Some(id), Error::request_cancelled()));
+        }
+
         #[tokio::test]
         async fn cancel_all() {
             let pending = ServerRequests::new();
@@ -0,0 +1,645 @@
+//! Client side of the Language Server Protocol.
+//!
+//! [`LanguageClient`] drives a language server the way an editor would, which is useful for
+//! integration tests and batch tools. It speaks the same wire format as [`Server`], reusing its
+//! codec and JSON-RPC types.
+//!
+//! [`Server`]: crate::Server
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use lspower::language_client::{Handler, LanguageClient};
+//! use tokio::process::Command;
+//!
+//! struct Editor;
+//!
+//! impl Handler for Editor {}
+//!
+//! let (client, connection) = LanguageClient::spawn(&mut Command::new("my-language-server"), Editor)?;
+//! tokio::spawn(connection);
+//!
+//! client.initialize(Default::default()).await?;
+//! let hover = client.request::<lsp::request::HoverRequest>(params).await?;
+//! client.shutdown().await?;
+//! ```
+
+mod handler;
+
+pub use self::handler::Handler;
+pub(crate) use self::handler::RawHandler;
+
+#[cfg(feature = "runtime-agnostic")]
+use async_codec_lite::{FramedRead, FramedWrite};
+#[cfg(feature = "runtime-agnostic")]
+use futures::io::{AsyncRead, AsyncWrite};
+
+#[cfg(feature = "runtime-tokio")]
+use tokio::io::{AsyncRead, AsyncWrite};
+#[cfg(feature = "runtime-tokio")]
+use tokio_util::codec::{FramedRead, FramedWrite};
+
+use crate::{
+    codec::{Decode, LanguageServerCodec},
+    jsonrpc::{
+        ClientRequest,
+        ClientRequests,
+        Error,
+        ErrorCode,
+        Id,
+        Outgoing,
+        Response,
+        Result,
+        ServerRequests,
+        Version,
+    },
+};
+use futures::{
+    channel::mpsc,
+    future::{self, FutureExt},
+    sink::SinkExt,
+    stream::{self, StreamExt},
+};
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+    fmt::{self, Debug, Formatter},
+    future::Future,
+    pin::Pin,
+    sync::{Arc, PoisonError, RwLock},
+    task::{Context, Poll},
+};
+
+/// Maximum number of server-to-client requests and notifications handled concurrently.
+const MAX_CONCURRENT_HANDLERS: usize = 16;
+
+/// Handle for sending requests and notifications to a language server.
+///
+/// Server-to-client requests and notifications are passed to the [`Handler`] given on creation.
+/// The connection itself is driven by the accompanying [`Connection`] future, which must be
+/// polled (e.g., spawned onto the runtime) for any message to be exchanged.
+///
+/// This type provides a very cheap implementation of [`Clone`] so API consumers can cheaply clone
+/// and pass it around as needed.
+///
+/// [`Clone`]: trait@std::clone::Clone
+#[derive(Clone)]
+pub struct LanguageClient {
+    inner: Arc<LanguageClientInner>,
+}
+
+struct LanguageClientInner {
+    sender: crate::queue::Sender,
+    pending_requests: Arc<ClientRequests>,
+    request_ids: crate::client::RequestIds,
+    initialize_result: RwLock<Option<Arc<lsp::InitializeResult>>>,
+}
+
+impl LanguageClient {
+    /// Creates a new `LanguageClient` talking to a server through the given streams, also
+    /// returning the future which drives the connection.
+    ///
+    /// Messages to the server are written to `stdin`, and messages from the server are read from
+    /// `stdout`.
+    pub fn new<I, O, H>(stdin: O, stdout: I, handler: H) -> (Self, Connection)
+    where
+        I: AsyncRead + Send + Unpin + 'static,
+        O: AsyncWrite + Send + Unpin + 'static,
+        H: Handler,
+    {
+        LanguageClient::with_raw_handler(stdin, stdout, Arc::new(handler))
+    }
+
+    pub(crate) fn with_raw_handler<I, O>(stdin: O, stdout: I, handler: Arc<dyn RawHandler>) -> (Self, Connection)
+    where
+        I: AsyncRead + Send + Unpin + 'static,
+        O: AsyncWrite + Send + Unpin + 'static,
+    {
+        let (sender, receiver) = crate::queue::channel(16, crate::OverflowPolicy::Block);
+        let pending_requests = Arc::new(ClientRequests::new());
+        let client = LanguageClient::from_parts(sender, pending_requests.clone());
+        let connection = connect(stdin, stdout, handler, receiver, pending_requests);
+        (client, Connection(Box::pin(connection)))
+    }
+
+    /// Creates a new `LanguageClient` which sends its messages through the given channel and
+    /// expects the responses to be inserted into the given map.
+    ///
+    /// This allows the requests sent to the other end of any connection to be tracked the same way,
+    /// regardless of which side of the protocol it is.
+    pub(crate) fn from_parts(sender: crate::queue::Sender, pending_requests: Arc<ClientRequests>) -> Self {
+        LanguageClient {
+            inner: Arc::new(LanguageClientInner {
+                sender,
+                pending_requests,
+                request_ids: crate::client::RequestIds::new(),
+                initialize_result: RwLock::new(None),
+            }),
+        }
+    }
+
+    /// Spawns a language server process and connects to it over its standard input and output.
+    ///
+    /// The process is killed once the returned [`Connection`] is dropped. The connection resolves
+    /// once the server has closed its standard output and exited.
+    ///
+    /// Requires the `process` feature.
+    #[cfg(feature = "process")]
+    pub fn spawn<H: Handler>(command: &mut tokio::process::Command, handler: H) -> std::io::Result<(Self, Connection)> {
+        LanguageClient::spawn_with_raw_handler(command, Arc::new(handler))
+    }
+
+    #[cfg(feature = "process")]
+    pub(crate) fn spawn_with_raw_handler(
+        command: &mut tokio::process::Command,
+        handler: Arc<dyn RawHandler>,
+    ) -> std::io::Result<(Self, Connection)> {
+        use std::process::Stdio;
+
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+
+        let (stdin, stdout) = match (child.stdin.take(), child.stdout.take()) {
+            (Some(stdin), Some(stdout)) => (stdin, stdout),
+            _ => {
+                let message = "failed to open the standard streams of the language server";
+                return Err(std::io::Error::new(std::io::ErrorKind::BrokenPipe, message));
+            },
+        };
+
+        let (client, connection) = LanguageClient::with_raw_handler(stdin, stdout, handler);
+        let connection = async move {
+            connection.await;
+            match child.wait().await {
+                Ok(status) => log::info!("language server exited with {}", status),
+                Err(err) => log::error!("failed to wait for the language server to exit: {}", err),
+            }
+        };
+
+        Ok((client, Connection(Box::pin(connection))))
+    }
+
+    /// Sends the [`initialize`] request, followed by the [`initialized`] notification once the
+    /// server has answered.
+    ///
+    /// The result is retained and can be obtained later with [`LanguageClient::initialize_result`].
+    ///
+    /// [`initialize`]: https://microsoft.github.io/language-server-protocol/specification#initialize
+    /// [`initialized`]: https://microsoft.github.io/language-server-protocol/specification#initialized
+    pub async fn initialize(&self, params: lsp::InitializeParams) -> Result<lsp::InitializeResult> {
+        let result = self.request::<lsp::request::Initialize>(params).await?;
+        *self
+            .inner
+            .initialize_result
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = Some(Arc::new(result.clone()));
+        self.notify::<lsp::notification::Initialized>(lsp::InitializedParams {})
+            .await?;
+        Ok(result)
+    }
+
+    /// Returns the result of the [`initialize`] request, if the server has been initialized.
+    ///
+    /// [`initialize`]: https://microsoft.github.io/language-server-protocol/specification#initialize
+    pub fn initialize_result(&self) -> Option<Arc<lsp::InitializeResult>> {
+        let result = self
+            .inner
+            .initialize_result
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        result.clone()
+    }
+
+    /// Returns the capabilities announced by the server, if it has been initialized.
+    pub fn server_capabilities(&self) -> Option<lsp::ServerCapabilities> {
+        self.initialize_result().map(|result| result.capabilities.clone())
+    }
+
+    /// Sends the [`shutdown`] request, followed by the [`exit`] notification once the server has
+    /// answered.
+    ///
+    /// [`shutdown`]: https://microsoft.github.io/language-server-protocol/specification#shutdown
+    /// [`exit`]: https://microsoft.github.io/language-server-protocol/specification#exit
+    pub async fn shutdown(&self) -> Result<()> {
+        self.request::<lsp::request::Shutdown>(()).await?;
+        self.notify::<lsp::notification::Exit>(()).await
+    }
+
+    /// Sends a request to the server and waits for the response.
+    ///
+    /// If the returned future is dropped before the server has answered, the request is cancelled
+    /// with a [`$/cancelRequest`] notification. If the connection closes before the server has
+    /// answered, this returns `Err` with [`Error::request_cancelled`].
+    ///
+    /// [`$/cancelRequest`]: https://microsoft.github.io/language-server-protocol/specification#cancelRequest
+    pub async fn request<R>(&self, params: R::Params) -> Result<R::Result>
+    where
+        R: lsp::request::Request,
+    {
+        let id = self.inner.request_ids.next();
+        let message = ClientRequest::request::<R>(id.clone(), params);
+        self.send_request(id, message).await.and_then(|v| {
+            serde_json::from_value(v).map_err(|e| Error {
+                code: ErrorCode::ParseError,
+                message: e.to_string(),
+                data: None,
+            })
+        })
+    }
+
+    /// Sends a request with untyped parameters to the server and waits for the response.
+    pub(crate) async fn raw_request(&self, method: String, params: Option<Value>) -> Result<Value> {
+        let id = self.inner.request_ids.next();
+        let message = ClientRequest::raw_request(id.clone(), method, params.unwrap_or(Value::Null));
+        self.send_request(id, message).await
+    }
+
+    async fn send_request(&self, id: Id, message: ClientRequest) -> Result<Value> {
+        let message = Outgoing::Request(message);
+        let response = self.inner.pending_requests.wait(id.clone())?;
+        if self.inner.sender.send(message).await.is_err() {
+            log::error!("failed to send request, connection is closed");
+            self.inner.pending_requests.0.remove(&id);
+            return Err(connection_closed());
+        }
+
+        let _guard = CancelOnDrop { client: self, id };
+        let (_, result) = response.await.into_parts();
+        result
+    }
+
+    /// Sends a notification to the server.
+    pub async fn notify<N>(&self, params: N::Params) -> Result<()>
+    where
+        N: lsp::notification::Notification,
+    {
+        self.send_notification(ClientRequest::notification::<N>(params)).await
+    }
+
+    /// Queues a notification with untyped parameters for the server.
+    ///
+    /// Unlike [`LanguageClient::notify`], this never waits for room in the outgoing queue, so that
+    /// notifications queued one after another are always sent in the same order.
+    pub(crate) fn raw_notification(&self, method: String, params: Option<Value>) -> Result<()> {
+        let message = ClientRequest::raw_notification(method, params.unwrap_or(Value::Null));
+        if self.inner.sender.push(Outgoing::Request(message)).is_err() {
+            log::error!("failed to send notification, connection is closed");
+            return Err(connection_closed());
+        }
+        Ok(())
+    }
+
+    async fn send_notification(&self, message: ClientRequest) -> Result<()> {
+        let message = Outgoing::Request(message);
+        if self.inner.sender.send(message).await.is_err() {
+            log::error!("failed to send notification, connection is closed");
+            return Err(connection_closed());
+        }
+        Ok(())
+    }
+}
+
+impl Debug for LanguageClient {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct(stringify!(LanguageClient))
+            .field("request_id", &self.inner.request_ids)
+            .field("pending_requests", &self.inner.pending_requests)
+            .field("initialize_result", &self.initialize_result())
+            .finish()
+    }
+}
+
+/// Future driving the connection of a [`LanguageClient`] to its server.
+///
+/// Resolves once the server has closed its output stream or writing to the server has failed.
+/// Requests still awaiting a response at that point fail with [`Error::request_cancelled`].
+#[must_use = "futures do nothing unless polled"]
+pub struct Connection(Pin<Box<dyn Future<Output = ()> + Send>>);
+
+impl Connection {
+    pub(crate) fn new<F: Future<Output = ()> + Send + 'static>(future: F) -> Self {
+        Connection(Box::pin(future))
+    }
+}
+
+impl Future for Connection {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        self.0.as_mut().poll(cx)
+    }
+}
+
+impl Debug for Connection {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct(stringify!(Connection)).finish()
+    }
+}
+
+/// Stops waiting for the response to a request whose future is dropped early, and notifies the
+/// server to cancel it.
+struct CancelOnDrop<'a> {
+    client: &'a LanguageClient,
+    id: Id,
+}
+
+impl Drop for CancelOnDrop<'_> {
+    fn drop(&mut self) {
+        let inner = &self.client.inner;
+        if inner.pending_requests.0.remove(&self.id).is_none() {
+            return;
+        }
+        let params = match crate::client::cancel_params(&self.id) {
+            Some(params) => params,
+            None => return,
+        };
+        let message = Outgoing::Request(ClientRequest::notification::<lsp::notification::Cancel>(params));
+        if let Err(e) = inner.sender.push(message) {
+            log::warn!("failed to cancel request {}: {}", self.id, e);
+        }
+    }
+}
+
+/// A message received from the server.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Incoming {
+    Request(ServerRequest),
+    Response(Response),
+}
+
+impl Decode for Incoming {
+    fn decode(body: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(body)
+    }
+}
+
+/// A server-to-client request or notification.
+#[derive(Debug, Deserialize)]
+struct ServerRequest {
+    #[serde(rename = "jsonrpc")]
+    _jsonrpc: Version,
+    method: String,
+    #[serde(default)]
+    params: Option<Value>,
+    #[serde(default)]
+    id: Option<Id>,
+}
+
+type HandlerFuture = Pin<Box<dyn Future<Output = Option<Outgoing>> + Send>>;
+
+async fn connect<I, O>(
+    stdin: O,
+    stdout: I,
+    handler: Arc<dyn RawHandler>,
+    receiver: crate::queue::Receiver,
+    pending_requests: Arc<ClientRequests>,
+) where
+    I: AsyncRead + Send + Unpin + 'static,
+    O: AsyncWrite + Send + Unpin + 'static,
+{
+    let (mut handlers, handled) = mpsc::channel::<HandlerFuture>(MAX_CONCURRENT_HANDLERS);
+    let pending_handlers = ServerRequests::new();
+
+    let mut framed_stdout = FramedRead::new(stdout, LanguageServerCodec::<Incoming>::default());
+    let framed_stdin = FramedWrite::new(stdin, LanguageServerCodec::default());
+    let responses = handled
+        .buffer_unordered(MAX_CONCURRENT_HANDLERS)
+        .filter_map(future::ready);
+
+    let printer = stream::select(receiver, responses)
+        .map(Ok)
+        .forward(framed_stdin.sink_map_err(|e| log::error!("failed to encode message: {}", e)))
+        .map(|_| ());
+
+    let reader = async {
+        while let Some(message) = framed_stdout.next().await {
+            let future: HandlerFuture = match message {
+                Ok(Incoming::Response(response)) => {
+                    pending_requests.insert(response);
+                    continue;
+                },
+                Ok(Incoming::Request(ServerRequest {
+                    method,
+                    params,
+                    id: Some(id),
+                    ..
+                })) => {
+                    let handled = handler.clone().request(method, params);
+                    let response = pending_handlers.execute(id, handled);
+                    Box::pin(response.map(|response| Some(Outgoing::Response(response))))
+                },
+                Ok(Incoming::Request(ServerRequest { method, params, .. })) => {
+                    if method == <lsp::notification::Cancel as lsp::notification::Notification>::METHOD {
+                        cancel(&pending_handlers, params);
+                        continue;
+                    }
+                    let handled = handler.clone().notification(method, params);
+                    Box::pin(handled.map(|()| None))
+                },
+                Err(err) => {
+                    log::error!("failed to decode message: {}", err);
+                    continue;
+                },
+            };
+
+            if handlers.send(future).await.is_err() {
+                break;
+            }
+        }
+        log::info!("server output stream closed, stopping");
+    };
+
+    future::select(Box::pin(reader), printer).await;
+
+    // Let every request still waiting for a response fail.
+    pending_requests.0.clear();
+    pending_handlers.cancel_all();
+}
+
+/// Cancels the server-to-client request referred to by a `$/cancelRequest` notification.
+fn cancel(pending: &ServerRequests, params: Option<Value>) {
+    let params = serde_json::from_value::<lsp::CancelParams>(params.unwrap_or(Value::Null));
+    match params.map(|params| params.id) {
+        Ok(lsp::NumberOrString::Number(id)) if id >= 0 => pending.cancel(&Id::Number(id as u64)),
+        Ok(lsp::NumberOrString::String(id)) => pending.cancel(&Id::String(id)),
+        Ok(lsp::NumberOrString::Number(id)) => log::warn!("cannot cancel request with negative ID {}", id),
+        Err(err) => log::error!("invalid parameters for $/cancelRequest notification: {}", err),
+    }
+}
+
+/// Error returned for every message sent once the connection to the server is closed.
+fn connection_closed() -> Error {
+    Error {
+        code: ErrorCode::InternalError,
+        message: "Connection to the language server is closed".to_string(),
+        data: None,
+    }
+}
+
+#[cfg(all(test, feature = "runtime-tokio"))]
+mod tests {
+    use super::*;
+    use crate::{LanguageServer, LspService, ServeOutcome, Server};
+    use async_trait::async_trait;
+    use serde_json::json;
+
+    #[derive(Debug)]
+    struct Mock {
+        client: crate::Client,
+    }
+
+    #[async_trait]
+    impl LanguageServer for Mock {
+        async fn initialize(&self, _: lsp::InitializeParams) -> Result<lsp::InitializeResult> {
+            let mut result = lsp::InitializeResult::default();
+            result.capabilities.hover_provider = Some(lsp::HoverProviderCapability::Simple(true));
+            Ok(result)
+        }
+
+        async fn initialized(&self, _: lsp::InitializedParams) {
+            let folders = self.client.workspace_folders().await.unwrap().unwrap_or_default();
+            let names: Vec<_> = folders.into_iter().map(|folder| folder.name).collect();
+            self.client.log_message(lsp::MessageType::Info, names.join(", ")).await;
+        }
+
+        async fn shutdown(&self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn hover(&self, _: lsp::HoverParams) -> Result<Option<lsp::Hover>> {
+            Ok(Some(lsp::Hover {
+                contents: lsp::HoverContents::Scalar(lsp::MarkedString::String("hello".into())),
+                range: None,
+            }))
+        }
+    }
+
+    struct Editor {
+        log: mpsc::UnboundedSender<String>,
+    }
+
+    #[async_trait]
+    impl Handler for Editor {
+        async fn workspace_folders(&self) -> Result<Option<Vec<lsp::WorkspaceFolder>>> {
+            Ok(Some(vec![lsp::WorkspaceFolder {
+                uri: lsp::Url::parse("inmemory::///workspace").unwrap(),
+                name: "workspace".into(),
+            }]))
+        }
+
+        async fn log_message(&self, params: lsp::LogMessageParams) {
+            self.log.unbounded_send(params.message).unwrap();
+        }
+    }
+
+    fn initialize_params() -> lsp::InitializeParams {
+        serde_json::from_value(json!({
+            "capabilities": { "workspace": { "workspaceFolders": true } },
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn drives_server() {
+        let (server_stdin, client_stdin) = tokio::io::duplex(1024);
+        let (client_stdout, server_stdout) = tokio::io::duplex(1024);
+
+        let (service, messages) = LspService::new(|client| Mock { client });
+        let server = Server::new(server_stdin, server_stdout).interleave(messages);
+        let server = tokio::spawn(server.serve(service));
+
+        let (log, mut logged) = mpsc::unbounded();
+        let (client, connection) = LanguageClient::new(client_stdin, client_stdout, Editor { log });
+        let connection = tokio::spawn(connection);
+
+        let result = client.initialize(initialize_params()).await.unwrap();
+        let hover_provider = Some(lsp::HoverProviderCapability::Simple(true));
+        assert_eq!(result.capabilities.hover_provider, hover_provider);
+        assert_eq!(client.server_capabilities().unwrap().hover_provider, hover_provider);
+        assert_eq!(logged.next().await.as_deref(), Some("workspace"));
+
+        let params = lsp::HoverParams {
+            text_document_position_params: lsp::TextDocumentPositionParams {
+                text_document: lsp::TextDocumentIdentifier {
+                    uri: lsp::Url::parse("inmemory::///test").unwrap(),
+                },
+                position: Default::default(),
+            },
+            work_done_progress_params: Default::default(),
+        };
+        let hover = client.request::<lsp::request::HoverRequest>(params).await.unwrap();
+        let contents = lsp::HoverContents::Scalar(lsp::MarkedString::String("hello".into()));
+        assert_eq!(hover.map(|hover| hover.contents), Some(contents));
+
+        let error = client
+            .request::<lsp::request::Formatting>(
+                serde_json::from_value(json!({
+                    "textDocument": { "uri": "inmemory::///test" },
+                    "options": { "tabSize": 4, "insertSpaces": true },
+                }))
+                .unwrap(),
+            )
+            .await;
+        assert_eq!(error.unwrap_err().code, ErrorCode::MethodNotFound);
+
+        client.shutdown().await.unwrap();
+        assert!(matches!(server.await.unwrap(), ServeOutcome::Exited));
+        connection.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn fails_pending_requests_when_closed() {
+        let (_, client_stdin) = tokio::io::duplex(1024);
+        let (client_stdout, server_stdout) = tokio::io::duplex(1024);
+
+        let (log, _) = mpsc::unbounded();
+        let (client, connection) = LanguageClient::new(client_stdin, client_stdout, Editor { log });
+        let connection = tokio::spawn(connection);
+
+        let response = tokio::spawn({
+            let client = client.clone();
+            async move { client.request::<lsp::request::Shutdown>(()).await }
+        });
+        tokio::task::yield_now().await;
+        drop(server_stdout);
+
+        connection.await.unwrap();
+        assert_eq!(response.await.unwrap(), Err(Error::request_cancelled()));
+        let notified = client.notify::<lsp::notification::Exit>(()).await;
+        assert_eq!(notified, Err(connection_closed()));
+    }
+
+    #[tokio::test]
+    async fn cancels_dropped_requests() {
+        use futures::FutureExt;
+
+        let (sender, mut receiver) = crate::queue::channel(4, crate::OverflowPolicy::Block);
+        let client = LanguageClient::from_parts(sender, Arc::new(ClientRequests::new()));
+
+        for id in 0 .. 2 {
+            let mut request = client.request::<lsp::request::Shutdown>(()).boxed();
+            assert!((&mut request).now_or_never().is_none());
+            drop(request);
+
+            let sent = ClientRequest::request::<lsp::request::Shutdown>(Id::Number(id), ());
+            assert_eq!(receiver.next().await, Some(Outgoing::Request(sent)));
+            let params = lsp::CancelParams {
+                id: lsp::NumberOrString::Number(id as i32),
+            };
+            let cancel = ClientRequest::notification::<lsp::notification::Cancel>(params);
+            assert_eq!(receiver.next().await, Some(Outgoing::Request(cancel)));
+        }
+    }
+
+    #[cfg(feature = "process")]
+    #[tokio::test]
+    async fn spawn_fails() {
+        let mut command = tokio::process::Command::new("/nonexistent/language-server");
+        let (log, _) = mpsc::unbounded();
+        assert!(LanguageClient::spawn(&mut command, Editor { log }).is_err());
+    }
+}
@@ -0,0 +1,263 @@
+//! Handling of server-to-client requests and notifications.
+
+use crate::jsonrpc::{Error, ErrorCode, Result};
+use futures::future::{BoxFuture, FutureExt};
+use lsp::{notification::Notification, request::Request};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+
+/// Trait implemented by the client side of a [`LanguageClient`] connection.
+///
+/// Every method has a default implementation, so that implementors only need to handle the
+/// messages they are interested in. Requests without a dedicated method are passed to
+/// [`Handler::request_else`], notifications to [`Handler::notification_else`].
+///
+/// [`LanguageClient`]: super::LanguageClient
+#[crate::async_trait]
+pub trait Handler: Send + Sync + 'static {
+    /// The [`workspace/configuration`] request asks for configuration settings of the client.
+    ///
+    /// Defaults to answering `null` for every requested item.
+    ///
+    /// [`workspace/configuration`]: https://microsoft.github.io/language-server-protocol/specification#workspace_configuration
+    async fn configuration(&self, params: lsp::ConfigurationParams) -> Result<Vec<Value>> {
+        Ok(vec![Value::Null; params.items.len()])
+    }
+
+    /// The [`workspace/workspaceFolders`] request asks for the current workspace folders.
+    ///
+    /// Defaults to answering that no workspace is open.
+    ///
+    /// [`workspace/workspaceFolders`]: https://microsoft.github.io/language-server-protocol/specification#workspace_workspaceFolders
+    async fn workspace_folders(&self) -> Result<Option<Vec<lsp::WorkspaceFolder>>> {
+        Ok(None)
+    }
+
+    /// The [`workspace/applyEdit`] request asks the client to modify resources.
+    ///
+    /// [`workspace/applyEdit`]: https://microsoft.github.io/language-server-protocol/specification#workspace_applyEdit
+    async fn apply_edit(&self, _params: lsp::ApplyWorkspaceEditParams) -> Result<lsp::ApplyWorkspaceEditResponse> {
+        log::error!("Got a workspace/applyEdit request, but it is not implemented");
+        Err(Error::method_not_found())
+    }
+
+    /// The [`window/showMessageRequest`] request asks the client to display a message and let
+    /// the user pick one of the given actions.
+    ///
+    /// Defaults to answering that no action was picked.
+    ///
+    /// [`window/showMessageRequest`]: https://microsoft.github.io/language-server-protocol/specification#window_showMessageRequest
+    async fn show_message_request(
+        &self,
+        _params: lsp::ShowMessageRequestParams,
+    ) -> Result<Option<lsp::MessageActionItem>> {
+        Ok(None)
+    }
+
+    /// The [`window/showDocument`] request asks the client to display a resource.
+    ///
+    /// [`window/showDocument`]: https://microsoft.github.io/language-server-protocol/specification#window_showDocument
+    async fn show_document(&self, _params: lsp::ShowDocumentParams) -> Result<lsp::ShowDocumentResult> {
+        log::error!("Got a window/showDocument request, but it is not implemented");
+        Err(Error::method_not_found())
+    }
+
+    /// The [`window/workDoneProgress/create`] request asks the client to create a work done
+    /// progress.
+    ///
+    /// [`window/workDoneProgress/create`]: https://microsoft.github.io/language-server-protocol/specification#window_workDoneProgress_create
+    async fn work_done_progress_create(&self, _params: lsp::WorkDoneProgressCreateParams) -> Result<()> {
+        Ok(())
+    }
+
+    /// The [`client/registerCapability`] request registers a capability dynamically.
+    ///
+    /// [`client/registerCapability`]: https://microsoft.github.io/language-server-protocol/specification#client_registerCapability
+    async fn register_capability(&self, _params: lsp::RegistrationParams) -> Result<()> {
+        Ok(())
+    }
+
+    /// The [`client/unregisterCapability`] request unregisters a previously registered capability.
+    ///
+    /// [`client/unregisterCapability`]: https://microsoft.github.io/language-server-protocol/specification#client_unregisterCapability
+    async fn unregister_capability(&self, _params: lsp::UnregistrationParams) -> Result<()> {
+        Ok(())
+    }
+
+    /// The [`workspace/codeLens/refresh`] request asks the client to refresh all code lenses.
+    ///
+    /// [`workspace/codeLens/refresh`]: https://microsoft.github.io/language-server-protocol/specification#codeLens_refresh
+    async fn code_lens_refresh(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// The [`workspace/semanticTokens/refresh`] request asks the client to refresh all semantic
+    /// tokens.
+    ///
+    /// [`workspace/semanticTokens/refresh`]: https://microsoft.github.io/language-server-protocol/specification#semanticTokens_refreshRequest
+    async fn semantic_tokens_refresh(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// The [`textDocument/publishDiagnostics`] notification reports the diagnostics of a document.
+    ///
+    /// [`textDocument/publishDiagnostics`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_publishDiagnostics
+    async fn publish_diagnostics(&self, _params: lsp::PublishDiagnosticsParams) {
+    }
+
+    /// The [`window/logMessage`] notification asks the client to log a message.
+    ///
+    /// Defaults to logging the message through the [`log`](https://docs.rs/log) crate.
+    ///
+    /// [`window/logMessage`]: https://microsoft.github.io/language-server-protocol/specification#window_logMessage
+    async fn log_message(&self, params: lsp::LogMessageParams) {
+        let level = match params.typ {
+            lsp::MessageType::Error => log::Level::Error,
+            lsp::MessageType::Warning => log::Level::Warn,
+            lsp::MessageType::Info => log::Level::Info,
+            lsp::MessageType::Log => log::Level::Debug,
+        };
+        log::log!(level, "server: {}", params.message);
+    }
+
+    /// The [`window/showMessage`] notification asks the client to display a message.
+    ///
+    /// [`window/showMessage`]: https://microsoft.github.io/language-server-protocol/specification#window_showMessage
+    async fn show_message(&self, _params: lsp::ShowMessageParams) {
+    }
+
+    /// The [`telemetry/event`] notification asks the client to log a telemetry event.
+    ///
+    /// [`telemetry/event`]: https://microsoft.github.io/language-server-protocol/specification#telemetry_event
+    async fn telemetry_event(&self, _params: Value) {
+    }
+
+    /// The [`$/progress`] notification reports progress of a work done or partial result token.
+    ///
+    /// [`$/progress`]: https://microsoft.github.io/language-server-protocol/specification#progress
+    async fn progress(&self, _params: lsp::ProgressParams) {
+    }
+
+    /// This handler can be used to respond to all requests that are not handled by built in request
+    /// handlers.
+    async fn request_else(&self, method: &str, _params: Option<Value>) -> Result<Value> {
+        log::error!("Got a {} request, but Handler::request_else is not implemented", method);
+        Err(Error::method_not_found())
+    }
+
+    /// This handler can be used to process all notifications that are not handled by built in
+    /// notification handlers.
+    async fn notification_else(&self, method: &str, _params: Option<Value>) {
+        if !method.starts_with("$/") {
+            log::warn!("Got a {} notification, but it is not handled", method);
+        }
+    }
+}
+
+/// Untyped handling of server-to-client messages.
+///
+/// Implemented for every [`Handler`] by routing each message to the corresponding method, and
+/// within the crate by handlers which pass messages on as they are (e.g., the proxy).
+pub(crate) trait RawHandler: Send + Sync + 'static {
+    /// Handles a server-to-client request, returning its result.
+    fn request(self: Arc<Self>, method: String, params: Option<Value>) -> BoxFuture<'static, Result<Value>>;
+
+    /// Handles a server-to-client notification.
+    fn notification(self: Arc<Self>, method: String, params: Option<Value>) -> BoxFuture<'static, ()>;
+}
+
+impl<H: Handler> RawHandler for H {
+    fn request(self: Arc<Self>, method: String, params: Option<Value>) -> BoxFuture<'static, Result<Value>> {
+        handle_request(self, method, params).boxed()
+    }
+
+    fn notification(self: Arc<Self>, method: String, params: Option<Value>) -> BoxFuture<'static, ()> {
+        handle_notification(self, method, params).boxed()
+    }
+}
+
+/// Routes a server-to-client request to the corresponding method of the handler.
+async fn handle_request(handler: Arc<dyn Handler>, method: String, params: Option<Value>) -> Result<Value> {
+    use lsp::request::*;
+
+    match method.as_str() {
+        WorkspaceConfiguration::METHOD => {
+            let params = parse::<WorkspaceConfiguration>(params)?;
+            serialize(handler.configuration(params).await)
+        },
+        WorkspaceFoldersRequest::METHOD => serialize(handler.workspace_folders().await),
+        ApplyWorkspaceEdit::METHOD => {
+            let params = parse::<ApplyWorkspaceEdit>(params)?;
+            serialize(handler.apply_edit(params).await)
+        },
+        ShowMessageRequest::METHOD => {
+            let params = parse::<ShowMessageRequest>(params)?;
+            serialize(handler.show_message_request(params).await)
+        },
+        ShowDocument::METHOD => {
+            let params = parse::<ShowDocument>(params)?;
+            serialize(handler.show_document(params).await)
+        },
+        WorkDoneProgressCreate::METHOD => {
+            let params = parse::<WorkDoneProgressCreate>(params)?;
+            serialize(handler.work_done_progress_create(params).await)
+        },
+        RegisterCapability::METHOD => {
+            let params = parse::<RegisterCapability>(params)?;
+            serialize(handler.register_capability(params).await)
+        },
+        UnregisterCapability::METHOD => {
+            let params = parse::<UnregisterCapability>(params)?;
+            serialize(handler.unregister_capability(params).await)
+        },
+        CodeLensRefresh::METHOD => serialize(handler.code_lens_refresh().await),
+        SemanticTokensRefesh::METHOD => serialize(handler.semantic_tokens_refresh().await),
+        _ => handler.request_else(&method, params).await,
+    }
+}
+
+/// Routes a server-to-client notification to the corresponding method of the handler.
+async fn handle_notification(handler: Arc<dyn Handler>, method: String, params: Option<Value>) {
+    use lsp::notification::*;
+
+    let result = match method.as_str() {
+        PublishDiagnostics::METHOD => {
+            parse_notification::<PublishDiagnostics>(params).map(|params| handler.publish_diagnostics(params))
+        },
+        LogMessage::METHOD => parse_notification::<LogMessage>(params).map(|params| handler.log_message(params)),
+        ShowMessage::METHOD => parse_notification::<ShowMessage>(params).map(|params| handler.show_message(params)),
+        TelemetryEvent::METHOD => {
+            parse_notification::<TelemetryEvent>(params).map(|params| handler.telemetry_event(params))
+        },
+        Progress::METHOD => parse_notification::<Progress>(params).map(|params| handler.progress(params)),
+        _ => Ok(handler.notification_else(&method, params)),
+    };
+
+    match result {
+        Ok(handled) => handled.await,
+        Err(error) => log::error!("invalid parameters for {} notification: {}", method, error),
+    }
+}
+
+fn parse<R: Request>(params: Option<Value>) -> Result<R::Params> {
+    deserialize(params)
+}
+
+fn parse_notification<N: Notification>(params: Option<Value>) -> Result<N::Params> {
+    deserialize(params)
+}
+
+fn deserialize<T: DeserializeOwned>(params: Option<Value>) -> Result<T> {
+    serde_json::from_value(params.unwrap_or(Value::Null)).map_err(|e| Error::invalid_params(e.to_string()))
+}
+
+fn serialize<T: Serialize>(result: Result<T>) -> Result<Value> {
+    result.and_then(|value| {
+        serde_json::to_value(value).map_err(|e| Error {
+            code: ErrorCode::InternalError,
+            message: e.to_string(),
+            data: None,
+        })
+    })
+}
@@ -0,0 +1,104 @@
+//! Middleware layers for [`LspService`](crate::LspService).
+//!
+//! Each layer wraps any [`Service`] which takes [`Incoming`] messages and produces optional
+//! [`Outgoing`] responses, so layers can be stacked on top of each other (e.g., with
+//! `tower::ServiceBuilder`) and the resulting service can be passed to
+//! [`Server::serve`](crate::Server::serve).
+//!
+//! Client responses and the notifications of the protocol lifecycle pass through every layer
+//! unchanged, so a layered service shuts down just like the bare `LspService`.
+//!
+//! ```rust,ignore
+//! use lspower::layers::{ConcurrencyLimitLayer, LoggingLayer, TimeoutLayer};
+//! use std::time::Duration;
+//!
+//! let (service, messages) = LspService::new(|client| Backend { client });
+//! let service = tower::ServiceBuilder::new()
+//!     .layer(LoggingLayer::default())
+//!     .layer(TimeoutLayer::new(Duration::from_secs(10)).method("textDocument/formatting", Duration::from_secs(30)))
+//!     .layer(ConcurrencyLimitLayer::new(4))
+//!     .service(service);
+//! Server::new(stdin, stdout).interleave(messages).serve(service).await;
+//! ```
+
+mod concurrency;
+mod filter;
+mod latency;
+mod logging;
+mod timeout;
+
+pub use self::{
+    concurrency::{ConcurrencyLimit, ConcurrencyLimitLayer},
+    filter::{Filter, FilterLayer},
+    latency::{Latency, LatencyLayer, LatencyMetrics},
+    logging::{Logging, LoggingLayer},
+    timeout::{Timeout, TimeoutLayer},
+};
+pub use tower_layer::Layer;
+
+use crate::jsonrpc::{Id, Incoming, Outgoing};
+use std::{future::Future, pin::Pin};
+
+type ResponseFuture<E> = Pin<Box<dyn Future<Output = Result<Option<Outgoing>, E>> + Send>>;
+
+/// Methods which are never filtered, delayed or timed out, since the protocol depends on them.
+const LIFECYCLE_METHODS: &[&str] = &["initialize", "initialized", "shutdown", "exit", "$/cancelRequest"];
+
+/// Returns the ID of a request awaiting a response, or `None` for notifications and responses.
+fn request_id(message: &Incoming) -> Option<&Id> {
+    match message {
+        Incoming::Request(request) => request.id(),
+        Incoming::Response(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jsonrpc::Response;
+    use std::{
+        task::{Context, Poll},
+        time::Duration,
+    };
+    use tower_service::Service;
+
+    /// Answers every request with `null` after the given delay.
+    #[derive(Clone, Debug, Default)]
+    pub(super) struct MockService(pub(super) Duration);
+
+    impl Service<Incoming> for MockService {
+        type Error = String;
+        type Future = ResponseFuture<String>;
+        type Response = Option<Outgoing>;
+
+        fn poll_ready(&mut self, _: &mut Context) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, message: Incoming) -> Self::Future {
+            let id = request_id(&message).cloned();
+            let delay = self.0;
+            Box::pin(async move {
+                tokio::time::sleep(delay).await;
+                Ok(id.map(|id| Outgoing::Response(Response::ok(id, serde_json::Value::Null))))
+            })
+        }
+    }
+
+    pub(super) fn request(method: &str, id: u64) -> Incoming {
+        let raw = serde_json::json!({ "jsonrpc": "2.0", "method": method, "params": {}, "id": id });
+        serde_json::from_value(raw).unwrap()
+    }
+
+    pub(super) fn notification(method: &str) -> Incoming {
+        let raw = serde_json::json!({ "jsonrpc": "2.0", "method": method, "params": {} });
+        serde_json::from_value(raw).unwrap()
+    }
+
+    pub(super) fn ok(id: u64) -> Option<Outgoing> {
+        Some(Outgoing::Response(Response::ok(
+            Id::Number(id),
+            serde_json::Value::Null,
+        )))
+    }
+}
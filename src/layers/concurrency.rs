@@ -0,0 +1,104 @@
+//! Limits on the number of concurrently handled requests.
+
+use super::{request_id, ResponseFuture, LIFECYCLE_METHODS};
+use crate::jsonrpc::{Incoming, Outgoing};
+use async_lock::Semaphore;
+use std::{
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// Layer which limits the number of requests handled at the same time.
+///
+/// Requests beyond the limit are queued until an earlier request has been answered. Unlike
+/// `tower::limit::ConcurrencyLimit`, this never stops the service from accepting messages, so
+/// notifications (including `$/cancelRequest`), client responses and the requests of the protocol
+/// lifecycle are not held up by pending requests.
+#[derive(Clone, Copy, Debug)]
+pub struct ConcurrencyLimitLayer {
+    max: usize,
+}
+
+impl ConcurrencyLimitLayer {
+    /// Creates a new `ConcurrencyLimitLayer` handling at most `max` requests at the same time.
+    pub fn new(max: usize) -> Self {
+        ConcurrencyLimitLayer { max }
+    }
+}
+
+impl<S> Layer<S> for ConcurrencyLimitLayer {
+    type Service = ConcurrencyLimit<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ConcurrencyLimit {
+            inner,
+            semaphore: Arc::new(Semaphore::new(self.max)),
+        }
+    }
+}
+
+/// Service which limits the number of requests handled at the same time.
+///
+/// See [`ConcurrencyLimitLayer`] for details.
+#[derive(Clone, Debug)]
+pub struct ConcurrencyLimit<S> {
+    inner: S,
+    semaphore: Arc<Semaphore>,
+}
+
+impl<S> Service<Incoming> for ConcurrencyLimit<S>
+where
+    S: Service<Incoming, Response = Option<Outgoing>>,
+    S::Future: Send + 'static,
+{
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Error>;
+    type Response = Option<Outgoing>;
+
+    fn poll_ready(&mut self, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, message: Incoming) -> Self::Future {
+        let lifecycle = matches!(message.method(), Some(method) if LIFECYCLE_METHODS.contains(&method));
+        if request_id(&message).is_some() && !lifecycle {
+            let permit = self.semaphore.acquire_arc();
+            let response = self.inner.call(message);
+            Box::pin(async move {
+                let _permit = permit.await;
+                response.await
+            })
+        } else {
+            Box::pin(self.inner.call(message))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::tests::{ok, request, MockService};
+    use std::time::{Duration, Instant};
+    use tower_test::mock::Spawn;
+
+    #[tokio::test]
+    async fn limits_requests() {
+        let delay = Duration::from_millis(20);
+        let mut service = Spawn::new(ConcurrencyLimitLayer::new(1).layer(MockService(delay)));
+
+        let start = Instant::now();
+        let first = service.call(request("textDocument/hover", 1));
+        let second = service.call(request("textDocument/hover", 2));
+        assert_eq!(futures::join!(first, second), (Ok(ok(1)), Ok(ok(2))));
+        assert!(start.elapsed() >= delay * 2);
+
+        let mut service = Spawn::new(ConcurrencyLimitLayer::new(1).layer(MockService(delay)));
+        let start = Instant::now();
+        let first = service.call(request("textDocument/hover", 1));
+        let shutdown = service.call(request("shutdown", 2));
+        assert_eq!(futures::join!(first, shutdown), (Ok(ok(1)), Ok(ok(2))));
+        assert!(start.elapsed() < delay * 2);
+    }
+}
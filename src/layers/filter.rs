@@ -0,0 +1,140 @@
+//! Allow and deny lists of methods.
+
+use super::{ResponseFuture, LIFECYCLE_METHODS};
+use crate::jsonrpc::{Error, Incoming, Outgoing, Response};
+use futures::future;
+use std::{
+    collections::HashSet,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// Layer which only passes on messages of the allowed methods to the inner service.
+///
+/// Filtered requests are answered with a "method not found" error (`-32601`), while filtered
+/// notifications are dropped. The methods of the protocol lifecycle (`initialize`, `initialized`,
+/// `shutdown`, `exit` and `$/cancelRequest`) and client responses are never filtered.
+#[derive(Clone, Debug)]
+pub struct FilterLayer {
+    methods: Arc<HashSet<String>>,
+    allow: bool,
+}
+
+impl FilterLayer {
+    /// Creates a new `FilterLayer` passing on only the given methods.
+    pub fn allow<I, M>(methods: I) -> Self
+    where
+        I: IntoIterator<Item = M>,
+        M: Into<String>,
+    {
+        FilterLayer {
+            methods: Arc::new(methods.into_iter().map(Into::into).collect()),
+            allow: true,
+        }
+    }
+
+    /// Creates a new `FilterLayer` passing on all but the given methods.
+    pub fn deny<I, M>(methods: I) -> Self
+    where
+        I: IntoIterator<Item = M>,
+        M: Into<String>,
+    {
+        FilterLayer {
+            methods: Arc::new(methods.into_iter().map(Into::into).collect()),
+            allow: false,
+        }
+    }
+
+    fn is_allowed(&self, method: &str) -> bool {
+        LIFECYCLE_METHODS.contains(&method) || self.methods.contains(method) == self.allow
+    }
+}
+
+impl<S> Layer<S> for FilterLayer {
+    type Service = Filter<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Filter {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// Service which only passes on messages of the allowed methods to the inner service.
+///
+/// See [`FilterLayer`] for details.
+#[derive(Clone, Debug)]
+pub struct Filter<S> {
+    inner: S,
+    layer: FilterLayer,
+}
+
+impl<S> Service<Incoming> for Filter<S>
+where
+    S: Service<Incoming, Response = Option<Outgoing>>,
+    S::Future: Send + 'static,
+    S::Error: Send + 'static,
+{
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Error>;
+    type Response = Option<Outgoing>;
+
+    fn poll_ready(&mut self, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, message: Incoming) -> Self::Future {
+        match message {
+            Incoming::Request(ref request) if !self.layer.is_allowed(request.method()) => {
+                log::debug!("method {:?} is filtered", request.method());
+                let response = request
+                    .id()
+                    .cloned()
+                    .map(|id| Outgoing::Response(Response::error(Some(id), Error::method_not_found())));
+                Box::pin(future::ok(response))
+            },
+            message => Box::pin(self.inner.call(message)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        jsonrpc::Id,
+        layers::tests::{notification, ok, request, MockService},
+    };
+    use tower_test::mock::Spawn;
+
+    fn method_not_found(id: u64) -> Option<Outgoing> {
+        let response = Response::error(Some(Id::Number(id)), Error::method_not_found());
+        Some(Outgoing::Response(response))
+    }
+
+    #[tokio::test]
+    async fn allow() {
+        let layer = FilterLayer::allow(vec!["textDocument/hover"]);
+        let mut service = Spawn::new(layer.layer(MockService::default()));
+
+        assert_eq!(service.call(request("textDocument/hover", 1)).await, Ok(ok(1)));
+        assert_eq!(service.call(request("shutdown", 2)).await, Ok(ok(2)));
+        let response = service.call(request("textDocument/formatting", 3)).await;
+        assert_eq!(response, Ok(method_not_found(3)));
+        assert_eq!(service.call(notification("textDocument/didOpen")).await, Ok(None));
+    }
+
+    #[tokio::test]
+    async fn deny() {
+        let layer = FilterLayer::deny(vec!["textDocument/formatting", "shutdown"]);
+        let mut service = Spawn::new(layer.layer(MockService::default()));
+
+        assert_eq!(service.call(request("textDocument/hover", 1)).await, Ok(ok(1)));
+        assert_eq!(service.call(request("shutdown", 2)).await, Ok(ok(2)));
+        let response = service.call(request("textDocument/formatting", 3)).await;
+        assert_eq!(response, Ok(method_not_found(3)));
+    }
+}
@@ -0,0 +1,153 @@
+//! Per-method latency metrics.
+
+use super::ResponseFuture;
+use crate::{
+    jsonrpc::{Incoming, Outgoing},
+    metrics::MethodMetrics,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// Handle to the latency metrics recorded by a [`LatencyLayer`].
+///
+/// This type provides a very cheap implementation of [`Clone`], sharing the metrics between clones.
+///
+/// [`Clone`]: trait@std::clone::Clone
+#[derive(Clone, Debug, Default)]
+pub struct LatencyMetrics {
+    methods: Arc<Mutex<HashMap<String, MethodMetrics>>>,
+}
+
+impl LatencyMetrics {
+    /// Returns the statistics of the given method, if it has been handled at least once.
+    pub fn get(&self, method: &str) -> Option<MethodMetrics> {
+        let methods = self.methods.lock().unwrap_or_else(PoisonError::into_inner);
+        methods.get(method).cloned()
+    }
+
+    /// Returns the statistics of all methods handled so far.
+    pub fn snapshot(&self) -> HashMap<String, MethodMetrics> {
+        self.methods.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    /// Discards all statistics recorded so far.
+    pub fn reset(&self) {
+        self.methods.lock().unwrap_or_else(PoisonError::into_inner).clear();
+    }
+
+    fn record_method(&self, method: String, elapsed: Duration, error: bool) {
+        let mut methods = self.methods.lock().unwrap_or_else(PoisonError::into_inner);
+        methods.entry(method).or_default().record(elapsed, error);
+    }
+}
+
+/// Layer which records how long each method takes to handle.
+///
+/// Client responses are not recorded.
+#[derive(Clone, Debug, Default)]
+pub struct LatencyLayer {
+    metrics: LatencyMetrics,
+}
+
+impl LatencyLayer {
+    /// Creates a new `LatencyLayer`.
+    pub fn new() -> Self {
+        LatencyLayer::default()
+    }
+
+    /// Returns a handle to the metrics recorded by the services this layer creates.
+    pub fn metrics(&self) -> LatencyMetrics {
+        self.metrics.clone()
+    }
+}
+
+impl<S> Layer<S> for LatencyLayer {
+    type Service = Latency<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Latency {
+            inner,
+            metrics: self.metrics.clone(),
+        }
+    }
+}
+
+/// Service which records how long each method takes to handle.
+///
+/// See [`LatencyLayer`] for details.
+#[derive(Clone, Debug)]
+pub struct Latency<S> {
+    inner: S,
+    metrics: LatencyMetrics,
+}
+
+impl<S> Service<Incoming> for Latency<S>
+where
+    S: Service<Incoming, Response = Option<Outgoing>>,
+    S::Future: Send + 'static,
+{
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Error>;
+    type Response = Option<Outgoing>;
+
+    fn poll_ready(&mut self, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, message: Incoming) -> Self::Future {
+        let method = match message.method() {
+            Some(method) => method.to_string(),
+            None => return Box::pin(self.inner.call(message)),
+        };
+
+        let metrics = self.metrics.clone();
+        let start = Instant::now();
+        let response = self.inner.call(message);
+
+        Box::pin(async move {
+            let response = response.await;
+            let error = match response {
+                Ok(Some(Outgoing::Response(ref response))) => response.is_error(),
+                Ok(_) => false,
+                Err(_) => true,
+            };
+            metrics.record_method(method, start.elapsed(), error);
+            response
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::tests::{notification, request, MockService};
+    use tower_test::mock::Spawn;
+
+    #[tokio::test]
+    async fn records_latency() {
+        let layer = LatencyLayer::new();
+        let metrics = layer.metrics();
+        let mut service = Spawn::new(layer.layer(MockService(Duration::from_millis(10))));
+
+        service.call(request("textDocument/hover", 1)).await.unwrap();
+        service.call(request("textDocument/hover", 2)).await.unwrap();
+        service.call(notification("textDocument/didOpen")).await.unwrap();
+
+        let hover = metrics.get("textDocument/hover").unwrap();
+        assert_eq!(hover.count, 2);
+        assert_eq!(hover.errors, 0);
+        assert!(hover.max >= Duration::from_millis(10));
+        assert!(hover.mean() <= hover.max);
+        assert_eq!(hover.histogram.iter().map(|bucket| bucket.count).sum::<u64>(), 2);
+        assert_eq!(metrics.snapshot().len(), 2);
+
+        metrics.reset();
+        assert!(metrics.get("textDocument/hover").is_none());
+    }
+}
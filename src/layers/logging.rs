@@ -0,0 +1,108 @@
+//! Logging of incoming messages.
+
+use super::ResponseFuture;
+use crate::jsonrpc::{Incoming, Outgoing};
+use std::{
+    task::{Context, Poll},
+    time::Instant,
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// Layer which logs every incoming message and how long it took to handle it.
+///
+/// Messages are logged through the [`log`](https://docs.rs/log) crate at the given level, which
+/// defaults to [`log::Level::Debug`]. Failing responses are logged as warnings.
+#[derive(Clone, Copy, Debug)]
+pub struct LoggingLayer {
+    level: log::Level,
+}
+
+impl LoggingLayer {
+    /// Creates a new `LoggingLayer` logging at the given level.
+    pub fn new(level: log::Level) -> Self {
+        LoggingLayer { level }
+    }
+}
+
+impl Default for LoggingLayer {
+    fn default() -> Self {
+        LoggingLayer::new(log::Level::Debug)
+    }
+}
+
+impl<S> Layer<S> for LoggingLayer {
+    type Service = Logging<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Logging {
+            inner,
+            level: self.level,
+        }
+    }
+}
+
+/// Service which logs every incoming message and how long it took to handle it.
+///
+/// See [`LoggingLayer`] for details.
+#[derive(Clone, Debug)]
+pub struct Logging<S> {
+    inner: S,
+    level: log::Level,
+}
+
+impl<S> Service<Incoming> for Logging<S>
+where
+    S: Service<Incoming, Response = Option<Outgoing>>,
+    S::Future: Send + 'static,
+{
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Error>;
+    type Response = Option<Outgoing>;
+
+    fn poll_ready(&mut self, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, message: Incoming) -> Self::Future {
+        let level = self.level;
+        let description = match (&message, message.id()) {
+            (Incoming::Request(request), Some(id)) => format!("request {:?} ({})", request.method(), id),
+            (Incoming::Request(request), None) => format!("notification {:?}", request.method()),
+            (Incoming::Response(_), Some(id)) => format!("response to request {}", id),
+            (Incoming::Response(_), None) => "response without request ID".to_string(),
+        };
+
+        log::log!(level, "received {}", description);
+        let start = Instant::now();
+        let response = self.inner.call(message);
+
+        Box::pin(async move {
+            let response = response.await;
+            match response {
+                Ok(Some(Outgoing::Response(ref response))) if response.is_error() => {
+                    log::warn!("{} failed after {:?}", description, start.elapsed())
+                },
+                Ok(_) => log::log!(level, "handled {} in {:?}", description, start.elapsed()),
+                Err(_) => log::log!(level, "service failed to handle {}", description),
+            }
+            response
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::tests::{notification, ok, request, MockService};
+    use tower_test::mock::Spawn;
+
+    #[tokio::test]
+    async fn passes_messages_through() {
+        let layer = LoggingLayer::new(log::Level::Info);
+        let mut service = Spawn::new(layer.layer(MockService::default()));
+
+        assert_eq!(service.call(request("textDocument/hover", 1)).await, Ok(ok(1)));
+        assert_eq!(service.call(notification("textDocument/didOpen")).await, Ok(None));
+    }
+}
@@ -0,0 +1,139 @@
+//! Per-method request timeouts.
+
+use super::{request_id, ResponseFuture, LIFECYCLE_METHODS};
+use crate::jsonrpc::{Error, ErrorCode, Incoming, Outgoing, Response};
+use futures::future::{self, Either};
+use futures_timer::Delay;
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// Layer which answers requests with an error if they are not handled in time.
+///
+/// Requests which time out are answered with a "server cancelled" error (`-32802`) and their
+/// handlers are dropped. Notifications and the requests of the protocol lifecycle (`initialize`
+/// and `shutdown`) never time out.
+#[derive(Clone, Debug)]
+pub struct TimeoutLayer {
+    default: Duration,
+    methods: Arc<HashMap<String, Duration>>,
+}
+
+impl TimeoutLayer {
+    /// Creates a new `TimeoutLayer` with the given timeout for all requests.
+    pub fn new(timeout: Duration) -> Self {
+        TimeoutLayer {
+            default: timeout,
+            methods: Default::default(),
+        }
+    }
+
+    /// Overrides the timeout for requests of the given method.
+    pub fn method<M: Into<String>>(mut self, method: M, timeout: Duration) -> Self {
+        Arc::make_mut(&mut self.methods).insert(method.into(), timeout);
+        self
+    }
+
+    fn timeout(&self, method: &str) -> Option<Duration> {
+        if LIFECYCLE_METHODS.contains(&method) {
+            None
+        } else {
+            Some(self.methods.get(method).copied().unwrap_or(self.default))
+        }
+    }
+}
+
+impl<S> Layer<S> for TimeoutLayer {
+    type Service = Timeout<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Timeout {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// Service which answers requests with an error if they are not handled in time.
+///
+/// See [`TimeoutLayer`] for details.
+#[derive(Clone, Debug)]
+pub struct Timeout<S> {
+    inner: S,
+    layer: TimeoutLayer,
+}
+
+impl<S> Service<Incoming> for Timeout<S>
+where
+    S: Service<Incoming, Response = Option<Outgoing>>,
+    S::Future: Send + 'static,
+{
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Error>;
+    type Response = Option<Outgoing>;
+
+    fn poll_ready(&mut self, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, message: Incoming) -> Self::Future {
+        let timeout = request_id(&message)
+            .cloned()
+            .zip(message.method().and_then(|method| self.layer.timeout(method)));
+        let response = self.inner.call(message);
+
+        match timeout {
+            None => Box::pin(response),
+            Some((id, timeout)) => Box::pin(async move {
+                match future::select(Box::pin(response), Delay::new(timeout)).await {
+                    Either::Left((response, _)) => response,
+                    Either::Right(_) => {
+                        log::warn!("request {} timed out after {:?}", id, timeout);
+                        let error = Error {
+                            code: ErrorCode::ServerCancelled,
+                            message: format!("Request timed out after {:?}", timeout),
+                            data: None,
+                        };
+                        Ok(Some(Outgoing::Response(Response::error(Some(id), error))))
+                    },
+                }
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::tests::{notification, ok, request, MockService};
+    use tower_test::mock::Spawn;
+
+    #[tokio::test]
+    async fn times_out() {
+        let layer =
+            TimeoutLayer::new(Duration::from_millis(10)).method("textDocument/formatting", Duration::from_secs(1));
+        let mut service = Spawn::new(layer.layer(MockService(Duration::from_millis(50))));
+
+        let response = service.call(request("textDocument/hover", 1)).await.unwrap();
+        match response {
+            Some(Outgoing::Response(response)) => {
+                let (id, result) = response.into_parts();
+                assert_eq!(id, Some(crate::jsonrpc::Id::Number(1)));
+                assert_eq!(result.unwrap_err().code, ErrorCode::ServerCancelled);
+            },
+            other => panic!("unexpected response: {:?}", other),
+        }
+
+        let response = service.call(request("textDocument/formatting", 2)).await;
+        assert_eq!(response, Ok(ok(2)));
+        let response = service.call(request("shutdown", 3)).await;
+        assert_eq!(response, Ok(ok(3)));
+        let response = service.call(notification("textDocument/didOpen")).await;
+        assert_eq!(response, Ok(None));
+    }
+}
@@ -8,21 +8,37 @@
 
 pub extern crate lsp;
 
+// Lets the code generated by `rpc` for extension traits refer to this crate in its own tests.
+#[cfg(test)]
+extern crate self as lspower;
+
+pub mod capabilities;
 mod client;
 mod codec;
+pub mod extension;
 pub mod jsonrpc;
+pub mod language_client;
+pub mod layers;
+pub mod metrics;
+mod monitor;
+pub mod notebook;
+pub mod proxy;
+mod queue;
 mod server;
 mod service;
 mod transport;
+pub mod workspace;
+pub mod workspace_symbol;
 
 pub use self::{
-    client::{CancellationToken, Client, TokenCanceller},
+    client::{CancellationToken, Client, DiagnosticsPublisher, RegistrationGuard, RequestIdStrategy, TokenCanceller},
+    queue::OverflowPolicy,
     service::{ExitedError, LspService, MessageStream},
-    transport::Server,
+    transport::{stdio, ServeOutcome, Server},
 };
 pub use async_trait::async_trait;
 use auto_impl::auto_impl;
-use lspower_macros::rpc;
+pub use lspower_macros::rpc;
 
 /// Trait implemented by language server backends.
 ///
@@ -30,7 +46,7 @@ use lspower_macros::rpc;
 /// safe and easily testable way without exposing the low-level implementation details.
 ///
 /// [Language Server Protocol]: https://microsoft.github.io/language-server-protocol/
-#[rpc]
+#[rpc(internal)]
 #[async_trait]
 #[auto_impl(Arc, Box)]
 pub trait LanguageServer: Send + Sync + 'static {
@@ -40,7 +56,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     ///
     /// This method is guaranteed to only execute once. If the client sends this request to the
     /// server again, the server will respond with JSON-RPC error code `-32600` (invalid request).
-    #[rpc(name = "initialize")]
+    #[rpc(request = lsp::request::Initialize)]
     async fn initialize(&self, params: lsp::InitializeParams) -> crate::jsonrpc::Result<lsp::InitializeResult>;
 
     /// The [`initialized`] notification is sent from the client to the server after the client
@@ -50,7 +66,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     /// capabilities with the client.
     ///
     /// [`initialized`]: https://microsoft.github.io/language-server-protocol/specification#initialized
-    #[rpc(name = "initialized")]
+    #[rpc(notification = lsp::notification::Initialized)]
     async fn initialized(&self, _params: lsp::InitializedParams) {
     }
 
@@ -64,7 +80,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     ///
     /// This method is guaranteed to only execute once. If the client sends this request to the
     /// server again, the server will respond with JSON-RPC error code `-32600` (invalid request).
-    #[rpc(name = "shutdown")]
+    #[rpc(request = lsp::request::Shutdown)]
     async fn shutdown(&self) -> crate::jsonrpc::Result<()>;
 
     /// The [`workspace/didChangeWorkspaceFolders`] notification is sent from the client to the
@@ -81,7 +97,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     ///
     /// [`workspace/didChangeWorkspaceFolders`]: https://microsoft.github.io/language-server-protocol/specification#workspace_didChangeWorkspaceFolders
     /// [`initialize`]: #tymethod.initialize
-    #[rpc(name = "workspace/didChangeWorkspaceFolders")]
+    #[rpc(notification = lsp::notification::DidChangeWorkspaceFolders)]
     async fn did_change_workspace_folders(&self, _params: lsp::DidChangeWorkspaceFoldersParams) {
         log::warn!("Got a workspace/didChangeWorkspaceFolders notification, but it is not implemented");
     }
@@ -90,7 +106,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     /// to signal the change of configuration settings.
     ///
     /// [`workspace/didChangeConfiguration`]: https://microsoft.github.io/language-server-protocol/specification#workspace_didChangeConfiguration
-    #[rpc(name = "workspace/didChangeConfiguration")]
+    #[rpc(notification = lsp::notification::DidChangeConfiguration)]
     async fn did_change_configuration(&self, _params: lsp::DidChangeConfigurationParams) {
         log::warn!("Got a workspace/didChangeConfiguration notification, but it is not implemented");
     }
@@ -104,7 +120,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     ///
     /// [`workspace/didChangeWatchedFiles`]: https://microsoft.github.io/language-server-protocol/specification#workspace_didChangeConfiguration
     /// [`initialized`]: #tymethod.initialized
-    #[rpc(name = "workspace/didChangeWatchedFiles")]
+    #[rpc(notification = lsp::notification::DidChangeWatchedFiles)]
     async fn did_change_watched_files(&self, _params: lsp::DidChangeWatchedFilesParams) {
         log::warn!("Got a workspace/didChangeWatchedFiles notification, but it is not implemented");
     }
@@ -113,7 +129,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     /// symbols matching the given query string.
     ///
     /// [`workspace/symbol`]: https://microsoft.github.io/language-server-protocol/specification#workspace_symbol
-    #[rpc(name = "workspace/symbol")]
+    #[rpc(request = lsp::request::WorkspaceSymbol)]
     async fn symbol(
         &self,
         _params: lsp::WorkspaceSymbolParams,
@@ -122,6 +138,24 @@ pub trait LanguageServer: Send + Sync + 'static {
         Err(crate::jsonrpc::Error::method_not_found())
     }
 
+    /// The [`workspaceSymbol/resolve`] request is sent from the client to the server to resolve
+    /// additional information, such as the range, of a symbol returned by [`symbol`].
+    ///
+    /// [`workspaceSymbol/resolve`]: https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#workspace_symbolResolve
+    /// [`symbol`]: #method.symbol
+    ///
+    /// # Compatibility
+    ///
+    /// This request was introduced in specification version 3.17.0.
+    #[rpc(request = crate::workspace_symbol::WorkspaceSymbolResolve)]
+    async fn symbol_resolve(
+        &self,
+        _params: crate::workspace_symbol::WorkspaceSymbol,
+    ) -> crate::jsonrpc::Result<crate::workspace_symbol::WorkspaceSymbol> {
+        log::error!("Got a workspaceSymbol/resolve request, but it is not implemented");
+        Err(crate::jsonrpc::Error::method_not_found())
+    }
+
     /// The [`workspace/executeCommand`] request is sent from the client to the server to trigger
     /// command execution on the server.
     ///
@@ -129,7 +163,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     /// the workspace using `Client::apply_edit()` before returning from this function.
     ///
     /// [`workspace/executeCommand`]: https://microsoft.github.io/language-server-protocol/specification#workspace_executeCommand
-    #[rpc(name = "workspace/executeCommand")]
+    #[rpc(request = lsp::request::ExecuteCommand)]
     async fn execute_command(
         &self,
         _params: lsp::ExecuteCommandParams,
@@ -146,7 +180,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     /// client. It doesn't necessarily mean that its content is presented in an editor.
     ///
     /// [`textDocument/didOpen`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_didOpen
-    #[rpc(name = "textDocument/didOpen")]
+    #[rpc(notification = lsp::notification::DidOpenTextDocument)]
     async fn did_open(&self, _params: lsp::DidOpenTextDocumentParams) {
         log::warn!("Got a textDocument/didOpen notification, but it is not implemented");
     }
@@ -158,7 +192,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     /// document for the server to interpret.
     ///
     /// [`textDocument/didChange`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_didChange
-    #[rpc(name = "textDocument/didChange")]
+    #[rpc(notification = lsp::notification::DidChangeTextDocument)]
     async fn did_change(&self, _params: lsp::DidChangeTextDocumentParams) {
         log::warn!("Got a textDocument/didChange notification, but it is not implemented");
     }
@@ -167,7 +201,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     /// document is actually saved.
     ///
     /// [`textDocument/willSave`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_willSave
-    #[rpc(name = "textDocument/willSave")]
+    #[rpc(notification = lsp::notification::WillSaveTextDocument)]
     async fn will_save(&self, _params: lsp::WillSaveTextDocumentParams) {
         log::warn!("Got a textDocument/willSave notification, but it is not implemented");
     }
@@ -180,7 +214,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     ///
     /// Please note that clients might drop results if computing the text edits took too long or if
     /// a server constantly fails on this request. This is done to keep the save fast and reliable.
-    #[rpc(name = "textDocument/willSaveWaitUntil")]
+    #[rpc(request = lsp::request::WillSaveWaitUntil)]
     async fn will_save_wait_until(
         &self,
         _params: lsp::WillSaveTextDocumentParams,
@@ -193,7 +227,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     /// document was saved in the client.
     ///
     /// [`textDocument/didSave`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_didSave
-    #[rpc(name = "textDocument/didSave")]
+    #[rpc(notification = lsp::notification::DidSaveTextDocument)]
     async fn did_save(&self, _params: lsp::DidSaveTextDocumentParams) {
         log::warn!("Got a textDocument/didSave notification, but it is not implemented");
     }
@@ -205,11 +239,50 @@ pub trait LanguageServer: Send + Sync + 'static {
     /// URI is a file URI, the truth now exists on disk).
     ///
     /// [`textDocument/didClose`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_didClose
-    #[rpc(name = "textDocument/didClose")]
+    #[rpc(notification = lsp::notification::DidCloseTextDocument)]
     async fn did_close(&self, _params: lsp::DidCloseTextDocumentParams) {
         log::warn!("Got a textDocument/didClose notification, but it is not implemented");
     }
 
+    /// The [`notebookDocument/didOpen`] notification is sent from the client to the server when a
+    /// notebook document is opened, along with the text documents of its cells.
+    ///
+    /// It is only sent for the notebooks selected by the [`notebook_document_sync`] capability.
+    ///
+    /// [`notebookDocument/didOpen`]: https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#notebookDocument_didOpen
+    /// [`notebook_document_sync`]: crate::LspService::notebook_document_sync
+    #[rpc(notification = crate::notebook::DidOpenNotebookDocument)]
+    async fn did_open_notebook_document(&self, _params: crate::notebook::DidOpenNotebookDocumentParams) {
+        log::warn!("Got a notebookDocument/didOpen notification, but it is not implemented");
+    }
+
+    /// The [`notebookDocument/didChange`] notification is sent from the client to the server when
+    /// cells are added to or removed from a notebook document, or when their content changes.
+    ///
+    /// [`notebookDocument/didChange`]: https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#notebookDocument_didChange
+    #[rpc(notification = crate::notebook::DidChangeNotebookDocument)]
+    async fn did_change_notebook_document(&self, _params: crate::notebook::DidChangeNotebookDocumentParams) {
+        log::warn!("Got a notebookDocument/didChange notification, but it is not implemented");
+    }
+
+    /// The [`notebookDocument/didSave`] notification is sent from the client to the server when a
+    /// notebook document was saved in the client.
+    ///
+    /// [`notebookDocument/didSave`]: https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#notebookDocument_didSave
+    #[rpc(notification = crate::notebook::DidSaveNotebookDocument)]
+    async fn did_save_notebook_document(&self, _params: crate::notebook::DidSaveNotebookDocumentParams) {
+        log::warn!("Got a notebookDocument/didSave notification, but it is not implemented");
+    }
+
+    /// The [`notebookDocument/didClose`] notification is sent from the client to the server when a
+    /// notebook document got closed in the client, along with the text documents of its cells.
+    ///
+    /// [`notebookDocument/didClose`]: https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#notebookDocument_didClose
+    #[rpc(notification = crate::notebook::DidCloseNotebookDocument)]
+    async fn did_close_notebook_document(&self, _params: crate::notebook::DidCloseNotebookDocumentParams) {
+        log::warn!("Got a notebookDocument/didClose notification, but it is not implemented");
+    }
+
     /// The [`textDocument/completion`] request is sent from the client to the server to compute
     /// completion items at a given cursor position.
     ///
@@ -218,7 +291,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     /// when a completion item is selected in the user interface.
     ///
     /// [`textDocument/completion`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_completion
-    #[rpc(name = "textDocument/completion")]
+    #[rpc(request = lsp::request::Completion)]
     async fn completion(
         &self,
         _params: lsp::CompletionParams,
@@ -231,7 +304,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     /// additional information for a given completion item.
     ///
     /// [`completionItem/resolve`]: https://microsoft.github.io/language-server-protocol/specification#completionItem_resolve
-    #[rpc(name = "completionItem/resolve")]
+    #[rpc(request = lsp::request::ResolveCompletionItem)]
     async fn completion_resolve(&self, _params: lsp::CompletionItem) -> crate::jsonrpc::Result<lsp::CompletionItem> {
         log::error!("Got a completionItem/resolve request, but it is not implemented");
         Err(crate::jsonrpc::Error::method_not_found())
@@ -244,7 +317,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     /// documentation for the symbol at the given text document position.
     ///
     /// [`textDocument/hover`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_hover
-    #[rpc(name = "textDocument/hover")]
+    #[rpc(request = lsp::request::HoverRequest)]
     async fn hover(&self, _params: lsp::HoverParams) -> crate::jsonrpc::Result<Option<lsp::Hover>> {
         log::error!("Got a textDocument/hover request, but it is not implemented");
         Err(crate::jsonrpc::Error::method_not_found())
@@ -254,7 +327,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     /// signature information at a given cursor position.
     ///
     /// [`textDocument/signatureHelp`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_signatureHelp
-    #[rpc(name = "textDocument/signatureHelp")]
+    #[rpc(request = lsp::request::SignatureHelpRequest)]
     async fn signature_help(
         &self,
         _params: lsp::SignatureHelpParams,
@@ -282,7 +355,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     ///
     /// [`GotoDefinitionResponse::Link`]: https://docs.rs/lsp-types/0.74.0/lsp_types/enum.GotoDefinitionResponse.html#variant.Link
     /// [`initialize`]: #tymethod.initialize
-    #[rpc(name = "textDocument/declaration")]
+    #[rpc(request = lsp::request::GotoDeclaration)]
     async fn goto_declaration(
         &self,
         _params: lsp::request::GotoDeclarationParams,
@@ -308,7 +381,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     ///
     /// [`GotoDefinitionResponse::Link`]: https://docs.rs/lsp-types/0.74.0/lsp_types/enum.GotoDefinitionResponse.html#variant.Link
     /// [`initialize`]: #tymethod.initialize
-    #[rpc(name = "textDocument/definition")]
+    #[rpc(request = lsp::request::GotoDefinition)]
     async fn goto_definition(
         &self,
         _params: lsp::GotoDefinitionParams,
@@ -336,7 +409,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     ///
     /// [`GotoDefinitionResponse::Link`]: https://docs.rs/lsp-types/0.74.0/lsp_types/enum.GotoDefinitionResponse.html#variant.Link
     /// [`initialize`]: #tymethod.initialize
-    #[rpc(name = "textDocument/typeDefinition")]
+    #[rpc(request = lsp::request::GotoTypeDefinition)]
     async fn goto_type_definition(
         &self,
         _params: lsp::request::GotoTypeDefinitionParams,
@@ -364,7 +437,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     ///
     /// [`GotoImplementationResponse::Link`]: https://docs.rs/lsp-types/0.74.0/lsp_types/enum.GotoDefinitionResponse.html#variant.Link
     /// [`initialize`]: #tymethod.initialize
-    #[rpc(name = "textDocument/implementation")]
+    #[rpc(request = lsp::request::GotoImplementation)]
     async fn goto_implementation(
         &self,
         _params: lsp::request::GotoImplementationParams,
@@ -377,7 +450,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     /// project-wide references for the symbol denoted by the given text document position.
     ///
     /// [`textDocument/references`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_references
-    #[rpc(name = "textDocument/references")]
+    #[rpc(request = lsp::request::References)]
     async fn references(&self, _params: lsp::ReferenceParams) -> crate::jsonrpc::Result<Option<Vec<lsp::Location>>> {
         log::error!("Got a textDocument/references request, but it is not implemented");
         Err(crate::jsonrpc::Error::method_not_found())
@@ -393,7 +466,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     /// be more fuzzy.
     ///
     /// [`textDocument/documentHighlight`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_documentHighlight
-    #[rpc(name = "textDocument/documentHighlight")]
+    #[rpc(request = lsp::request::DocumentHighlightRequest)]
     async fn document_highlight(
         &self,
         _params: lsp::DocumentHighlightParams,
@@ -416,7 +489,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     /// [`textDocument/documentSymbol`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_documentSymbol
     /// [`DocumentSymbolResponse::Flat`]: https://docs.rs/lsp-types/0.74.0/lsp_types/enum.DocumentSymbolResponse.html#variant.Flat
     /// [`DocumentSymbolResponse::Nested`]: https://docs.rs/lsp-types/0.74.0/lsp_types/enum.DocumentSymbolResponse.html#variant.Nested
-    #[rpc(name = "textDocument/documentSymbol")]
+    #[rpc(request = lsp::request::DocumentSymbolRequest)]
     async fn document_symbol(
         &self,
         _params: lsp::DocumentSymbolParams,
@@ -456,7 +529,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     ///
     /// [`textDocument/codeAction`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_codeAction
     /// [`workspace/executeCommand`]: https://microsoft.github.io/language-server-protocol/specification#workspace_executeCommand
-    #[rpc(name = "textDocument/codeAction")]
+    #[rpc(request = lsp::request::CodeActionRequest)]
     async fn code_action(
         &self,
         _params: lsp::CodeActionParams,
@@ -469,7 +542,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     /// lenses for a given text document.
     ///
     /// [`textDocument/codeLens`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_codeLens
-    #[rpc(name = "textDocument/codeLens")]
+    #[rpc(request = lsp::request::CodeLensRequest)]
     async fn code_lens(&self, _params: lsp::CodeLensParams) -> crate::jsonrpc::Result<Option<Vec<lsp::CodeLens>>> {
         log::error!("Got a textDocument/codeLens request, but it is not implemented");
         Err(crate::jsonrpc::Error::method_not_found())
@@ -479,7 +552,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     /// command for a given code lens item.
     ///
     /// [`codeLens/resolve`]: https://microsoft.github.io/language-server-protocol/specification#codeLens_resolve
-    #[rpc(name = "codeLens/resolve")]
+    #[rpc(request = lsp::request::CodeLensResolve)]
     async fn code_lens_resolve(&self, _params: lsp::CodeLens) -> crate::jsonrpc::Result<lsp::CodeLens> {
         log::error!("Got a codeLens/resolve request, but it is not implemented");
         Err(crate::jsonrpc::Error::method_not_found())
@@ -504,7 +577,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     /// ```
     ///
     /// [`initialize`]: #tymethod.initialize
-    #[rpc(name = "textDocument/documentLink")]
+    #[rpc(request = lsp::request::DocumentLinkRequest)]
     async fn document_link(
         &self,
         _params: lsp::DocumentLinkParams,
@@ -520,7 +593,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     /// resource, like another text document or a web site.
     ///
     /// [`documentLink/resolve`]: https://microsoft.github.io/language-server-protocol/specification#documentLink_resolve
-    #[rpc(name = "documentLink/resolve")]
+    #[rpc(request = lsp::request::DocumentLinkResolve)]
     async fn document_link_resolve(&self, _params: lsp::DocumentLink) -> crate::jsonrpc::Result<lsp::DocumentLink> {
         log::error!("Got a documentLink/resolve request, but it is not implemented");
         Err(crate::jsonrpc::Error::method_not_found())
@@ -540,7 +613,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     /// # Compatibility
     ///
     /// This request was introduced in specification version 3.6.0.
-    #[rpc(name = "textDocument/documentColor")]
+    #[rpc(request = lsp::request::DocumentColor)]
     async fn document_color(
         &self,
         _params: lsp::DocumentColorParams,
@@ -565,7 +638,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     /// resolve request for the [`textDocument/documentColor`] request.
     ///
     /// [`textDocument/documentColor`]: #tymethod.document_color
-    #[rpc(name = "textDocument/colorPresentation")]
+    #[rpc(request = lsp::request::ColorPresentationRequest)]
     async fn color_presentation(
         &self,
         _params: lsp::ColorPresentationParams,
@@ -578,7 +651,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     /// whole document.
     ///
     /// [`textDocument/formatting`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_formatting
-    #[rpc(name = "textDocument/formatting")]
+    #[rpc(request = lsp::request::Formatting)]
     async fn formatting(
         &self,
         _params: lsp::DocumentFormattingParams,
@@ -591,7 +664,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     /// format a given range in a document.
     ///
     /// [`textDocument/rangeFormatting`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_rangeFormatting
-    #[rpc(name = "textDocument/rangeFormatting")]
+    #[rpc(request = lsp::request::RangeFormatting)]
     async fn range_formatting(
         &self,
         _params: lsp::DocumentRangeFormattingParams,
@@ -604,7 +677,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     /// format parts of the document during typing.
     ///
     /// [`textDocument/onTypeFormatting`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_onTypeFormatting
-    #[rpc(name = "textDocument/onTypeFormatting")]
+    #[rpc(request = lsp::request::OnTypeFormatting)]
     async fn on_type_formatting(
         &self,
         _params: lsp::DocumentOnTypeFormattingParams,
@@ -618,7 +691,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     /// symbol.
     ///
     /// [`textDocument/rename`]: https://microsoft.github.io/language-server-protocol/specification#textDocument_rename
-    #[rpc(name = "textDocument/rename")]
+    #[rpc(request = lsp::request::Rename)]
     async fn rename(&self, _params: lsp::RenameParams) -> crate::jsonrpc::Result<Option<lsp::WorkspaceEdit>> {
         log::error!("Got a textDocument/rename request, but it is not implemented");
         Err(crate::jsonrpc::Error::method_not_found())
@@ -632,7 +705,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     /// # Compatibility
     ///
     /// This request was introduced in specification version 3.12.0.
-    #[rpc(name = "textDocument/prepareRename")]
+    #[rpc(request = lsp::request::PrepareRenameRequest)]
     async fn prepare_rename(
         &self,
         _params: lsp::TextDocumentPositionParams,
@@ -649,7 +722,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     /// # Compatibility
     ///
     /// This request was introduced in specification version 3.10.0.
-    #[rpc(name = "textDocument/foldingRange")]
+    #[rpc(request = lsp::request::FoldingRangeRequest)]
     async fn folding_range(
         &self,
         _params: lsp::FoldingRangeParams,
@@ -670,7 +743,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     /// # Compatibility
     ///
     /// This request was introduced in specification version 3.15.0.
-    #[rpc(name = "textDocument/selectionRange")]
+    #[rpc(request = lsp::request::SelectionRangeRequest)]
     async fn selection_range(
         &self,
         _params: lsp::SelectionRangeParams,
@@ -680,7 +753,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     }
 
     /// [`callHierarchy/incomingCalls`]: https://microsoft.github.io/language-server-protocol/specifications/specification-3-16/#callHierarchy_incomingCalls
-    #[rpc(name = "callHierarchy/incomingCalls")]
+    #[rpc(request = lsp::request::CallHierarchyIncomingCalls)]
     async fn incoming_calls(
         &self,
         _params: lsp::CallHierarchyIncomingCallsParams,
@@ -690,7 +763,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     }
 
     /// [`callHierarchy/outgoingCalls`]: https://microsoft.github.io/language-server-protocol/specifications/specification-3-16/#callHierarchy_outgoingCalls
-    #[rpc(name = "callHierarchy/outgoingCalls")]
+    #[rpc(request = lsp::request::CallHierarchyOutgoingCalls)]
     async fn outgoing_calls(
         &self,
         _params: lsp::CallHierarchyOutgoingCallsParams,
@@ -700,7 +773,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     }
 
     /// [`textDocument/prepareCallHierarchy`]: https://microsoft.github.io/language-server-protocol/specifications/specification-3-16/#textDocument_prepareCallHierarchy
-    #[rpc(name = "textDocument/prepareCallHierarchy")]
+    #[rpc(request = lsp::request::CallHierarchyPrepare)]
     async fn prepare_call_hierarchy(
         &self,
         _params: lsp::CallHierarchyPrepareParams,
@@ -710,7 +783,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     }
 
     /// [`textDocument/semanticTokens/full`]: https://microsoft.github.io/language-server-protocol/specifications/specification-3-16/#textDocument_semanticTokens
-    #[rpc(name = "textDocument/semanticTokens/full")]
+    #[rpc(request = lsp::request::SemanticTokensFullRequest)]
     async fn semantic_tokens_full(
         &self,
         _params: lsp::SemanticTokensParams,
@@ -720,7 +793,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     }
 
     /// [`textDocument/semanticTokens/full/delta`]: https://microsoft.github.io/language-server-protocol/specifications/specification-3-16/#textDocument_semanticTokens
-    #[rpc(name = "textDocument/semanticTokens/full/delta")]
+    #[rpc(request = lsp::request::SemanticTokensFullDeltaRequest)]
     async fn semantic_tokens_full_delta(
         &self,
         _params: lsp::SemanticTokensDeltaParams,
@@ -730,7 +803,7 @@ pub trait LanguageServer: Send + Sync + 'static {
     }
 
     /// [`textDocument/semanticTokens/range`]: https://microsoft.github.io/language-server-protocol/specifications/specification-3-16/#textDocument_semanticTokens
-    #[rpc(name = "textDocument/semanticTokens/range")]
+    #[rpc(request = lsp::request::SemanticTokensRangeRequest)]
     async fn semantic_tokens_range(
         &self,
         _params: lsp::SemanticTokensRangeParams,
@@ -740,19 +813,36 @@ pub trait LanguageServer: Send + Sync + 'static {
     }
 
     /// [`workspace/semanticTokens/full`]: https://microsoft.github.io/language-server-protocol/specifications/specification-3-16/#textDocument_semanticTokens
-    #[rpc(name = "workspace/semanticTokens/refresh")]
+    #[rpc(request = lsp::request::SemanticTokensRefesh)]
     async fn semantic_tokens_refresh(&self) -> crate::jsonrpc::Result<()> {
         log::error!("Got a workspace/semanticTokens/refresh request, but it is not implemented");
         Err(crate::jsonrpc::Error::method_not_found())
     }
 
     /// [`codeAction/resolve`]: https://microsoft.github.io/language-server-protocol/specifications/specification-3-16/#codeAction_resolve
-    #[rpc(name = "codeAction/resolve")]
+    #[rpc(request = lsp::request::CodeActionResolveRequest)]
     async fn code_action_resolve(&self, _params: lsp::CodeAction) -> crate::jsonrpc::Result<lsp::CodeAction> {
         log::error!("Got a codeAction/resolve request, but it is not implemented");
         Err(crate::jsonrpc::Error::method_not_found())
     }
 
+    /// [`textDocument/linkedEditingRange`]: https://microsoft.github.io/language-server-protocol/specifications/specification-3-16/#textDocument_linkedEditingRange
+    #[rpc(request = lsp::request::LinkedEditingRange)]
+    async fn linked_editing_range(
+        &self,
+        _params: lsp::LinkedEditingRangeParams,
+    ) -> crate::jsonrpc::Result<Option<lsp::LinkedEditingRanges>> {
+        log::error!("Got a textDocument/linkedEditingRange request, but it is not implemented");
+        Err(crate::jsonrpc::Error::method_not_found())
+    }
+
+    /// [`textDocument/moniker`]: https://microsoft.github.io/language-server-protocol/specifications/specification-3-16/#textDocument_moniker
+    #[rpc(request = lsp::request::MonikerRequest)]
+    async fn moniker(&self, _params: lsp::MonikerParams) -> crate::jsonrpc::Result<Option<Vec<lsp::Moniker>>> {
+        log::error!("Got a textDocument/moniker request, but it is not implemented");
+        Err(crate::jsonrpc::Error::method_not_found())
+    }
+
     /// This handler can be used to respond to all requests that are not handled by built in request
     /// handlers.
     async fn request_else(
@@ -766,6 +856,15 @@ pub trait LanguageServer: Send + Sync + 'static {
         );
         Err(crate::jsonrpc::Error::method_not_found())
     }
+
+    /// Returns the names of the handlers overridden by this implementation, or `None` if unknown.
+    ///
+    /// This method is generated by annotating the implementation with [`rpc`], and should not be
+    /// implemented manually. The handlers are used to infer the [`capabilities::baseline`] of the
+    /// server, and, in debug builds, to warn about capabilities which disagree with them.
+    fn implemented_handlers(&self) -> Option<&'static [&'static str]> {
+        None
+    }
 }
 
 #[cfg(test)]
@@ -1545,6 +1644,57 @@ mod tests {
             );
         }
 
+        #[tokio::test]
+        async fn linked_editing_range() {
+            let (service, _) = LspService::new(|_| Mock::default());
+            let mut service = Spawn::new(service);
+
+            super::helper::initialize(&mut service).await;
+
+            let params = lsp::LinkedEditingRangeParams {
+                text_document_position_params: lsp::TextDocumentPositionParams {
+                    text_document: lsp::TextDocumentIdentifier {
+                        uri: lsp::Url::parse("inmemory::///test").unwrap(),
+                    },
+                    position: Default::default(),
+                },
+                work_done_progress_params: Default::default(),
+            };
+            let request: Incoming = helper::request("textDocument/linkedEditingRange", params).unwrap();
+            let response = Response::error(Some(Id::Number(1)), Error::method_not_found());
+            assert_eq!(service.poll_ready(), Poll::Ready(Ok(())));
+            assert_eq!(
+                service.call(request.clone()).await,
+                Ok(Some(Outgoing::Response(response)))
+            );
+        }
+
+        #[tokio::test]
+        async fn moniker() {
+            let (service, _) = LspService::new(|_| Mock::default());
+            let mut service = Spawn::new(service);
+
+            super::helper::initialize(&mut service).await;
+
+            let params = lsp::MonikerParams {
+                text_document_position_params: lsp::TextDocumentPositionParams {
+                    text_document: lsp::TextDocumentIdentifier {
+                        uri: lsp::Url::parse("inmemory::///test").unwrap(),
+                    },
+                    position: Default::default(),
+                },
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            };
+            let request: Incoming = helper::request("textDocument/moniker", params).unwrap();
+            let response = Response::error(Some(Id::Number(1)), Error::method_not_found());
+            assert_eq!(service.poll_ready(), Poll::Ready(Ok(())));
+            assert_eq!(
+                service.call(request.clone()).await,
+                Ok(Some(Outgoing::Response(response)))
+            );
+        }
+
         #[tokio::test]
         async fn on_type_formatting() {
             let (service, _) = LspService::new(|_| Mock::default());
@@ -1913,4 +2063,37 @@ mod tests {
             );
         }
     }
+
+    mod workspace_symbol {
+        use super::*;
+        use crate::jsonrpc::{Error, Id, Incoming, Outgoing, Response};
+        use std::task::Poll;
+        use tower_test::mock::Spawn;
+
+        #[tokio::test]
+        async fn resolve() {
+            let (service, _) = LspService::new(|_| Mock::default());
+            let mut service = Spawn::new(service);
+
+            super::helper::initialize(&mut service).await;
+
+            let params = crate::workspace_symbol::WorkspaceSymbol {
+                name: Default::default(),
+                kind: lsp::SymbolKind::Unknown,
+                tags: Default::default(),
+                container_name: Default::default(),
+                location: crate::workspace_symbol::WorkspaceSymbolLocation::Document {
+                    uri: lsp::Url::parse("inmemory::///test").unwrap(),
+                },
+                data: Default::default(),
+            };
+            let request: Incoming = helper::request("workspaceSymbol/resolve", params).unwrap();
+            let response = Response::error(Some(Id::Number(1)), Error::method_not_found());
+            assert_eq!(service.poll_ready(), Poll::Ready(Ok(())));
+            assert_eq!(
+                service.call(request.clone()).await,
+                Ok(Some(Outgoing::Response(response)))
+            );
+        }
+    }
 }
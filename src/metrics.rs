@@ -0,0 +1,334 @@
+//! Metrics collected by [`LspService`](crate::LspService) and [`Server`](crate::Server).
+//!
+//! The current metrics are returned by [`Metrics::status`] and, for clients, by the custom
+//! [`$/lspower/status`](StatusRequest) request, which every `LspService` answers on its own.
+
+use crate::{
+    jsonrpc::{ClientRequests, ServerRequests},
+    queue::Queue,
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::{self, Debug, Formatter},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+        Mutex,
+        PoisonError,
+    },
+    time::Duration,
+};
+
+/// Upper bounds of the latency histogram buckets, in milliseconds.
+///
+/// Durations above the last bound are counted in an additional, unbounded bucket.
+const BUCKETS_MS: [u64; 13] = [1, 2, 5, 10, 25, 50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000];
+
+/// The custom request returning the [`Status`] of the server.
+///
+/// The request has no parameters and may be sent at any time before the server exits, including
+/// before the `initialize` request.
+#[derive(Debug)]
+pub enum StatusRequest {}
+
+impl lsp::request::Request for StatusRequest {
+    type Params = ();
+    type Result = Status;
+
+    const METHOD: &'static str = "$/lspower/status";
+}
+
+/// Snapshot of the metrics of a language server.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Status {
+    /// Statistics of every request and notification method handled so far.
+    pub methods: BTreeMap<String, MethodMetrics>,
+    /// Number of client-to-server requests currently being handled.
+    pub in_flight_requests: usize,
+    /// Number of server-to-client requests currently awaiting a response.
+    pub pending_client_requests: usize,
+    /// Number of server-to-client messages waiting in the outgoing queue.
+    pub queued_messages: usize,
+    /// Number of log messages dropped because the outgoing queue was full.
+    pub dropped_messages: u64,
+    /// Number of server-to-client messages which could not be queued, either because the queue
+    /// was full or because the connection to the client was closed.
+    pub rejected_messages: u64,
+    /// Number of messages read from the client.
+    pub messages_received: u64,
+    /// Number of bytes read from the client, including headers.
+    pub bytes_received: u64,
+    /// Number of messages written to the client.
+    pub messages_sent: u64,
+    /// Number of bytes written to the client, including headers.
+    pub bytes_sent: u64,
+}
+
+/// Statistics of a single request or notification method.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MethodMetrics {
+    /// Number of handled requests or notifications.
+    pub count: u64,
+    /// Number of requests which were answered with an error.
+    pub errors: u64,
+    /// Total time spent handling the method.
+    #[serde(rename = "totalMs", with = "millis")]
+    pub total: Duration,
+    /// Longest time spent handling a single request or notification.
+    #[serde(rename = "maxMs", with = "millis")]
+    pub max: Duration,
+    /// Number of requests or notifications per latency bucket, in ascending order.
+    pub histogram: Vec<HistogramBucket>,
+}
+
+/// A bucket of a latency histogram.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistogramBucket {
+    /// Upper bound of the bucket in milliseconds, or `None` for the last, unbounded bucket.
+    pub le_ms: Option<u64>,
+    /// Number of requests or notifications which took longer than the previous bucket's bound
+    /// but no longer than this one's.
+    pub count: u64,
+}
+
+impl MethodMetrics {
+    /// Returns the average time spent handling a single request or notification.
+    pub fn mean(&self) -> Duration {
+        match self.count {
+            0 => Duration::default(),
+            count => Duration::from_secs_f64(self.total.as_secs_f64() / count as f64),
+        }
+    }
+
+    /// Estimates the latency below which the given fraction (between `0.0` and `1.0`) of requests
+    /// or notifications were handled.
+    ///
+    /// The estimate is the upper bound of the histogram bucket containing the percentile, capped
+    /// at the maximum latency. Returns `None` if the method has not been handled yet.
+    pub fn percentile(&self, fraction: f64) -> Option<Duration> {
+        let rank = (fraction.clamp(0.0, 1.0) * self.count as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for bucket in &self.histogram {
+            seen += bucket.count;
+            if seen >= rank {
+                let bound = bucket.le_ms.map_or(self.max, Duration::from_millis);
+                return Some(bound.min(self.max));
+            }
+        }
+        None
+    }
+
+    pub(crate) fn record(&mut self, elapsed: Duration, error: bool) {
+        self.count += 1;
+        self.errors += u64::from(error);
+        self.total += elapsed;
+        self.max = self.max.max(elapsed);
+        let index = BUCKETS_MS
+            .iter()
+            .position(|&bound| elapsed <= Duration::from_millis(bound))
+            .unwrap_or(BUCKETS_MS.len());
+        self.histogram[index].count += 1;
+    }
+}
+
+impl Default for MethodMetrics {
+    fn default() -> Self {
+        let bounds = BUCKETS_MS.iter().map(|&bound| Some(bound)).chain(Some(None));
+        MethodMetrics {
+            count: 0,
+            errors: 0,
+            total: Duration::default(),
+            max: Duration::default(),
+            histogram: bounds.map(|le_ms| HistogramBucket { le_ms, count: 0 }).collect(),
+        }
+    }
+}
+
+/// Handle to the metrics of a language server.
+///
+/// Obtained from [`LspService::metrics`]. Message and byte counts are only collected if the handle
+/// is also passed to [`Server::metrics`].
+///
+/// This type provides a very cheap implementation of [`Clone`], sharing the metrics between clones.
+///
+/// [`LspService::metrics`]: crate::LspService::metrics
+/// [`Server::metrics`]: crate::Server::metrics
+/// [`Clone`]: trait@std::clone::Clone
+#[derive(Clone)]
+pub struct Metrics {
+    inner: Arc<MetricsInner>,
+}
+
+struct MetricsInner {
+    methods: Mutex<HashMap<String, MethodMetrics>>,
+    pending_server: ServerRequests,
+    pending_client: Arc<ClientRequests>,
+    outgoing: Arc<Queue>,
+    messages_received: AtomicU64,
+    bytes_received: AtomicU64,
+    messages_sent: AtomicU64,
+    bytes_sent: AtomicU64,
+}
+
+impl Metrics {
+    pub(crate) fn new(
+        pending_server: ServerRequests,
+        pending_client: Arc<ClientRequests>,
+        outgoing: Arc<Queue>,
+    ) -> Self {
+        Metrics {
+            inner: Arc::new(MetricsInner {
+                methods: Default::default(),
+                pending_server,
+                pending_client,
+                outgoing,
+                messages_received: AtomicU64::new(0),
+                bytes_received: AtomicU64::new(0),
+                messages_sent: AtomicU64::new(0),
+                bytes_sent: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Returns a snapshot of the current metrics.
+    pub fn status(&self) -> Status {
+        let inner = &self.inner;
+        let methods = inner.methods.lock().unwrap_or_else(PoisonError::into_inner);
+        Status {
+            methods: methods.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+            in_flight_requests: inner.pending_server.len(),
+            pending_client_requests: inner.pending_client.0.len(),
+            queued_messages: inner.outgoing.len(),
+            dropped_messages: inner.outgoing.dropped(),
+            rejected_messages: inner.outgoing.rejected(),
+            messages_received: inner.messages_received.load(Ordering::Relaxed),
+            bytes_received: inner.bytes_received.load(Ordering::Relaxed),
+            messages_sent: inner.messages_sent.load(Ordering::Relaxed),
+            bytes_sent: inner.bytes_sent.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Discards the method statistics and message counts recorded so far.
+    pub fn reset(&self) {
+        let inner = &self.inner;
+        inner.methods.lock().unwrap_or_else(PoisonError::into_inner).clear();
+        inner.outgoing.reset();
+        inner.messages_received.store(0, Ordering::Relaxed);
+        inner.bytes_received.store(0, Ordering::Relaxed);
+        inner.messages_sent.store(0, Ordering::Relaxed);
+        inner.bytes_sent.store(0, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_method(&self, method: String, elapsed: Duration, error: bool) {
+        let mut methods = self.inner.methods.lock().unwrap_or_else(PoisonError::into_inner);
+        methods.entry(method).or_default().record(elapsed, error);
+    }
+
+    pub(crate) fn record_received(&self, bytes: usize) {
+        self.inner.messages_received.fetch_add(1, Ordering::Relaxed);
+        self.inner.bytes_received.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_sent(&self, bytes: usize) {
+        self.inner.messages_sent.fetch_add(1, Ordering::Relaxed);
+        self.inner.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+}
+
+impl Debug for Metrics {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct(stringify!(Metrics))
+            .field("pending_server", &self.inner.pending_server)
+            .field("pending_client", &self.inner.pending_client)
+            .field("outgoing", &self.inner.outgoing)
+            .finish()
+    }
+}
+
+/// (De)serializes a `Duration` as fractional milliseconds.
+mod millis {
+    use super::*;
+
+    pub(super) fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        (duration.as_secs_f64() * 1000.0).serialize(serializer)
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        let millis = f64::deserialize(deserializer)?;
+        Ok(Duration::from_secs_f64(millis.max(0.0) / 1000.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn metrics() -> Metrics {
+        let (sender, _) = crate::queue::channel(1, crate::OverflowPolicy::Block);
+        Metrics::new(
+            ServerRequests::new(),
+            Arc::new(ClientRequests::new()),
+            sender.queue().clone(),
+        )
+    }
+
+    #[test]
+    fn percentile() {
+        let mut method = MethodMetrics::default();
+        assert_eq!(method.percentile(0.5), None);
+
+        for _ in 0 .. 9 {
+            method.record(Duration::from_micros(500), false);
+        }
+        method.record(Duration::from_millis(40), true);
+
+        assert_eq!(method.count, 10);
+        assert_eq!(method.errors, 1);
+        assert_eq!(method.histogram[0].count, 9);
+        assert_eq!(method.histogram[5].count, 1);
+        assert_eq!(method.percentile(0.5), Some(Duration::from_millis(1)));
+        assert_eq!(method.percentile(0.99), Some(Duration::from_millis(40)));
+        assert_eq!(method.mean(), Duration::from_micros(4450));
+    }
+
+    #[test]
+    fn status() {
+        let metrics = metrics();
+        metrics.record_method("textDocument/hover".into(), Duration::from_secs(20), false);
+        metrics.record_received(100);
+        metrics.record_sent(50);
+        metrics.record_sent(50);
+
+        let status = metrics.status();
+        let hover = &status.methods["textDocument/hover"];
+        assert_eq!(hover.histogram.last(), Some(&HistogramBucket { le_ms: None, count: 1 }));
+        assert_eq!(hover.percentile(0.5), Some(Duration::from_secs(20)));
+        assert_eq!((status.messages_received, status.bytes_received), (1, 100));
+        assert_eq!((status.messages_sent, status.bytes_sent), (2, 100));
+
+        metrics.reset();
+        assert_eq!(metrics.status(), Status::default());
+    }
+
+    #[test]
+    fn status_json() {
+        let metrics = metrics();
+        metrics.record_method("initialize".into(), Duration::from_micros(1500), false);
+
+        let value = serde_json::to_value(metrics.status()).unwrap();
+        assert_eq!(value["inFlightRequests"], json!(0));
+        assert_eq!(value["methods"]["initialize"]["totalMs"], json!(1.5));
+        assert_eq!(
+            value["methods"]["initialize"]["histogram"][1],
+            json!({ "leMs": 2, "count": 1 })
+        );
+
+        let status: Status = serde_json::from_value(value).unwrap();
+        assert_eq!(status, metrics.status());
+    }
+}
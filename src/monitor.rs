@@ -0,0 +1,125 @@
+//! Monitoring of the language client process.
+
+use crate::{
+    jsonrpc::ServerRequests,
+    server::{State, StateKind},
+};
+use std::{sync::Arc, time::Duration};
+
+/// Spawns a background thread which periodically checks whether the client process is still alive.
+///
+/// If the process disappears, all pending requests are cancelled and the server transitions to the
+/// `Exited` state, which in turn stops the [`Server`](crate::Server) driving the service. The
+/// thread stops on its own once the server has exited for any other reason, or once the service
+/// has been dropped, e.g. after the connection was closed.
+///
+/// Monitoring is only supported on Linux. On other platforms this function only logs a warning.
+#[cfg(target_os = "linux")]
+pub(crate) fn spawn(pid: u32, interval: Duration, state: Arc<State>, pending: ServerRequests) {
+    let state = Arc::downgrade(&state);
+    let spawned = std::thread::Builder::new()
+        .name("lspower-client-monitor".into())
+        .spawn(move || {
+            // Only hold on to the state while checking, so that the thread doesn't keep it alive.
+            while let Some(state) = state.upgrade().filter(|state| state.get() != StateKind::Exited) {
+                if !is_alive(pid) {
+                    log::warn!("client process {} is gone, stopping", pid);
+                    state.set(StateKind::Exited);
+                    pending.cancel_all();
+                    break;
+                }
+                drop(state);
+                std::thread::sleep(interval);
+            }
+        });
+
+    match spawned {
+        Ok(_) => log::info!("monitoring client process {}", pid),
+        Err(err) => log::error!("failed to spawn client process monitor: {}", err),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn spawn(pid: u32, _: Duration, _: Arc<State>, _: ServerRequests) {
+    log::warn!(
+        "client process monitoring is only supported on Linux, not monitoring process {}",
+        pid
+    );
+}
+
+/// Checks whether the process with the given ID exists and has not terminated.
+#[cfg(target_os = "linux")]
+fn is_alive(pid: u32) -> bool {
+    // A terminated process keeps its `/proc` entry until it is reaped, so check its state as well.
+    // The state follows the executable name, which is in parentheses and may contain spaces.
+    match std::fs::read_to_string(format!("/proc/{}/stat", pid)) {
+        Ok(stat) => match stat
+            .rsplit(')')
+            .next()
+            .and_then(|rest| rest.trim_start().chars().next())
+        {
+            Some('Z') | Some('X') | None => false,
+            Some(_) => true,
+        },
+        Err(_) => false,
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::jsonrpc::{Id, Result};
+    use std::process::Command;
+
+    #[test]
+    fn is_alive_current_process() {
+        assert!(is_alive(std::process::id()));
+    }
+
+    #[test]
+    fn is_alive_terminated_process() {
+        let mut child = Command::new("true").spawn().unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        assert!(!is_alive(pid));
+    }
+
+    #[tokio::test]
+    async fn exits_when_process_is_gone() {
+        let mut child = Command::new("sleep").arg("30").spawn().unwrap();
+        let state = Arc::new(State::new());
+        state.set(StateKind::Initialized);
+        spawn(
+            child.id(),
+            Duration::from_millis(10),
+            state.clone(),
+            ServerRequests::new(),
+        );
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(state.get(), StateKind::Initialized);
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(state.get(), StateKind::Exited);
+    }
+
+    #[tokio::test]
+    async fn stops_when_service_is_dropped() {
+        let mut child = Command::new("sleep").arg("30").spawn().unwrap();
+        let state = Arc::new(State::new());
+        state.set(StateKind::Initialized);
+        let pending = ServerRequests::new();
+        let _request = pending.execute(Id::Number(0), futures::future::pending::<Result<()>>());
+        spawn(child.id(), Duration::from_millis(10), state.clone(), pending.clone());
+
+        drop(state);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(pending.len(), 1);
+    }
+}
@@ -0,0 +1,477 @@
+//! Notebook document synchronization, introduced in LSP 3.17.
+//!
+//! A notebook (e.g., a Jupyter notebook) is an ordered list of cells, each of which is backed by a
+//! text document of its own. Servers declare which notebooks they are interested in with
+//! [`LspService::notebook_document_sync`], and receive the [`notebookDocument/didOpen`],
+//! [`notebookDocument/didChange`], [`notebookDocument/didSave`] and [`notebookDocument/didClose`]
+//! notifications through the corresponding handlers of [`LanguageServer`]. The text of the cells is
+//! part of these notifications, rather than synchronized with `textDocument/*` notifications.
+//!
+//! These types are not part of `lsp-types` yet, and follow the [specification].
+//!
+//! [`LspService::notebook_document_sync`]: crate::LspService::notebook_document_sync
+//! [`notebookDocument/didOpen`]: https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#notebookDocument_didOpen
+//! [`notebookDocument/didChange`]: https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#notebookDocument_didChange
+//! [`notebookDocument/didSave`]: https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#notebookDocument_didSave
+//! [`notebookDocument/didClose`]: https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#notebookDocument_didClose
+//! [`LanguageServer`]: crate::LanguageServer
+//! [specification]: https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#notebookDocument_synchronization
+
+use lsp::{
+    TextDocumentContentChangeEvent,
+    TextDocumentIdentifier,
+    TextDocumentItem,
+    Url,
+    VersionedTextDocumentIdentifier,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// A notebook document.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotebookDocument {
+    /// The URI of the notebook.
+    pub uri: Url,
+    /// The type of the notebook (e.g., `jupyter-notebook`).
+    pub notebook_type: String,
+    /// The version of the notebook, which increases after each change, including undo and redo.
+    pub version: i32,
+    /// Additional metadata stored with the notebook.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Map<String, Value>>,
+    /// The cells of the notebook, in order.
+    pub cells: Vec<NotebookCell>,
+}
+
+impl NotebookDocument {
+    /// Applies the change sent in a [`notebookDocument/didChange`] notification to the notebook.
+    ///
+    /// Only the cells themselves are updated, the text of their documents is changed by the
+    /// [`NotebookDocumentCellChange::text_content`] of the event.
+    ///
+    /// [`notebookDocument/didChange`]: https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#notebookDocument_didChange
+    pub fn change(&mut self, version: i32, change: &NotebookDocumentChangeEvent) {
+        self.version = version;
+        if let Some(metadata) = &change.metadata {
+            self.metadata = Some(metadata.clone());
+        }
+
+        let cells = match &change.cells {
+            Some(cells) => cells,
+            None => return,
+        };
+        if let Some(structure) = &cells.structure {
+            let array = &structure.array;
+            let start = (array.start as usize).min(self.cells.len());
+            let end = start.saturating_add(array.delete_count as usize).min(self.cells.len());
+            self.cells.splice(start .. end, array.cells.iter().flatten().cloned());
+        }
+        for data in cells.data.iter().flatten() {
+            if let Some(cell) = self.cells.iter_mut().find(|cell| cell.document == data.document) {
+                *cell = data.clone();
+            }
+        }
+    }
+}
+
+/// A cell of a notebook document.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotebookCell {
+    /// The kind of the cell.
+    pub kind: NotebookCellKind,
+    /// The URI of the text document holding the content of the cell.
+    pub document: Url,
+    /// Additional metadata stored with the cell.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Map<String, Value>>,
+    /// Summary of the last execution of the cell, if it is a code cell which has been executed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub execution_summary: Option<ExecutionSummary>,
+}
+
+/// The kind of a notebook cell.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct NotebookCellKind(i32);
+
+impl NotebookCellKind {
+    /// A code cell, whose content is source code.
+    pub const CODE: NotebookCellKind = NotebookCellKind(2);
+    /// A markup cell, whose content is formatted source to display.
+    pub const MARKUP: NotebookCellKind = NotebookCellKind(1);
+}
+
+/// Summary of the execution of a notebook cell.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecutionSummary {
+    /// A strictly monotonically increasing value indicating the execution order of the cell.
+    pub execution_order: u32,
+    /// Whether the execution was successful, if known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub success: Option<bool>,
+}
+
+/// Identifies a notebook document.
+#[derive(Clone, Debug, Eq, Hash, PartialEq, Deserialize, Serialize)]
+pub struct NotebookDocumentIdentifier {
+    /// The URI of the notebook.
+    pub uri: Url,
+}
+
+/// Identifies a version of a notebook document.
+#[derive(Clone, Debug, Eq, Hash, PartialEq, Deserialize, Serialize)]
+pub struct VersionedNotebookDocumentIdentifier {
+    /// The version of the notebook.
+    pub version: i32,
+    /// The URI of the notebook.
+    pub uri: Url,
+}
+
+/// Parameters of the [`DidOpenNotebookDocument`] notification.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DidOpenNotebookDocumentParams {
+    /// The notebook which was opened.
+    pub notebook_document: NotebookDocument,
+    /// The text documents of the cells of the notebook.
+    pub cell_text_documents: Vec<TextDocumentItem>,
+}
+
+/// Parameters of the [`DidChangeNotebookDocument`] notification.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DidChangeNotebookDocumentParams {
+    /// The notebook which changed, with its version after the change.
+    pub notebook_document: VersionedNotebookDocumentIdentifier,
+    /// The change to the notebook.
+    pub change: NotebookDocumentChangeEvent,
+}
+
+/// A change to a notebook document.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotebookDocumentChangeEvent {
+    /// The new metadata of the notebook, if it changed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Map<String, Value>>,
+    /// The changes to the cells of the notebook, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cells: Option<NotebookDocumentCellChange>,
+}
+
+/// A change to the cells of a notebook document.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotebookDocumentCellChange {
+    /// Cells which were added or removed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub structure: Option<NotebookDocumentCellChangeStructure>,
+    /// Cells whose kind, metadata or execution summary changed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Vec<NotebookCell>>,
+    /// Changes to the text documents of the cells.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_content: Option<Vec<NotebookDocumentChangeTextContent>>,
+}
+
+/// Cells added to or removed from a notebook document.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotebookDocumentCellChangeStructure {
+    /// The change to the array of cells.
+    pub array: NotebookCellArrayChange,
+    /// The text documents of the added cells.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub did_open: Option<Vec<TextDocumentItem>>,
+    /// The text documents of the removed cells.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub did_close: Option<Vec<TextDocumentIdentifier>>,
+}
+
+/// A change to the array of cells of a notebook document, like [`Vec::splice`].
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotebookCellArrayChange {
+    /// The index of the first removed cell.
+    pub start: u32,
+    /// The number of removed cells.
+    pub delete_count: u32,
+    /// The cells inserted at `start`, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cells: Option<Vec<NotebookCell>>,
+}
+
+/// Changes to the text document of a notebook cell.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct NotebookDocumentChangeTextContent {
+    /// The text document, with its version after the changes.
+    pub document: VersionedTextDocumentIdentifier,
+    /// The changes to the text document, like those of a `textDocument/didChange` notification.
+    pub changes: Vec<TextDocumentContentChangeEvent>,
+}
+
+/// Parameters of the [`DidSaveNotebookDocument`] notification.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DidSaveNotebookDocumentParams {
+    /// The notebook which was saved.
+    pub notebook_document: NotebookDocumentIdentifier,
+}
+
+/// Parameters of the [`DidCloseNotebookDocument`] notification.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DidCloseNotebookDocumentParams {
+    /// The notebook which was closed.
+    pub notebook_document: NotebookDocumentIdentifier,
+    /// The text documents of the cells of the notebook.
+    pub cell_text_documents: Vec<TextDocumentIdentifier>,
+}
+
+/// Options of the `notebookDocumentSync` server capability.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotebookDocumentSyncOptions {
+    /// The notebooks to synchronize.
+    pub notebook_selector: Vec<NotebookSelector>,
+    /// Whether the server is interested in `notebookDocument/didSave` notifications.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub save: Option<bool>,
+}
+
+/// Selects notebooks, or the cells of notebooks, to synchronize.
+///
+/// At least one of `notebook` and `cells` must be set.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct NotebookSelector {
+    /// The notebooks to synchronize, or all notebooks if unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notebook: Option<NotebookDocumentFilter>,
+    /// The cells to synchronize, by language, or all cells if unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cells: Option<Vec<NotebookCellSelector>>,
+}
+
+/// Filters notebooks by type, URI scheme and path, any of which may be left unset.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotebookDocumentFilter {
+    /// The type of the notebooks (e.g., `jupyter-notebook`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notebook_type: Option<String>,
+    /// The URI scheme of the notebooks (e.g., `file`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scheme: Option<String>,
+    /// A glob pattern matching the paths of the notebooks.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+}
+
+/// Selects the cells of a notebook by language.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct NotebookCellSelector {
+    /// The language of the cells (e.g., `lookml`).
+    pub language: String,
+}
+
+/// The [`notebookDocument/didOpen`] notification.
+///
+/// [`notebookDocument/didOpen`]: https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#notebookDocument_didOpen
+#[derive(Debug)]
+pub enum DidOpenNotebookDocument {}
+
+impl lsp::notification::Notification for DidOpenNotebookDocument {
+    type Params = DidOpenNotebookDocumentParams;
+
+    const METHOD: &'static str = "notebookDocument/didOpen";
+}
+
+/// The [`notebookDocument/didChange`] notification.
+///
+/// [`notebookDocument/didChange`]: https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#notebookDocument_didChange
+#[derive(Debug)]
+pub enum DidChangeNotebookDocument {}
+
+impl lsp::notification::Notification for DidChangeNotebookDocument {
+    type Params = DidChangeNotebookDocumentParams;
+
+    const METHOD: &'static str = "notebookDocument/didChange";
+}
+
+/// The [`notebookDocument/didSave`] notification.
+///
+/// [`notebookDocument/didSave`]: https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#notebookDocument_didSave
+#[derive(Debug)]
+pub enum DidSaveNotebookDocument {}
+
+impl lsp::notification::Notification for DidSaveNotebookDocument {
+    type Params = DidSaveNotebookDocumentParams;
+
+    const METHOD: &'static str = "notebookDocument/didSave";
+}
+
+/// The [`notebookDocument/didClose`] notification.
+///
+/// [`notebookDocument/didClose`]: https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#notebookDocument_didClose
+#[derive(Debug)]
+pub enum DidCloseNotebookDocument {}
+
+impl lsp::notification::Notification for DidCloseNotebookDocument {
+    type Params = DidCloseNotebookDocumentParams;
+
+    const METHOD: &'static str = "notebookDocument/didClose";
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        jsonrpc::{Incoming, Outgoing, Result},
+        LanguageServer,
+        LspService,
+    };
+    use serde_json::json;
+    use std::{
+        sync::{Arc, Mutex, PoisonError},
+        task::Poll,
+    };
+    use tower_test::mock::Spawn;
+
+    #[derive(Debug)]
+    struct Mock {
+        opened: Arc<Mutex<Vec<NotebookDocument>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl LanguageServer for Mock {
+        async fn initialize(&self, _: lsp::InitializeParams) -> Result<lsp::InitializeResult> {
+            Ok(lsp::InitializeResult::default())
+        }
+
+        async fn shutdown(&self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn did_open_notebook_document(&self, params: DidOpenNotebookDocumentParams) {
+            let mut opened = self.opened.lock().unwrap_or_else(PoisonError::into_inner);
+            opened.push(params.notebook_document);
+        }
+    }
+
+    fn cell(index: u32) -> Value {
+        json!({ "kind": 2, "document": format!("vscode-notebook-cell:/model.ipynb#{}", index) })
+    }
+
+    fn notebook() -> NotebookDocument {
+        serde_json::from_value(json!({
+            "uri": "file:///model.ipynb",
+            "notebookType": "jupyter-notebook",
+            "version": 1,
+            "cells": [cell(0), cell(1), cell(2)],
+        }))
+        .unwrap()
+    }
+
+    fn documents(notebook: &NotebookDocument) -> Vec<&str> {
+        notebook
+            .cells
+            .iter()
+            .map(|cell| cell.document.fragment().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn change_structure() {
+        let mut notebook = notebook();
+        let change = serde_json::from_value(json!({
+            "cells": {
+                "structure": {
+                    "array": { "start": 1, "deleteCount": 1, "cells": [cell(3), cell(4)] },
+                    "didOpen": [],
+                    "didClose": [{ "uri": "vscode-notebook-cell:/model.ipynb#1" }],
+                },
+            },
+        }))
+        .unwrap();
+
+        notebook.change(2, &change);
+        assert_eq!(notebook.version, 2);
+        assert_eq!(documents(&notebook), vec!["0", "3", "4", "2"]);
+
+        let change = serde_json::from_value(json!({
+            "cells": { "structure": { "array": { "start": 3, "deleteCount": 5 } } },
+        }))
+        .unwrap();
+        notebook.change(3, &change);
+        assert_eq!(documents(&notebook), vec!["0", "3", "4"]);
+    }
+
+    #[test]
+    fn change_data() {
+        let mut notebook = notebook();
+        let mut markup = cell(1);
+        markup["kind"] = json!(1);
+        markup["executionSummary"] = json!({ "executionOrder": 4, "success": true });
+        let change = serde_json::from_value(json!({
+            "metadata": { "kernel": "lookml" },
+            "cells": { "data": [markup] },
+        }))
+        .unwrap();
+
+        notebook.change(2, &change);
+        assert_eq!(notebook.metadata.as_ref().unwrap()["kernel"], json!("lookml"));
+        assert_eq!(notebook.cells[0].kind, NotebookCellKind::CODE);
+        assert_eq!(notebook.cells[1].kind, NotebookCellKind::MARKUP);
+        assert_eq!(
+            notebook.cells[1].execution_summary,
+            Some(ExecutionSummary {
+                execution_order: 4,
+                success: Some(true),
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn synchronizes_notebooks() {
+        let opened = Arc::new(Mutex::new(Vec::new()));
+        let (service, _) = LspService::new({
+            let opened = opened.clone();
+            move |_| Mock { opened }
+        });
+        let options = NotebookDocumentSyncOptions {
+            notebook_selector: vec![NotebookSelector {
+                notebook: Some(NotebookDocumentFilter {
+                    notebook_type: Some("jupyter-notebook".into()),
+                    ..Default::default()
+                }),
+                cells: Some(vec![NotebookCellSelector {
+                    language: "python".into(),
+                }]),
+            }],
+            save: None,
+        };
+        let mut service = Spawn::new(service.notebook_document_sync(options));
+
+        let initialize = json!({ "jsonrpc": "2.0", "method": "initialize", "params": { "capabilities": {} }, "id": 1 });
+        let sync = json!({
+            "notebookSelector": [{ "notebook": { "notebookType": "jupyter-notebook" }, "cells": [{ "language": "python" }] }],
+        });
+        let raw = json!({ "jsonrpc": "2.0", "result": { "capabilities": { "notebookDocumentSync": sync } }, "id": 1 });
+        let ok: Outgoing = serde_json::from_value(raw).unwrap();
+        assert_eq!(service.poll_ready(), Poll::Ready(Ok(())));
+        let initialize: Incoming = serde_json::from_value(initialize).unwrap();
+        assert_eq!(service.call(initialize).await, Ok(Some(ok)));
+
+        let open: Incoming = serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "method": "notebookDocument/didOpen",
+            "params": { "notebookDocument": notebook(), "cellTextDocuments": [] },
+        }))
+        .unwrap();
+        assert_eq!(service.call(open).await, Ok(None));
+        assert_eq!(*opened.lock().unwrap(), vec![notebook()]);
+    }
+}
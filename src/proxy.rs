@@ -0,0 +1,991 @@
+//! Proxy which fans out the messages of one editor to several language servers.
+//!
+//! [`ProxyService`] takes the place of an [`LspService`] between the editor and any number of
+//! backend servers, which makes it possible to combine servers for languages embedded into each
+//! other (e.g., SQL inside of LookML). The proxy
+//!
+//! * routes requests to backends by document and, within a document, by range,
+//! * keeps track of the text documents and notebooks opened by the editor,
+//! * merges the capabilities of all backends when answering `initialize`,
+//! * combines list results (e.g., completions) and the diagnostics published by each backend, and
+//! * rewrites request IDs in both directions, so that cancellation works end to end.
+//!
+//! The parameters of requests and notifications are forwarded as they were sent by the editor,
+//! including fields unknown to [`lsp`], for which the proxy must be served with
+//! [`Server::raw_params`]. Only the parameters needed for routing are parsed.
+//!
+//! [`LspService`]: crate::LspService
+//! [`Server::raw_params`]: crate::Server::raw_params
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use lspower::{proxy::{Backend, ProxyService}, Server};
+//! use tokio::process::Command;
+//!
+//! let lookml = Backend::spawn("lookml", Command::new("lookml-language-server"))
+//!     .documents(|uri| uri.path().ends_with(".lkml"));
+//! let sql = Backend::spawn("sql", Command::new("sql-language-server"))
+//!     .documents(|uri| uri.path().ends_with(".lkml"))
+//!     .ranges(|document, range| in_sql_block(document, range.start));
+//!
+//! let (service, messages, connection) = ProxyService::new(vec![lookml, sql])?;
+//! tokio::spawn(connection);
+//! Server::new(tokio::io::stdin(), tokio::io::stdout())
+//!     .raw_params()
+//!     .interleave(messages)
+//!     .serve(service)
+//!     .await;
+//! ```
+
+mod document;
+pub(crate) mod merge;
+
+pub use self::document::Document;
+pub(crate) use self::document::OpenDocuments;
+
+#[cfg(feature = "runtime-agnostic")]
+use futures::io::{AsyncRead, AsyncWrite};
+#[cfg(feature = "runtime-tokio")]
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::{
+    generated_impl::Params,
+    jsonrpc::{ClientRequests, Error, Id, Incoming, Outgoing, Response, Result, ServerRequests},
+    language_client::{Connection, LanguageClient, RawHandler},
+    notebook::NotebookDocument,
+    server::{State, StateKind},
+    service::{ExitedError, MessageStream},
+};
+use futures::future::{self, BoxFuture, FutureExt};
+use lsp::{notification::Notification, request::Request};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    fmt::{self, Debug, Formatter},
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex, PoisonError, RwLock},
+    task::{Context, Poll},
+};
+use tower_service::Service;
+
+type DocumentFilter = Box<dyn Fn(&lsp::Url) -> bool + Send + Sync>;
+type RangeFilter = Box<dyn Fn(&Document, lsp::Range) -> bool + Send + Sync>;
+type Connect = Box<dyn FnOnce(Arc<dyn RawHandler>) -> std::io::Result<(LanguageClient, Connection)> + Send>;
+
+/// A language server behind a [`ProxyService`].
+pub struct Backend {
+    name: String,
+    connect: Connect,
+    documents: Option<DocumentFilter>,
+    ranges: Option<RangeFilter>,
+}
+
+impl Backend {
+    /// Creates a new `Backend` talking to a server through the given streams.
+    ///
+    /// Messages to the server are written to `stdin`, and messages from the server are read from
+    /// `stdout`.
+    pub fn new<N, I, O>(name: N, stdin: O, stdout: I) -> Self
+    where
+        N: Into<String>,
+        I: AsyncRead + Send + Unpin + 'static,
+        O: AsyncWrite + Send + Unpin + 'static,
+    {
+        let connect = move |handler| Ok(LanguageClient::with_raw_handler(stdin, stdout, handler));
+        Backend::with_connect(name.into(), Box::new(connect))
+    }
+
+    /// Creates a new `Backend` running the given command, connected over its standard input and
+    /// output.
+    ///
+    /// The process is spawned by [`ProxyService::new`]. Requires the `process` feature.
+    #[cfg(feature = "process")]
+    pub fn spawn<N: Into<String>>(name: N, mut command: tokio::process::Command) -> Self {
+        let connect = move |handler| LanguageClient::spawn_with_raw_handler(&mut command, handler);
+        Backend::with_connect(name.into(), Box::new(connect))
+    }
+
+    fn with_connect(name: String, connect: Connect) -> Self {
+        Backend {
+            name,
+            connect,
+            documents: None,
+            ranges: None,
+        }
+    }
+
+    /// Restricts the backend to the documents whose URI matches the given predicate.
+    ///
+    /// By default, a backend receives the messages for all documents.
+    pub fn documents<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&lsp::Url) -> bool + Send + Sync + 'static,
+    {
+        self.documents = Some(Box::new(filter));
+        self
+    }
+
+    /// Restricts the requests for a position or range in a document to those matching the given
+    /// predicate.
+    ///
+    /// Requests for a position are checked against an empty range at that position. A request is
+    /// only sent to the backends without such a predicate if no backend's predicate matches, so
+    /// that the backend for an embedded language takes precedence over the one for the enclosing
+    /// document. Notifications and requests for whole documents are not affected.
+    pub fn ranges<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&Document, lsp::Range) -> bool + Send + Sync + 'static,
+    {
+        self.ranges = Some(Box::new(filter));
+        self
+    }
+}
+
+impl Debug for Backend {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct(stringify!(Backend))
+            .field("name", &self.name)
+            .field("documents", &self.documents.is_some())
+            .field("ranges", &self.ranges.is_some())
+            .finish()
+    }
+}
+
+/// Service which proxies the messages of an editor to several backend servers.
+///
+/// See the [module documentation](self) for details.
+pub struct ProxyService {
+    inner: Arc<Inner>,
+    pending_server: ServerRequests,
+    pending_client: Arc<ClientRequests>,
+    state: Arc<State>,
+    /// Whether a warning was logged about parameters decoded into the types of [`lsp`].
+    warned_typed_params: bool,
+}
+
+struct Inner {
+    backends: Vec<BackendClient>,
+    documents: Mutex<OpenDocuments>,
+}
+
+struct BackendClient {
+    name: String,
+    client: LanguageClient,
+    documents: Option<DocumentFilter>,
+    ranges: Option<RangeFilter>,
+    capabilities: RwLock<Value>,
+}
+
+impl ProxyService {
+    /// Connects to the given backends, returning the service, the stream of messages from the
+    /// backends to the editor, and the future driving the connections to the backends.
+    ///
+    /// The connection future must be polled (e.g., spawned onto the runtime) for the backends to
+    /// receive any message. It resolves once all backends have closed their connection.
+    pub fn new<I>(backends: I) -> std::io::Result<(Self, MessageStream, Connection)>
+    where
+        I: IntoIterator<Item = Backend>,
+    {
+        let (sender, receiver) = crate::queue::channel(16, crate::OverflowPolicy::Block);
+        let pending_client = Arc::new(ClientRequests::new());
+        let editor = LanguageClient::from_parts(sender, pending_client.clone());
+        let diagnostics = Arc::new(Mutex::new(HashMap::new()));
+
+        let backends: Vec<_> = backends.into_iter().collect();
+        let count = backends.len();
+        let mut clients = Vec::with_capacity(count);
+        let mut connections = Vec::with_capacity(count);
+        for (index, backend) in backends.into_iter().enumerate() {
+            let forwarder = Forwarder {
+                index,
+                count,
+                editor: editor.clone(),
+                diagnostics: diagnostics.clone(),
+            };
+            let (client, connection) = (backend.connect)(Arc::new(forwarder))?;
+            connections.push(connection);
+            clients.push(BackendClient {
+                name: backend.name,
+                client,
+                documents: backend.documents,
+                ranges: backend.ranges,
+                capabilities: RwLock::new(Value::Null),
+            });
+        }
+
+        let service = ProxyService {
+            inner: Arc::new(Inner {
+                backends: clients,
+                documents: Default::default(),
+            }),
+            pending_server: ServerRequests::new(),
+            pending_client,
+            state: Arc::new(State::new()),
+            warned_typed_params: false,
+        };
+        let connection = Connection::new(future::join_all(connections).map(|_| ()));
+
+        Ok((service, MessageStream(receiver), connection))
+    }
+
+    /// Returns a snapshot of the documents currently opened by the editor.
+    pub fn document(&self, uri: &lsp::Url) -> Option<Document> {
+        let documents = self.inner.documents.lock().unwrap_or_else(PoisonError::into_inner);
+        documents.documents.get(uri).cloned()
+    }
+
+    /// Returns a snapshot of the notebook currently opened by the editor with the given URI.
+    pub fn notebook(&self, uri: &lsp::Url) -> Option<NotebookDocument> {
+        let documents = self.inner.documents.lock().unwrap_or_else(PoisonError::into_inner);
+        documents.notebooks.get(uri).cloned()
+    }
+
+    /// Returns a snapshot of the text documents of the cells of a notebook currently opened by the
+    /// editor, in the order of the cells.
+    pub fn notebook_cells(&self, uri: &lsp::Url) -> Option<Vec<Document>> {
+        let cells: Vec<_> = self
+            .notebook(uri)?
+            .cells
+            .into_iter()
+            .map(|cell| cell.document)
+            .collect();
+        let documents = self.inner.documents.lock().unwrap_or_else(PoisonError::into_inner);
+        Some(
+            cells
+                .iter()
+                .filter_map(|cell| documents.documents.get(cell).cloned())
+                .collect(),
+        )
+    }
+}
+
+impl Service<Incoming> for ProxyService {
+    type Error = ExitedError;
+    type Future = Pin<Box<dyn Future<Output = std::result::Result<Self::Response, Self::Error>> + Send>>;
+    type Response = Option<Outgoing>;
+
+    fn poll_ready(&mut self, cx: &mut Context) -> Poll<std::result::Result<(), Self::Error>> {
+        self.state.register(cx.waker());
+        if self.state.get() == StateKind::Exited {
+            Poll::Ready(Err(ExitedError::new(&self.state)))
+        } else {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn call(&mut self, message: Incoming) -> Self::Future {
+        if self.state.get() == StateKind::Exited {
+            return future::err(ExitedError::new(&self.state)).boxed();
+        }
+
+        let request = match message {
+            Incoming::Request(request) => request,
+            Incoming::Response(response) => {
+                self.pending_client.insert(response);
+                return future::ok(None).boxed();
+            },
+        };
+
+        let method = request.method().to_string();
+        let id = request.id().cloned();
+        let params = match request.params() {
+            Some(Params::Raw(params)) => serde_json::from_str(params.get()),
+            Some(params) => {
+                if !self.warned_typed_params {
+                    self.warned_typed_params = true;
+                    log::warn!("dropping fields unknown to lsp-types, serve the proxy with `Server::raw_params`");
+                }
+                serde_json::to_value(params)
+            },
+            None => Ok(Value::Null),
+        }
+        .map(|params| match params {
+            Value::Null => None,
+            params => Some(params),
+        });
+
+        let params = match (params, &id) {
+            (Ok(params), _) => params,
+            (Err(error), Some(id)) => {
+                let response = Response::error(Some(id.clone()), Error::invalid_params(error.to_string()));
+                return future::ok(Some(Outgoing::Response(response))).boxed();
+            },
+            (Err(error), None) => {
+                log::error!("invalid parameters for {} notification: {}", method, error);
+                return future::ok(None).boxed();
+            },
+        };
+
+        match id {
+            Some(id) => {
+                let response = match (method.as_str(), self.state.get()) {
+                    (lsp::request::Initialize::METHOD, StateKind::Uninitialized) => {
+                        self.state.set(StateKind::Initializing);
+                        self.initialize(params).boxed()
+                    },
+                    (lsp::request::Shutdown::METHOD, StateKind::Initialized) => {
+                        self.state.set(StateKind::ShutDown);
+                        self.inner
+                            .clone()
+                            .broadcast(method, params)
+                            .map(|result| result.map(|_| Value::Null))
+                            .boxed()
+                    },
+                    (_, StateKind::Uninitialized) => future::err(Error::server_not_initialized()).boxed(),
+                    (_, StateKind::Initialized) => self.inner.clone().request(method, params).boxed(),
+                    _ => future::err(Error::invalid_request()).boxed(),
+                };
+                let response = self.pending_server.execute(id, response);
+                response.map(|response| Ok(Some(Outgoing::Response(response)))).boxed()
+            },
+            None => {
+                match method.as_str() {
+                    lsp::notification::Cancel::METHOD => self.cancel(params),
+                    lsp::notification::Exit::METHOD => {
+                        log::info!("exit notification received, stopping");
+                        self.inner.notify(method, params);
+                        self.state.set(StateKind::Exited);
+                        self.pending_server.cancel_all();
+                    },
+                    _ => self.inner.notify(method, params),
+                }
+                future::ok(None).boxed()
+            },
+        }
+    }
+}
+
+impl ProxyService {
+    /// Initializes all backends, answering with their merged capabilities and the server info of
+    /// the first backend which sent one.
+    fn initialize(&self, params: Option<Value>) -> impl Future<Output = Result<Value>> + Send + 'static {
+        let inner = self.inner.clone();
+        let state = self.state.clone();
+        async move {
+            let method = lsp::request::Initialize::METHOD;
+            let results = future::join_all(
+                inner
+                    .backends
+                    .iter()
+                    .map(|backend| backend.client.raw_request(method.into(), params.clone())),
+            )
+            .await;
+
+            let mut capabilities = Vec::with_capacity(results.len());
+            let mut server_info = Value::Null;
+            for (backend, result) in inner.backends.iter().zip(results) {
+                match result {
+                    Ok(mut result) => {
+                        if server_info.is_null() {
+                            server_info = result["serverInfo"].take();
+                        }
+                        let result = result["capabilities"].take();
+                        *backend.capabilities.write().unwrap_or_else(PoisonError::into_inner) = result.clone();
+                        capabilities.push(result);
+                    },
+                    Err(error) => {
+                        log::error!("backend {:?} failed to initialize: {}", backend.name, error);
+                        state.set(StateKind::Uninitialized);
+                        return Err(error);
+                    },
+                }
+            }
+
+            state.set(StateKind::Initialized);
+            let mut result = serde_json::json!({ "capabilities": merge::capabilities(capabilities) });
+            if !server_info.is_null() {
+                result["serverInfo"] = server_info;
+            }
+            Ok(result)
+        }
+    }
+
+    /// Cancels the request referred to by a `$/cancelRequest` notification, which in turn cancels
+    /// the corresponding requests to the backends.
+    fn cancel(&self, params: Option<Value>) {
+        match params.map(serde_json::from_value::<lsp::CancelParams>) {
+            Some(Ok(lsp::CancelParams { id })) => match id {
+                lsp::NumberOrString::Number(id) if id >= 0 => self.pending_server.cancel(&Id::Number(id as u64)),
+                lsp::NumberOrString::String(id) => self.pending_server.cancel(&Id::String(id)),
+                lsp::NumberOrString::Number(id) => log::warn!("cannot cancel request with negative ID {}", id),
+            },
+            _ => log::error!("invalid parameters for $/cancelRequest notification"),
+        }
+    }
+}
+
+impl Inner {
+    /// Forwards a request to the backends it is routed to, combining their results.
+    fn request(self: Arc<Self>, method: String, mut params: Option<Value>) -> BoxFuture<'static, Result<Value>> {
+        if merge::RESOLVE_REQUESTS.contains(&method.as_str()) {
+            if let Some(backend) = params.as_mut().and_then(merge::untag) {
+                return async move {
+                    let client = match self.backends.get(backend) {
+                        Some(backend) => &backend.client,
+                        None => return Err(Error::invalid_params("Unknown backend")),
+                    };
+                    let mut result = client.raw_request(method, params).await?;
+                    merge::tag(&mut result, backend);
+                    Ok(result)
+                }
+                .boxed();
+            }
+        }
+
+        let backends = self.route(&method, params.as_ref(), true);
+        async move {
+            let results = future::join_all(backends.iter().map(|&backend| {
+                let client = &self.backends[backend].client;
+                client.raw_request(method.clone(), params.clone())
+            }))
+            .await;
+
+            let mut first_error = None;
+            let mut values = Vec::with_capacity(results.len());
+            for (&backend, result) in backends.iter().zip(results) {
+                match result {
+                    Ok(mut value) => {
+                        if merge::RESOLVABLE_REQUESTS.contains(&method.as_str()) {
+                            merge::tag_items(&mut value, backend);
+                        }
+                        values.push(value);
+                    },
+                    Err(error) => {
+                        log::warn!(
+                            "backend {:?} failed to handle {}: {}",
+                            self.backends[backend].name,
+                            method,
+                            error
+                        );
+                        first_error.get_or_insert(error);
+                    },
+                }
+            }
+
+            match first_error {
+                Some(error) if values.is_empty() => Err(error),
+                _ => Ok(merge::results(values)),
+            }
+        }
+        .boxed()
+    }
+
+    /// Forwards a request to all backends, returning the first error if any of them fails.
+    async fn broadcast(self: Arc<Self>, method: String, params: Option<Value>) -> Result<()> {
+        let results = future::join_all(
+            self.backends
+                .iter()
+                .map(|backend| backend.client.raw_request(method.clone(), params.clone())),
+        )
+        .await;
+        results.into_iter().try_for_each(|result| result.map(|_| ()))
+    }
+
+    /// Forwards a notification to the backends it is routed to, keeping track of the documents and
+    /// notebooks opened by the editor.
+    fn notify(&self, method: String, params: Option<Value>) {
+        self.track_document(&method, params.as_ref());
+        for backend in self.route(&method, params.as_ref(), false) {
+            let backend = &self.backends[backend];
+            if let Err(error) = backend.client.raw_notification(method.clone(), params.clone()) {
+                log::warn!("failed to notify backend {:?}: {}", backend.name, error);
+            }
+        }
+    }
+
+    fn track_document(&self, method: &str, params: Option<&Value>) {
+        if let Some(params) = params {
+            let mut documents = self.documents.lock().unwrap_or_else(PoisonError::into_inner);
+            documents.track(method, params.clone());
+        }
+    }
+
+    /// Returns the indices of the backends which a message should be sent to.
+    fn route(&self, method: &str, params: Option<&Value>, by_range: bool) -> Vec<usize> {
+        let all = 0 .. self.backends.len();
+
+        if method == lsp::request::ExecuteCommand::METHOD {
+            let command = params.and_then(|params| params.get("command"));
+            let owners: Vec<_> = all
+                .clone()
+                .filter(|&index| {
+                    let capabilities = self.backends[index].capabilities.read();
+                    let capabilities = capabilities.unwrap_or_else(PoisonError::into_inner);
+                    let commands = capabilities["executeCommandProvider"]["commands"].as_array();
+                    matches!((command, commands), (Some(command), Some(commands)) if commands.contains(command))
+                })
+                .collect();
+            return if owners.is_empty() { all.collect() } else { owners };
+        }
+
+        let uri = params
+            .and_then(|params| {
+                let notebook = &params["notebookDocument"]["uri"];
+                params["textDocument"]["uri"].as_str().or_else(|| notebook.as_str())
+            })
+            .and_then(|uri| lsp::Url::parse(uri).ok());
+        let uri = match uri {
+            Some(uri) => uri,
+            None => return all.collect(),
+        };
+
+        let candidates: Vec<_> = all
+            .filter(|&index| match &self.backends[index].documents {
+                Some(filter) => filter(&uri),
+                None => true,
+            })
+            .collect();
+        let range = params.and_then(|params| match (params.get("range"), params.get("position")) {
+            (Some(range), _) => serde_json::from_value::<lsp::Range>(range.clone()).ok(),
+            (None, Some(position)) => {
+                let position = serde_json::from_value::<lsp::Position>(position.clone()).ok()?;
+                Some(lsp::Range::new(position, position))
+            },
+            (None, None) => None,
+        });
+        let range = match range {
+            Some(range) if by_range => range,
+            _ => return candidates,
+        };
+
+        let documents = self.documents.lock().unwrap_or_else(PoisonError::into_inner);
+        let document = documents.documents.get(&uri);
+        let specific: Vec<_> = candidates
+            .iter()
+            .copied()
+            .filter(|&index| match (&self.backends[index].ranges, document) {
+                (Some(filter), Some(document)) => filter(document, range),
+                _ => false,
+            })
+            .collect();
+        if specific.is_empty() {
+            candidates
+                .into_iter()
+                .filter(|&index| self.backends[index].ranges.is_none())
+                .collect()
+        } else {
+            specific
+        }
+    }
+}
+
+impl Debug for ProxyService {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let backends: Vec<_> = self.inner.backends.iter().map(|backend| &backend.name).collect();
+        f.debug_struct(stringify!(ProxyService))
+            .field("backends", &backends)
+            .field("pending_server", &self.pending_server)
+            .field("pending_client", &self.pending_client)
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+/// Handler passing the messages of one backend on to the editor.
+struct Forwarder {
+    index: usize,
+    count: usize,
+    editor: LanguageClient,
+    diagnostics: Arc<Mutex<HashMap<lsp::Url, Vec<Vec<lsp::Diagnostic>>>>>,
+}
+
+impl Forwarder {
+    /// Merges the diagnostics published by this backend with those of the other backends.
+    fn publish_diagnostics(&self, params: lsp::PublishDiagnosticsParams) -> lsp::PublishDiagnosticsParams {
+        let mut diagnostics = self.diagnostics.lock().unwrap_or_else(PoisonError::into_inner);
+        let published = diagnostics
+            .entry(params.uri.clone())
+            .or_insert_with(|| vec![Vec::new(); self.count]);
+        published[self.index] = params.diagnostics;
+        let merged = published.concat();
+        if merged.is_empty() {
+            diagnostics.remove(&params.uri);
+        }
+
+        lsp::PublishDiagnosticsParams {
+            uri: params.uri,
+            diagnostics: merged,
+            version: params.version,
+        }
+    }
+}
+
+impl RawHandler for Forwarder {
+    fn request(self: Arc<Self>, method: String, params: Option<Value>) -> BoxFuture<'static, Result<Value>> {
+        async move { self.editor.raw_request(method, params).await }.boxed()
+    }
+
+    fn notification(self: Arc<Self>, method: String, mut params: Option<Value>) -> BoxFuture<'static, ()> {
+        if method == lsp::notification::PublishDiagnostics::METHOD {
+            let published = params
+                .take()
+                .map(serde_json::from_value::<lsp::PublishDiagnosticsParams>);
+            params = match published {
+                Some(Ok(published)) => serde_json::to_value(self.publish_diagnostics(published)).ok(),
+                _ => {
+                    log::error!("invalid parameters for {} notification", method);
+                    return future::ready(()).boxed();
+                },
+            };
+        }
+        if let Err(error) = self.editor.raw_notification(method, params) {
+            log::warn!("failed to notify the editor: {}", error);
+        }
+        future::ready(()).boxed()
+    }
+}
+
+#[cfg(all(test, feature = "runtime-tokio"))]
+mod tests {
+    use super::*;
+    use crate::{language_client::Handler, LanguageServer, LspService, ServeOutcome, Server};
+    use async_trait::async_trait;
+    use futures::{channel::mpsc, StreamExt};
+    use serde_json::json;
+
+    #[derive(Debug)]
+    struct Mock {
+        name: &'static str,
+        client: crate::Client,
+    }
+
+    #[async_trait]
+    impl LanguageServer for Mock {
+        async fn initialize(&self, _: lsp::InitializeParams) -> Result<lsp::InitializeResult> {
+            Ok(serde_json::from_value(json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "hoverProvider": true,
+                    "documentSymbolProvider": true,
+                    "completionProvider": { "triggerCharacters": [self.name], "resolveProvider": true },
+                    "executeCommandProvider": { "commands": [format!("{}.run", self.name)] },
+                },
+                "serverInfo": { "name": self.name },
+            }))
+            .unwrap())
+        }
+
+        async fn initialized(&self, _: lsp::InitializedParams) {
+            let folders = self.client.workspace_folders().await.unwrap().unwrap_or_default();
+            let names: Vec<_> = folders.into_iter().map(|folder| folder.name).collect();
+            self.client.log_message(lsp::MessageType::Info, names.join(", ")).await;
+        }
+
+        async fn shutdown(&self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn did_open(&self, params: lsp::DidOpenTextDocumentParams) {
+            let range = lsp::Range::default();
+            let diagnostic = lsp::Diagnostic::new_simple(range, self.name.into());
+            let uri = params.text_document.uri;
+            self.client.publish_diagnostics(uri, vec![diagnostic], None).await;
+        }
+
+        async fn hover(&self, _: lsp::HoverParams) -> Result<Option<lsp::Hover>> {
+            Ok(Some(lsp::Hover {
+                contents: lsp::HoverContents::Scalar(lsp::MarkedString::String(self.name.into())),
+                range: None,
+            }))
+        }
+
+        async fn document_symbol(
+            &self,
+            params: lsp::DocumentSymbolParams,
+        ) -> Result<Option<lsp::DocumentSymbolResponse>> {
+            Ok(Some(lsp::DocumentSymbolResponse::Flat(vec![serde_json::from_value(
+                json!({
+                    "name": self.name,
+                    "kind": 5,
+                    "location": { "uri": params.text_document.uri, "range": lsp::Range::default() },
+                }),
+            )
+            .unwrap()])))
+        }
+
+        async fn completion(&self, _: lsp::CompletionParams) -> Result<Option<lsp::CompletionResponse>> {
+            let item = serde_json::from_value(json!({ "label": self.name, "data": 42 })).unwrap();
+            Ok(Some(lsp::CompletionResponse::Array(vec![item])))
+        }
+
+        async fn completion_resolve(&self, mut item: lsp::CompletionItem) -> Result<lsp::CompletionItem> {
+            assert_eq!(item.data, Some(json!(42)));
+            item.detail = Some(self.name.into());
+            Ok(item)
+        }
+
+        async fn execute_command(&self, _: lsp::ExecuteCommandParams) -> Result<Option<Value>> {
+            Ok(Some(json!(self.name)))
+        }
+    }
+
+    struct Editor {
+        log: mpsc::UnboundedSender<String>,
+        diagnostics: mpsc::UnboundedSender<Vec<lsp::Diagnostic>>,
+    }
+
+    #[async_trait]
+    impl Handler for Editor {
+        async fn workspace_folders(&self) -> Result<Option<Vec<lsp::WorkspaceFolder>>> {
+            Ok(Some(vec![lsp::WorkspaceFolder {
+                uri: lsp::Url::parse("inmemory::///workspace").unwrap(),
+                name: "workspace".into(),
+            }]))
+        }
+
+        async fn publish_diagnostics(&self, params: lsp::PublishDiagnosticsParams) {
+            self.diagnostics.unbounded_send(params.diagnostics).unwrap();
+        }
+
+        async fn log_message(&self, params: lsp::LogMessageParams) {
+            self.log.unbounded_send(params.message).unwrap();
+        }
+    }
+
+    fn backend(name: &'static str) -> (Backend, tokio::task::JoinHandle<ServeOutcome>) {
+        let (server_stdin, proxy_stdin) = tokio::io::duplex(1024);
+        let (proxy_stdout, server_stdout) = tokio::io::duplex(1024);
+
+        let (service, messages) = LspService::new(move |client| Mock { name, client });
+        let server = Server::new(server_stdin, server_stdout).interleave(messages);
+        let server = tokio::spawn(server.serve(service));
+        (Backend::new(name, proxy_stdin, proxy_stdout), server)
+    }
+
+    fn position(line: u32, character: u32) -> lsp::TextDocumentPositionParams {
+        lsp::TextDocumentPositionParams {
+            text_document: lsp::TextDocumentIdentifier {
+                uri: lsp::Url::parse("inmemory::///view.lkml").unwrap(),
+            },
+            position: lsp::Position::new(line, character),
+        }
+    }
+
+    async fn hover(editor: &LanguageClient, line: u32) -> lsp::HoverContents {
+        let params = lsp::HoverParams {
+            text_document_position_params: position(line, 4),
+            work_done_progress_params: Default::default(),
+        };
+        let hover = editor.request::<lsp::request::HoverRequest>(params).await.unwrap();
+        hover.unwrap().contents
+    }
+
+    fn contents(name: &str) -> lsp::HoverContents {
+        lsp::HoverContents::Scalar(lsp::MarkedString::String(name.into()))
+    }
+
+    #[tokio::test]
+    async fn forwards_unknown_fields() {
+        use tokio::io::AsyncReadExt;
+
+        let (mut server_stdin, proxy_stdin) = tokio::io::duplex(1024);
+        let (proxy_stdout, _server_stdout) = tokio::io::duplex(1024);
+        let backend = Backend::new("raw", proxy_stdin, proxy_stdout);
+        let (mut service, _, connection) = ProxyService::new(vec![backend]).unwrap();
+        let _connection = tokio::spawn(connection);
+
+        let params = json!({ "capabilities": { "textDocument": { "inlineCompletion": {} } } });
+        let request = json!({ "jsonrpc": "2.0", "method": "initialize", "params": params, "id": 1 });
+        let _response = tokio::spawn(service.call(serde_json::from_value(request).unwrap()));
+
+        let mut buf = vec![0; 1024];
+        let n = server_stdin.read(&mut buf).await.unwrap();
+        let message = String::from_utf8_lossy(&buf[.. n]);
+        assert!(message.contains(r#""inlineCompletion":{}"#), "{}", message);
+    }
+
+    #[tokio::test]
+    async fn multiplexes_backends() {
+        let (lookml, lookml_server) = backend("lookml");
+        let (sql, sql_server) = backend("sql");
+        let sql = sql.ranges(|document, range| {
+            let line = document.text().lines().nth(range.start.line as usize);
+            matches!(line, Some(line) if line.contains("sql:"))
+        });
+        let (service, messages, connection) = ProxyService::new(vec![lookml, sql]).unwrap();
+        let connection = tokio::spawn(connection);
+
+        let (proxy_stdin, editor_stdin) = tokio::io::duplex(1024);
+        let (editor_stdout, proxy_stdout) = tokio::io::duplex(1024);
+        let proxy = Server::new(proxy_stdin, proxy_stdout).raw_params().interleave(messages);
+        let proxy = tokio::spawn(proxy.serve(service));
+
+        let (log, mut logged) = mpsc::unbounded();
+        let (diagnostics, mut published) = mpsc::unbounded();
+        let (editor, editor_connection) = LanguageClient::new(editor_stdin, editor_stdout, Editor { log, diagnostics });
+        let editor_connection = tokio::spawn(editor_connection);
+
+        let params = serde_json::from_value(json!({
+            "capabilities": { "workspace": { "workspaceFolders": true } },
+        }))
+        .unwrap();
+        let result = editor.initialize(params).await.unwrap();
+        assert_eq!(result.server_info.unwrap().name, "lookml");
+        let completion = result.capabilities.completion_provider.unwrap();
+        assert_eq!(completion.trigger_characters, Some(vec!["lookml".into(), "sql".into()]));
+        assert_eq!(completion.resolve_provider, Some(true));
+        let commands = result.capabilities.execute_command_provider.unwrap().commands;
+        assert_eq!(commands, vec!["lookml.run".to_string(), "sql.run".to_string()]);
+        assert_eq!(logged.next().await.as_deref(), Some("workspace"));
+        assert_eq!(logged.next().await.as_deref(), Some("workspace"));
+
+        editor
+            .notify::<lsp::notification::DidOpenTextDocument>(lsp::DidOpenTextDocumentParams {
+                text_document: lsp::TextDocumentItem {
+                    uri: position(0, 0).text_document.uri,
+                    language_id: "lookml".into(),
+                    version: 0,
+                    text: "view: users {\n  sql: SELECT 1 ;;\n}\n".into(),
+                },
+            })
+            .await
+            .unwrap();
+        let mut messages = Vec::new();
+        while messages.len() < 2 {
+            let diagnostics = published.next().await.unwrap();
+            messages = diagnostics.into_iter().map(|diagnostic| diagnostic.message).collect();
+        }
+        messages.sort();
+        assert_eq!(messages, vec!["lookml".to_string(), "sql".to_string()]);
+
+        assert_eq!(hover(&editor, 0).await, contents("lookml"));
+        assert_eq!(hover(&editor, 1).await, contents("sql"));
+
+        let params = lsp::DocumentSymbolParams {
+            text_document: position(0, 0).text_document,
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let symbols = editor
+            .request::<lsp::request::DocumentSymbolRequest>(params)
+            .await
+            .unwrap();
+        let names: Vec<_> = match symbols {
+            Some(lsp::DocumentSymbolResponse::Flat(symbols)) => symbols.into_iter().map(|symbol| symbol.name).collect(),
+            _ => panic!("expected flat document symbols"),
+        };
+        assert_eq!(names, vec!["lookml".to_string(), "sql".to_string()]);
+
+        let params = lsp::CompletionParams {
+            text_document_position: position(1, 4),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+            context: None,
+        };
+        let items = match editor.request::<lsp::request::Completion>(params).await.unwrap() {
+            Some(lsp::CompletionResponse::Array(items)) => items,
+            _ => panic!("expected completion items"),
+        };
+        assert_eq!(items.len(), 1);
+        let item = editor
+            .request::<lsp::request::ResolveCompletionItem>(items[0].clone())
+            .await
+            .unwrap();
+        assert_eq!(item.detail.as_deref(), Some("sql"));
+        assert_eq!(item.data, items[0].data);
+
+        let params = lsp::ExecuteCommandParams {
+            command: "sql.run".into(),
+            arguments: vec![],
+            work_done_progress_params: Default::default(),
+        };
+        let result = editor.request::<lsp::request::ExecuteCommand>(params).await.unwrap();
+        assert_eq!(result, Some(json!("sql")));
+
+        editor
+            .notify::<lsp::notification::DidChangeTextDocument>(lsp::DidChangeTextDocumentParams {
+                text_document: lsp::VersionedTextDocumentIdentifier {
+                    uri: position(0, 0).text_document.uri,
+                    version: 1,
+                },
+                content_changes: vec![lsp::TextDocumentContentChangeEvent {
+                    range: Some(lsp::Range::new(lsp::Position::new(1, 2), lsp::Position::new(1, 6))),
+                    range_length: None,
+                    text: "label:".into(),
+                }],
+            })
+            .await
+            .unwrap();
+        assert_eq!(hover(&editor, 1).await, contents("lookml"));
+
+        editor.shutdown().await.unwrap();
+        assert!(matches!(proxy.await.unwrap(), ServeOutcome::Exited));
+        assert!(matches!(lookml_server.await.unwrap(), ServeOutcome::Exited));
+        assert!(matches!(sql_server.await.unwrap(), ServeOutcome::Exited));
+        connection.await.unwrap();
+        editor_connection.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn rejects_requests_before_initialize() {
+        let (lookml, _) = backend("lookml");
+        let (mut service, ..) = ProxyService::new(vec![lookml]).unwrap();
+
+        let request = serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/hover",
+            "params": position(0, 0),
+            "id": 1,
+        }))
+        .unwrap();
+        let response = service.call(request).await.unwrap();
+        let expected = Response::error(Some(Id::Number(1)), Error::server_not_initialized());
+        assert_eq!(response, Some(Outgoing::Response(expected)));
+    }
+
+    #[tokio::test]
+    async fn tracks_notebooks() {
+        let (lookml, _) = backend("lookml");
+        let (service, ..) = ProxyService::new(vec![lookml]).unwrap();
+        let uri: lsp::Url = "file:///model.ipynb".parse().unwrap();
+        let cell = |index: u32| format!("vscode-notebook-cell:/model.ipynb#{}", index);
+        let item =
+            |index: u32, text: &str| json!({ "uri": cell(index), "languageId": "lookml", "version": 1, "text": text });
+        let texts = |service: &ProxyService| {
+            let cells = service.notebook_cells(&uri)?;
+            Some(cells.iter().map(|cell| cell.text().to_owned()).collect::<Vec<_>>())
+        };
+
+        let open = json!({
+            "notebookDocument": {
+                "uri": uri,
+                "notebookType": "jupyter-notebook",
+                "version": 1,
+                "cells": [{ "kind": 2, "document": cell(0) }, { "kind": 2, "document": cell(1) }],
+            },
+            "cellTextDocuments": [item(0, "view: a {}"), item(1, "view: b {}")],
+        });
+        service.inner.track_document("notebookDocument/didOpen", Some(&open));
+        assert_eq!(texts(&service).unwrap(), vec!["view: a {}", "view: b {}"]);
+
+        let change = json!({
+            "notebookDocument": { "uri": uri, "version": 2 },
+            "change": {
+                "cells": {
+                    "structure": {
+                        "array": { "start": 0, "deleteCount": 1, "cells": [{ "kind": 1, "document": cell(2) }] },
+                        "didOpen": [item(2, "# Views")],
+                        "didClose": [{ "uri": cell(0) }],
+                    },
+                    "textContent": [{
+                        "document": { "uri": cell(1), "version": 2 },
+                        "changes": [{ "text": "view: c {}" }],
+                    }],
+                },
+            },
+        });
+        service
+            .inner
+            .track_document("notebookDocument/didChange", Some(&change));
+        assert_eq!(service.notebook(&uri).unwrap().version, 2);
+        assert_eq!(texts(&service).unwrap(), vec!["# Views", "view: c {}"]);
+        assert!(service.document(&cell(0).parse().unwrap()).is_none());
+
+        let close = json!({ "notebookDocument": { "uri": uri }, "cellTextDocuments": [{ "uri": cell(1) }, { "uri": cell(2) }] });
+        service.inner.track_document("notebookDocument/didClose", Some(&close));
+        assert!(service.notebook(&uri).is_none());
+        assert!(service.document(&cell(1).parse().unwrap()).is_none());
+    }
+}
@@ -0,0 +1,226 @@
+//! Text documents opened by the editor.
+
+use crate::notebook::NotebookDocument;
+use lsp::{notification::Notification, Url};
+use serde_json::Value;
+use std::{collections::HashMap, convert::TryFrom};
+
+/// A text document opened by the editor, as seen by the proxy.
+///
+/// The proxy keeps the text of every open document up to date, so that backends can be chosen
+/// based on the content surrounding a request (see [`Backend::ranges`]).
+///
+/// [`Backend::ranges`]: super::Backend::ranges
+#[derive(Clone, Debug, PartialEq)]
+pub struct Document {
+    uri: lsp::Url,
+    language_id: String,
+    version: i32,
+    text: String,
+}
+
+impl Document {
+    pub(crate) fn new(item: lsp::TextDocumentItem) -> Self {
+        Document {
+            uri: item.uri,
+            language_id: item.language_id,
+            version: item.version,
+            text: item.text,
+        }
+    }
+
+    /// Returns the URI of the document.
+    pub fn uri(&self) -> &lsp::Url {
+        &self.uri
+    }
+
+    /// Returns the language identifier announced by the editor.
+    pub fn language_id(&self) -> &str {
+        &self.language_id
+    }
+
+    /// Returns the version of the document.
+    pub fn version(&self) -> i32 {
+        self.version
+    }
+
+    /// Returns the current text of the document.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Returns the byte offset into [`Document::text`] of the given position.
+    ///
+    /// Characters past the end of a line refer to the end of that line. Returns `None` if the line
+    /// does not exist.
+    pub fn offset(&self, position: lsp::Position) -> Option<usize> {
+        let mut line_start = 0;
+        for _ in 0 .. position.line {
+            line_start += self.text[line_start ..].find('\n')? + 1;
+        }
+
+        let line = &self.text[line_start ..];
+        let line = &line[.. line.find('\n').unwrap_or(line.len())];
+        let character = usize::try_from(position.character).unwrap_or(usize::MAX);
+        let mut units = 0;
+        for (index, c) in line.char_indices() {
+            if units >= character {
+                return Some(line_start + index);
+            }
+            units += c.len_utf16();
+        }
+        Some(line_start + line.len())
+    }
+
+    /// Applies the changes sent by the editor in a `textDocument/didChange` notification.
+    pub(crate) fn change(&mut self, params: lsp::DidChangeTextDocumentParams) {
+        self.version = params.text_document.version;
+        for change in params.content_changes {
+            let range = change
+                .range
+                .and_then(|range| Some((self.offset(range.start)?, self.offset(range.end)?)));
+            match range {
+                Some((start, end)) if start <= end => self.text.replace_range(start .. end, &change.text),
+                Some(_) => log::warn!("ignoring change with inverted range in {}", self.uri),
+                None => self.text = change.text,
+            }
+        }
+    }
+}
+
+/// The text documents and notebooks opened by the editor, kept up to date with the
+/// synchronization notifications.
+#[derive(Debug, Default)]
+pub(crate) struct OpenDocuments {
+    pub(crate) documents: HashMap<Url, Document>,
+    pub(crate) notebooks: HashMap<Url, NotebookDocument>,
+}
+
+impl OpenDocuments {
+    /// Applies a synchronization notification sent by the editor, ignoring other messages.
+    pub(crate) fn track(&mut self, method: &str, params: Value) {
+        use crate::notebook::{DidChangeNotebookDocument, DidCloseNotebookDocument, DidOpenNotebookDocument};
+        use lsp::notification::{DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument};
+
+        let documents = &mut self.documents;
+        match method {
+            DidOpenTextDocument::METHOD => {
+                if let Ok(params) = serde_json::from_value::<lsp::DidOpenTextDocumentParams>(params) {
+                    let document = Document::new(params.text_document);
+                    documents.insert(document.uri().clone(), document);
+                }
+            },
+            DidChangeTextDocument::METHOD => {
+                if let Ok(params) = serde_json::from_value::<lsp::DidChangeTextDocumentParams>(params) {
+                    if let Some(document) = documents.get_mut(&params.text_document.uri) {
+                        document.change(params);
+                    }
+                }
+            },
+            DidCloseTextDocument::METHOD => {
+                if let Ok(params) = serde_json::from_value::<lsp::DidCloseTextDocumentParams>(params) {
+                    documents.remove(&params.text_document.uri);
+                }
+            },
+            // The cells of notebooks are tracked as documents of their own.
+            DidOpenNotebookDocument::METHOD => {
+                if let Ok(params) = serde_json::from_value::<crate::notebook::DidOpenNotebookDocumentParams>(params) {
+                    for item in params.cell_text_documents {
+                        documents.insert(item.uri.clone(), Document::new(item));
+                    }
+                    let notebook = params.notebook_document;
+                    self.notebooks.insert(notebook.uri.clone(), notebook);
+                }
+            },
+            DidChangeNotebookDocument::METHOD => {
+                if let Ok(params) = serde_json::from_value::<crate::notebook::DidChangeNotebookDocumentParams>(params) {
+                    let cells = params.change.cells.as_ref();
+                    if let Some(structure) = cells.and_then(|cells| cells.structure.as_ref()) {
+                        for cell in structure.did_close.iter().flatten() {
+                            documents.remove(&cell.uri);
+                        }
+                        for item in structure.did_open.iter().flatten() {
+                            documents.insert(item.uri.clone(), Document::new(item.clone()));
+                        }
+                    }
+                    for content in cells
+                        .and_then(|cells| cells.text_content.as_ref())
+                        .into_iter()
+                        .flatten()
+                    {
+                        if let Some(document) = documents.get_mut(&content.document.uri) {
+                            document.change(lsp::DidChangeTextDocumentParams {
+                                text_document: content.document.clone(),
+                                content_changes: content.changes.clone(),
+                            });
+                        }
+                    }
+
+                    if let Some(notebook) = self.notebooks.get_mut(&params.notebook_document.uri) {
+                        notebook.change(params.notebook_document.version, &params.change);
+                    }
+                }
+            },
+            DidCloseNotebookDocument::METHOD => {
+                if let Ok(params) = serde_json::from_value::<crate::notebook::DidCloseNotebookDocumentParams>(params) {
+                    for cell in params.cell_text_documents {
+                        documents.remove(&cell.uri);
+                    }
+                    self.notebooks.remove(&params.notebook_document.uri);
+                }
+            },
+            _ => {},
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(text: &str) -> Document {
+        Document::new(lsp::TextDocumentItem {
+            uri: lsp::Url::parse("inmemory::///test").unwrap(),
+            language_id: "lookml".into(),
+            version: 0,
+            text: text.into(),
+        })
+    }
+
+    #[test]
+    fn offset() {
+        let document = document("a\u{1F600}b\ncd\n");
+        assert_eq!(document.offset(lsp::Position::new(0, 0)), Some(0));
+        assert_eq!(document.offset(lsp::Position::new(0, 1)), Some(1));
+        assert_eq!(document.offset(lsp::Position::new(0, 3)), Some(5));
+        assert_eq!(document.offset(lsp::Position::new(0, 9)), Some(6));
+        assert_eq!(document.offset(lsp::Position::new(1, 1)), Some(8));
+        assert_eq!(document.offset(lsp::Position::new(2, 0)), Some(10));
+        assert_eq!(document.offset(lsp::Position::new(3, 0)), None);
+    }
+
+    #[test]
+    fn change() {
+        let mut document = document("sql: SELECT 1 ;;\n");
+        document.change(lsp::DidChangeTextDocumentParams {
+            text_document: lsp::VersionedTextDocumentIdentifier {
+                uri: document.uri().clone(),
+                version: 1,
+            },
+            content_changes: vec![
+                lsp::TextDocumentContentChangeEvent {
+                    range: Some(lsp::Range::new(lsp::Position::new(0, 12), lsp::Position::new(0, 13))),
+                    range_length: None,
+                    text: "2".into(),
+                },
+                lsp::TextDocumentContentChangeEvent {
+                    range: Some(lsp::Range::new(lsp::Position::new(1, 0), lsp::Position::new(1, 0))),
+                    range_length: None,
+                    text: "x".into(),
+                },
+            ],
+        });
+        assert_eq!(document.text(), "sql: SELECT 2 ;;\nx");
+        assert_eq!(document.version(), 1);
+    }
+}
@@ -0,0 +1,218 @@
+//! Merging of the capabilities and results of several backends.
+
+use lsp::request::Request;
+use serde_json::{json, Map, Value};
+
+/// Requests whose results contain items which the editor may resolve later.
+pub(crate) const RESOLVABLE_REQUESTS: [&str; 5] = [
+    lsp::request::Completion::METHOD,
+    lsp::request::CodeLensRequest::METHOD,
+    lsp::request::DocumentLinkRequest::METHOD,
+    lsp::request::CodeActionRequest::METHOD,
+    lsp::request::WorkspaceSymbol::METHOD,
+];
+
+/// Requests which resolve an item returned by one of the [`RESOLVABLE_REQUESTS`].
+pub(crate) const RESOLVE_REQUESTS: [&str; 5] = [
+    lsp::request::ResolveCompletionItem::METHOD,
+    lsp::request::CodeLensResolve::METHOD,
+    lsp::request::DocumentLinkResolve::METHOD,
+    lsp::request::CodeActionResolveRequest::METHOD,
+    crate::workspace_symbol::WorkspaceSymbolResolve::METHOD,
+];
+
+/// Key under which the index of the originating backend is stored in the `data` field of items
+/// which may be resolved later.
+const BACKEND_KEY: &str = "lspower.backend";
+
+/// Merges the capabilities of all backends into the capabilities announced to the editor.
+///
+/// Enabled features and registration options are combined, and lists such as trigger characters
+/// or commands are united. Documents are synchronized in full if any backend asks for it, since
+/// backends asking for incremental changes also accept full ones.
+pub(crate) fn capabilities(backends: Vec<Value>) -> Value {
+    let mut merged = Value::Object(Map::new());
+    let mut change = None;
+
+    for mut capabilities in backends {
+        if let Some(sync) = capabilities.get_mut("textDocumentSync") {
+            if let Some(kind) = sync.as_u64() {
+                *sync = json!({ "openClose": kind > 0, "change": kind });
+            }
+            match sync.get("change").and_then(Value::as_u64) {
+                Some(kind) if kind > 0 => change = Some(change.map_or(kind, |change: u64| change.min(kind))),
+                _ => {},
+            }
+        }
+        merge(&mut merged, capabilities);
+    }
+
+    if let Some(sync) = merged.get_mut("textDocumentSync") {
+        sync["change"] = json!(change.unwrap_or(0));
+    }
+    merged
+}
+
+/// Recursively merges `from` into `into`, with the values already present in `into` taking
+/// precedence over conflicting ones.
+fn merge(into: &mut Value, from: Value) {
+    match (into, from) {
+        (Value::Object(into), Value::Object(from)) => {
+            for (key, value) in from {
+                match into.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        into.insert(key, value);
+                    },
+                }
+            }
+        },
+        (Value::Array(into), Value::Array(from)) => {
+            for value in from {
+                if !into.contains(&value) {
+                    into.push(value);
+                }
+            }
+        },
+        (into @ Value::Null, from) | (into @ Value::Bool(false), from) => *into = from,
+        (into @ Value::Bool(true), from @ Value::Object(_)) => *into = from,
+        _ => {},
+    }
+}
+
+/// Combines the results of several backends answering the same request.
+///
+/// Lists are concatenated, including completion lists (which are incomplete if any of them is).
+/// For any other kind of result, the first one which is not `null` wins.
+pub(crate) fn results(results: Vec<Value>) -> Value {
+    let mut results = results.into_iter().filter(|result| !result.is_null()).peekable();
+    let first = match results.peek() {
+        Some(first) => first.clone(),
+        None => return Value::Null,
+    };
+    if !first.is_array() && first.get("items").is_none() {
+        return first;
+    }
+
+    let mut items = Vec::new();
+    let mut is_list = false;
+    let mut is_incomplete = false;
+    for result in results {
+        match result {
+            Value::Array(result) => items.extend(result),
+            Value::Object(mut list) => {
+                is_list = true;
+                is_incomplete |= list.get("isIncomplete").and_then(Value::as_bool).unwrap_or(false);
+                if let Some(Value::Array(result)) = list.remove("items") {
+                    items.extend(result);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    if is_list {
+        json!({ "isIncomplete": is_incomplete, "items": items })
+    } else {
+        Value::Array(items)
+    }
+}
+
+/// Records the originating backend in the `data` field of every item of a result, so that a later
+/// resolve request for the item can be routed back to it.
+pub(crate) fn tag_items(result: &mut Value, backend: usize) {
+    let items = match result {
+        Value::Array(items) => items,
+        Value::Object(list) => match list.get_mut("items") {
+            Some(Value::Array(items)) => items,
+            _ => return tag(result, backend),
+        },
+        _ => return,
+    };
+    for item in items {
+        tag(item, backend);
+    }
+}
+
+/// Records the originating backend in the `data` field of a single item.
+pub(crate) fn tag(item: &mut Value, backend: usize) {
+    if let Value::Object(item) = item {
+        let data = item.remove("data").unwrap_or(Value::Null);
+        item.insert("data".into(), json!({ BACKEND_KEY: backend, "data": data }));
+    }
+}
+
+/// Restores the original `data` field of an item tagged by [`tag`], returning the index of the
+/// originating backend.
+pub(crate) fn untag(item: &mut Value) -> Option<usize> {
+    let data = item.get_mut("data")?;
+    let backend = data.get(BACKEND_KEY)?.as_u64()? as usize;
+    *data = data.get_mut("data").map(Value::take).unwrap_or(Value::Null);
+    if data.is_null() {
+        item.as_object_mut()?.remove("data");
+    }
+    Some(backend)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merges_capabilities() {
+        let lookml = json!({
+            "textDocumentSync": 2,
+            "hoverProvider": true,
+            "completionProvider": { "triggerCharacters": ["."] },
+            "executeCommandProvider": { "commands": ["lookml.validate"] },
+        });
+        let sql = json!({
+            "textDocumentSync": { "openClose": true, "change": 1 },
+            "hoverProvider": { "workDoneProgress": true },
+            "completionProvider": { "triggerCharacters": [".", " "], "resolveProvider": true },
+            "documentFormattingProvider": false,
+        });
+
+        let merged = capabilities(vec![lookml, sql]);
+        assert_eq!(
+            merged,
+            json!({
+                "textDocumentSync": { "openClose": true, "change": 1 },
+                "hoverProvider": { "workDoneProgress": true },
+                "completionProvider": { "triggerCharacters": [".", " "], "resolveProvider": true },
+                "executeCommandProvider": { "commands": ["lookml.validate"] },
+                "documentFormattingProvider": false,
+            })
+        );
+    }
+
+    #[test]
+    fn combines_results() {
+        assert_eq!(results(vec![Value::Null, Value::Null]), Value::Null);
+        assert_eq!(
+            results(vec![Value::Null, json!({ "contents": "sql" })]),
+            json!({ "contents": "sql" })
+        );
+        assert_eq!(results(vec![json!([1]), Value::Null, json!([2, 3])]), json!([1, 2, 3]));
+
+        let list = json!({ "isIncomplete": true, "items": [{ "label": "b" }] });
+        assert_eq!(
+            results(vec![json!([{ "label": "a" }]), list]),
+            json!({ "isIncomplete": true, "items": [{ "label": "a" }, { "label": "b" }] })
+        );
+    }
+
+    #[test]
+    fn tags_items() {
+        let mut result = json!({ "isIncomplete": false, "items": [{ "label": "a", "data": 42 }, { "label": "b" }] });
+        tag_items(&mut result, 1);
+
+        let mut a = result["items"][0].take();
+        let mut b = result["items"][1].take();
+        assert_eq!(untag(&mut a), Some(1));
+        assert_eq!(a, json!({ "label": "a", "data": 42 }));
+        assert_eq!(untag(&mut b), Some(1));
+        assert_eq!(b, json!({ "label": "b" }));
+        assert_eq!(untag(&mut b), None);
+    }
+}
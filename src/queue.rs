@@ -0,0 +1,390 @@
+//! Bounded queue of outgoing messages in which log messages yield to all other messages.
+
+use crate::jsonrpc::Outgoing;
+use futures::{future, stream::FusedStream, Stream};
+use std::{
+    collections::VecDeque,
+    fmt::{self, Debug, Display, Formatter},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+        Mutex,
+        PoisonError,
+    },
+    task::{Context, Poll, Waker},
+};
+
+/// Methods of the notifications which are only sent once no other message is waiting.
+const LOG_METHODS: [&str; 3] = ["window/logMessage", "$/logTrace", "telemetry/event"];
+
+/// What happens to a message sent to the client while the outgoing queue is full.
+///
+/// See [`LspService::outgoing_queue`](crate::LspService::outgoing_queue).
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum OverflowPolicy {
+    /// Wait until there is room in the queue.
+    Block,
+    /// Make room by dropping the oldest queued log message, i.e. [`window/logMessage`],
+    /// [`$/logTrace`] or [`telemetry/event`]. A log message is dropped itself if no other log
+    /// message is queued, while other messages wait until there is room.
+    ///
+    /// [`window/logMessage`]: https://microsoft.github.io/language-server-protocol/specification#window_logMessage
+    /// [`$/logTrace`]: https://microsoft.github.io/language-server-protocol/specification#logTrace
+    /// [`telemetry/event`]: https://microsoft.github.io/language-server-protocol/specification#telemetry_event
+    DropOldestLog,
+    /// Fail to send the message. Requests return `Err` and notifications are logged.
+    Error,
+}
+
+/// Error returned when a message could not be queued.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum SendError {
+    /// The queue is full and the message could not wait for room.
+    Full,
+    /// The receiving end of the queue has been dropped.
+    Closed,
+}
+
+impl Display for SendError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            SendError::Full => f.write_str("outgoing message queue is full"),
+            SendError::Closed => f.write_str("outgoing message queue is closed"),
+        }
+    }
+}
+
+impl std::error::Error for SendError {
+}
+
+/// State shared by the sending and receiving ends of a queue.
+pub(crate) struct Queue {
+    state: Mutex<State>,
+    senders: AtomicUsize,
+    dropped: AtomicU64,
+    rejected: AtomicU64,
+}
+
+struct State {
+    messages: VecDeque<Outgoing>,
+    logs: VecDeque<Outgoing>,
+    capacity: usize,
+    overflow: OverflowPolicy,
+    closed: bool,
+    receiver: Option<Waker>,
+    blocked: Vec<Waker>,
+}
+
+impl State {
+    fn len(&self) -> usize {
+        self.messages.len() + self.logs.len()
+    }
+
+    fn push(&mut self, message: Outgoing) {
+        if is_log(&message) {
+            self.logs.push_back(message);
+        } else {
+            self.messages.push_back(message);
+        }
+        if let Some(waker) = self.receiver.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl Queue {
+    /// Changes the capacity and overflow policy of the queue. The capacity is at least `1`.
+    pub(crate) fn configure(&self, capacity: usize, overflow: OverflowPolicy) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.capacity = capacity.max(1);
+        state.overflow = overflow;
+        state.blocked.drain(..).for_each(Waker::wake);
+    }
+
+    /// Returns the number of messages waiting to be sent.
+    pub(crate) fn len(&self) -> usize {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner).len()
+    }
+
+    /// Returns the number of log messages dropped to make room for other messages.
+    pub(crate) fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of messages which could not be queued.
+    pub(crate) fn rejected(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn reset(&self) {
+        self.dropped.store(0, Ordering::Relaxed);
+        self.rejected.store(0, Ordering::Relaxed);
+    }
+
+    fn poll_push(&self, message: &mut Option<Outgoing>, cx: &mut Context) -> Poll<Result<(), SendError>> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if state.closed {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+            return Poll::Ready(Err(SendError::Closed));
+        }
+
+        let log_message = matches!(message, Some(message) if is_log(message));
+        if state.len() >= state.capacity {
+            match state.overflow {
+                OverflowPolicy::DropOldestLog if log_message || !state.logs.is_empty() => {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    let oldest = state.logs.pop_front().or_else(|| message.take());
+                    if let Some(oldest) = oldest {
+                        log::trace!("outgoing message queue is full, dropping {}", oldest);
+                    }
+                },
+                OverflowPolicy::Block | OverflowPolicy::DropOldestLog => {
+                    state.blocked.push(cx.waker().clone());
+                    return Poll::Pending;
+                },
+                OverflowPolicy::Error => {
+                    self.rejected.fetch_add(1, Ordering::Relaxed);
+                    return Poll::Ready(Err(SendError::Full));
+                },
+            }
+        }
+
+        if let Some(message) = message.take() {
+            state.push(message);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Debug for Queue {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        f.debug_struct(stringify!(Queue))
+            .field("len", &state.len())
+            .field("capacity", &state.capacity)
+            .field("overflow", &state.overflow)
+            .field("closed", &state.closed)
+            .finish()
+    }
+}
+
+/// Creates a queue with the given capacity and overflow policy.
+pub(crate) fn channel(capacity: usize, overflow: OverflowPolicy) -> (Sender, Receiver) {
+    let queue = Arc::new(Queue {
+        state: Mutex::new(State {
+            messages: VecDeque::new(),
+            logs: VecDeque::new(),
+            capacity: capacity.max(1),
+            overflow,
+            closed: false,
+            receiver: None,
+            blocked: Vec::new(),
+        }),
+        senders: AtomicUsize::new(1),
+        dropped: AtomicU64::new(0),
+        rejected: AtomicU64::new(0),
+    });
+    let receiver = Receiver {
+        queue: queue.clone(),
+        terminated: false,
+    };
+    (Sender { queue }, receiver)
+}
+
+/// Sending end of a queue.
+#[derive(Debug)]
+pub(crate) struct Sender {
+    queue: Arc<Queue>,
+}
+
+impl Sender {
+    /// Returns the state shared by both ends of the queue.
+    pub(crate) fn queue(&self) -> &Arc<Queue> {
+        &self.queue
+    }
+
+    /// Queues the message, handling a full queue according to its overflow policy.
+    pub(crate) async fn send(&self, message: Outgoing) -> Result<(), SendError> {
+        let mut message = Some(message);
+        future::poll_fn(|cx| self.queue.poll_push(&mut message, cx)).await
+    }
+
+    /// Queues the message even if the queue is full, for messages which can neither wait nor be
+    /// dropped (e.g., when sent from a destructor).
+    pub(crate) fn push(&self, message: Outgoing) -> Result<(), SendError> {
+        let mut state = self.queue.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if state.closed {
+            self.queue.rejected.fetch_add(1, Ordering::Relaxed);
+            return Err(SendError::Closed);
+        }
+        state.push(message);
+        Ok(())
+    }
+}
+
+impl Clone for Sender {
+    fn clone(&self) -> Self {
+        self.queue.senders.fetch_add(1, Ordering::Relaxed);
+        Sender {
+            queue: self.queue.clone(),
+        }
+    }
+}
+
+impl Drop for Sender {
+    fn drop(&mut self) {
+        if self.queue.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            let mut state = self.queue.state.lock().unwrap_or_else(PoisonError::into_inner);
+            if let Some(waker) = state.receiver.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+/// Receiving end of a queue, yielding log messages only once no other message is waiting.
+///
+/// The stream ends once all senders have been dropped and the queue is empty.
+#[derive(Debug)]
+pub(crate) struct Receiver {
+    queue: Arc<Queue>,
+    terminated: bool,
+}
+
+impl Stream for Receiver {
+    type Item = Outgoing;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        if self.terminated {
+            return Poll::Ready(None);
+        }
+
+        let mut state = self.queue.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(message) = state.messages.pop_front().or_else(|| state.logs.pop_front()) {
+            state.blocked.drain(..).for_each(Waker::wake);
+            return Poll::Ready(Some(message));
+        }
+
+        if self.queue.senders.load(Ordering::Acquire) == 0 {
+            drop(state);
+            self.terminated = true;
+            return Poll::Ready(None);
+        }
+
+        state.receiver = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl FusedStream for Receiver {
+    fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+}
+
+impl Drop for Receiver {
+    fn drop(&mut self) {
+        let mut state = self.queue.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.closed = true;
+        state.messages.clear();
+        state.logs.clear();
+        state.blocked.drain(..).for_each(Waker::wake);
+    }
+}
+
+fn is_log(message: &Outgoing) -> bool {
+    match message {
+        Outgoing::Request(request) => LOG_METHODS.contains(&request.method()),
+        Outgoing::Response(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jsonrpc::{ClientRequest, Id};
+    use futures::{FutureExt, StreamExt};
+
+    fn log(message: &str) -> Outgoing {
+        let params = lsp::LogMessageParams {
+            typ: lsp::MessageType::Log,
+            message: message.into(),
+        };
+        Outgoing::Request(ClientRequest::notification::<lsp::notification::LogMessage>(params))
+    }
+
+    fn request(id: u64) -> Outgoing {
+        Outgoing::Request(ClientRequest::request::<lsp::request::WorkspaceFoldersRequest>(
+            Id::Number(id),
+            (),
+        ))
+    }
+
+    #[tokio::test]
+    async fn log_messages_yield() {
+        let (sender, mut receiver) = channel(4, OverflowPolicy::Block);
+        sender.send(log("first")).await.unwrap();
+        sender.send(request(1)).await.unwrap();
+        sender.send(log("second")).await.unwrap();
+        sender.send(request(2)).await.unwrap();
+        assert_eq!(sender.queue().len(), 4);
+        drop(sender);
+
+        let received: Vec<_> = receiver.by_ref().collect().await;
+        assert_eq!(received, vec![request(1), request(2), log("first"), log("second")]);
+        assert!(receiver.is_terminated());
+    }
+
+    #[tokio::test]
+    async fn block() {
+        let (sender, mut receiver) = channel(1, OverflowPolicy::Block);
+        sender.send(request(1)).await.unwrap();
+
+        let mut send = Box::pin(sender.send(request(2)));
+        assert!(send.as_mut().now_or_never().is_none());
+        assert_eq!(receiver.next().await, Some(request(1)));
+        assert_eq!(send.await, Ok(()));
+
+        sender.push(request(3)).unwrap();
+        assert_eq!(sender.queue().len(), 2);
+        assert_eq!(receiver.next().await, Some(request(2)));
+        assert_eq!(receiver.next().await, Some(request(3)));
+        assert_eq!(sender.queue().rejected(), 0);
+    }
+
+    #[tokio::test]
+    async fn drop_oldest_log() {
+        let (sender, mut receiver) = channel(2, OverflowPolicy::DropOldestLog);
+        sender.send(log("first")).await.unwrap();
+        sender.send(log("second")).await.unwrap();
+        sender.send(log("third")).await.unwrap();
+        sender.send(request(1)).await.unwrap();
+        sender.send(request(2)).await.unwrap();
+        assert_eq!(sender.queue().dropped(), 3);
+
+        assert!(sender.send(request(3)).now_or_never().is_none());
+        sender.send(log("fourth")).await.unwrap();
+        assert_eq!(sender.queue().dropped(), 4);
+
+        assert_eq!(receiver.next().await, Some(request(1)));
+        assert_eq!(receiver.next().await, Some(request(2)));
+        assert!(receiver.next().now_or_never().is_none());
+    }
+
+    #[tokio::test]
+    async fn error() {
+        let (sender, receiver) = channel(1, OverflowPolicy::Error);
+        sender.send(log("first")).await.unwrap();
+        assert_eq!(sender.send(request(1)).await, Err(SendError::Full));
+        assert_eq!(sender.queue().rejected(), 1);
+
+        drop(receiver);
+        assert_eq!(sender.push(log("second")), Err(SendError::Closed));
+        assert_eq!(sender.queue().len(), 0);
+        assert_eq!(sender.queue().rejected(), 2);
+
+        sender.queue().reset();
+        assert_eq!(sender.queue().rejected(), 0);
+    }
+}
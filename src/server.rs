@@ -1,24 +1,54 @@
 #![allow(dead_code)]
 
+use futures::task::AtomicWaker;
 use std::{
     fmt,
-    sync::atomic::{AtomicUsize, Ordering},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    task::Waker,
 };
 
 /// Atomic value which represents the current state of the server.
-pub(crate) struct State(AtomicUsize);
+pub(crate) struct State {
+    kind: AtomicUsize,
+    shut_down: AtomicBool,
+    waker: AtomicWaker,
+}
 
 impl State {
     pub(crate) const fn new() -> Self {
-        State(AtomicUsize::new(StateKind::Uninitialized as usize))
+        State {
+            kind: AtomicUsize::new(StateKind::Uninitialized as usize),
+            shut_down: AtomicBool::new(false),
+            waker: AtomicWaker::new(),
+        }
     }
 
+    /// Stores the new state and wakes the task registered through [`State::register`], if any.
     pub(crate) fn set(&self, state: StateKind) {
-        self.0.store(state as usize, Ordering::SeqCst);
+        if state == StateKind::ShutDown {
+            self.shut_down.store(true, Ordering::SeqCst);
+        }
+        self.kind.store(state as usize, Ordering::SeqCst);
+        self.waker.wake();
+    }
+
+    /// Returns whether the server has received a `shutdown` request at some point.
+    ///
+    /// Unlike checking for [`StateKind::ShutDown`], this remains `true` after the server exits.
+    pub(crate) fn was_shut_down(&self) -> bool {
+        self.shut_down.load(Ordering::SeqCst)
+    }
+
+    /// Registers a task to be woken up whenever the state changes.
+    ///
+    /// This allows state transitions triggered outside of the request flow (e.g., the client
+    /// process disappearing) to be observed by whoever is driving the service.
+    pub(crate) fn register(&self, waker: &Waker) {
+        self.waker.register(waker);
     }
 
     pub(crate) fn get(&self) -> StateKind {
-        match self.0.load(Ordering::SeqCst) {
+        match self.kind.load(Ordering::SeqCst) {
             0 => StateKind::Uninitialized,
             1 => StateKind::Initializing,
             2 => StateKind::Initialized,
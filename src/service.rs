@@ -1,24 +1,49 @@
 //! Service abstraction for language servers.
 
 use futures::{
-    channel::mpsc,
     future,
     stream::{FusedStream, Stream},
     FutureExt,
 };
+use lsp::request::Request as _;
 use std::{
+    any::Any,
     error::Error,
     fmt::{self, Debug, Display, Formatter},
     future::Future,
     pin::Pin,
     sync::Arc,
     task::{Context, Poll},
+    time::{Duration, Instant},
 };
 use tower_service::Service;
 
+const DEFAULT_SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(5);
+const DEFAULT_OUTGOING_CAPACITY: usize = 64;
+
 /// Error that occurs when attempting to call the language server after it has already exited.
 #[derive(Clone, Debug, PartialEq)]
-pub struct ExitedError;
+pub struct ExitedError {
+    shut_down: bool,
+}
+
+impl ExitedError {
+    pub(crate) fn new(state: &crate::server::State) -> Self {
+        ExitedError {
+            shut_down: state.was_shut_down(),
+        }
+    }
+
+    /// Returns whether the server received a [`shutdown`] request before it exited.
+    ///
+    /// The specification asks for the server process to exit with code `0` if this is the case,
+    /// and with code `1` otherwise.
+    ///
+    /// [`shutdown`]: https://microsoft.github.io/language-server-protocol/specification#shutdown
+    pub fn was_shut_down(&self) -> bool {
+        self.shut_down
+    }
+}
 
 impl Display for ExitedError {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
@@ -30,9 +55,12 @@ impl Error for ExitedError {
 }
 
 /// Stream of messages produced by the language server.
+///
+/// Log messages are only yielded once no other message is waiting, see
+/// [`LspService::outgoing_queue`].
 #[derive(Debug)]
 #[must_use = "streams do nothing unless polled"]
-pub struct MessageStream(mpsc::Receiver<crate::jsonrpc::Outgoing>);
+pub struct MessageStream(pub(crate) crate::queue::Receiver);
 
 impl Stream for MessageStream {
     type Item = crate::jsonrpc::Outgoing;
@@ -68,9 +96,79 @@ impl FusedStream for MessageStream {
 /// [`exit`]: https://microsoft.github.io/language-server-protocol/specification#exit
 pub struct LspService {
     server: Arc<dyn crate::LanguageServer>,
+    backend: Arc<dyn Any + Send + Sync>,
     pending_server: crate::jsonrpc::ServerRequests,
     pending_client: Arc<crate::jsonrpc::ClientRequests>,
     state: Arc<crate::server::State>,
+    outgoing: Arc<crate::queue::Queue>,
+    metrics: crate::metrics::Metrics,
+    hooks: Hooks,
+}
+
+/// Parts of the service which the generated request router notifies about certain messages.
+#[derive(Debug)]
+pub(crate) struct Hooks {
+    client: crate::client::Client,
+    state: Arc<crate::server::State>,
+    pending_server: crate::jsonrpc::ServerRequests,
+    parent_monitor: Option<Duration>,
+    shutdown_grace_period: Duration,
+    extensions: crate::extension::Extensions,
+    notebook_document_sync: Option<crate::notebook::NotebookDocumentSyncOptions>,
+}
+
+impl Hooks {
+    /// Called upon receiving the `initialize` request, before it is handled by the server.
+    pub(crate) fn initialize(&self, params: &lsp::InitializeParams) {
+        self.client.set_initialize_params(params.clone());
+        match (self.parent_monitor, params.process_id) {
+            (Some(interval), Some(pid)) => {
+                let state = self.state.clone();
+                let pending = self.pending_server.clone();
+                crate::monitor::spawn(pid, interval, state, pending);
+            },
+            (Some(_), None) => log::warn!("client did not send a process ID, not monitoring it"),
+            (None, _) => {},
+        }
+    }
+
+    /// Called upon receiving the `workspace/didChangeConfiguration` notification, before it is
+    /// handled by the server.
+    pub(crate) fn did_change_configuration(&self, params: &lsp::DidChangeConfigurationParams) {
+        self.client.did_change_configuration(params.settings.clone());
+    }
+
+    /// Returns the `notebookDocumentSync` capability to add to the result of the `initialize`
+    /// request, if any.
+    pub(crate) fn notebook_document_sync(&self) -> Option<serde_json::Value> {
+        let options = self.notebook_document_sync.as_ref()?;
+        serde_json::to_value(options).ok()
+    }
+
+    /// Returns how long the `shutdown` request waits for pending requests to finish.
+    pub(crate) fn shutdown_grace_period(&self) -> Duration {
+        self.shutdown_grace_period
+    }
+
+    /// Returns whether one of the registered extension traits declares the given method.
+    pub(crate) fn handles_extension(&self, method: &str) -> bool {
+        self.extensions.handles(method)
+    }
+
+    /// Called upon receiving a request or notification of a method declared in one of the
+    /// registered extension traits, to route it to its handler.
+    pub(crate) fn route_extension(
+        &self,
+        method: &str,
+        id: Option<crate::jsonrpc::Id>,
+        params: Option<Box<serde_json::value::RawValue>>,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<crate::jsonrpc::Outgoing>, ExitedError>> + Send>> {
+        let state = self.state.get();
+        self.extensions
+            .route(method, id, params, state, &self.pending_server)
+            .map(Ok)
+            .boxed()
+    }
 }
 
 impl LspService {
@@ -82,21 +180,194 @@ impl LspService {
         T: crate::LanguageServer,
     {
         let state = Arc::new(crate::server::State::new());
-        let (tx, rx) = mpsc::channel(1);
+        let (tx, rx) = crate::queue::channel(DEFAULT_OUTGOING_CAPACITY, crate::OverflowPolicy::Block);
+        let outgoing = tx.queue().clone();
         let messages = MessageStream(rx);
 
         let pending_client = Arc::new(crate::jsonrpc::ClientRequests::new());
         let client = crate::client::Client::new(tx, pending_client.clone(), state.clone());
 
+        let pending_server = crate::jsonrpc::ServerRequests::new();
+        let metrics = crate::metrics::Metrics::new(pending_server.clone(), pending_client.clone(), outgoing.clone());
+
+        let server = Arc::new(init(client.clone()));
         let service = LspService {
-            server: Arc::from(init(client)),
-            pending_server: crate::jsonrpc::ServerRequests::new(),
+            server: server.clone(),
+            backend: server,
+            pending_server: pending_server.clone(),
             pending_client,
-            state,
+            state: state.clone(),
+            outgoing,
+            metrics,
+            hooks: Hooks {
+                client,
+                state,
+                pending_server,
+                parent_monitor: None,
+                shutdown_grace_period: DEFAULT_SHUTDOWN_GRACE_PERIOD,
+                extensions: Default::default(),
+                notebook_document_sync: None,
+            },
         };
 
         (service, messages)
     }
+
+    /// Creates a new `LspService` like [`LspService::new`], giving the server access to state
+    /// shared with other connections (e.g., a project index shared by all clients of a TCP
+    /// server).
+    ///
+    /// The state is passed to `init`, and is also available from the [`Client`] of the connection
+    /// with [`Client::state`]. Data belonging to a single connection can be stored in its `Client`
+    /// with [`Client::insert_data`] instead.
+    ///
+    /// [`Client`]: crate::Client
+    /// [`Client::state`]: crate::Client::state
+    /// [`Client::insert_data`]: crate::Client::insert_data
+    pub fn with_state<S, T, F>(state: Arc<S>, init: F) -> (Self, MessageStream)
+    where
+        S: Send + Sync + 'static,
+        F: FnOnce(crate::client::Client, Arc<S>) -> T,
+        T: crate::LanguageServer,
+    {
+        LspService::new(|client| {
+            client.set_shared_state(state.clone());
+            init(client, state)
+        })
+    }
+
+    /// Monitors the parent process of the server, polling it at the given interval.
+    ///
+    /// The process ID is taken from the `processId` field of the [`initialize`] request. If the
+    /// parent process disappears, the service cancels all pending requests and shuts down as if it
+    /// had received an [`exit`] notification, which in turn makes [`Server::serve`] return. This
+    /// keeps the server from lingering (e.g., holding on to a TCP port) after the editor crashes.
+    ///
+    /// [`initialize`]: https://microsoft.github.io/language-server-protocol/specification#initialize
+    /// [`exit`]: https://microsoft.github.io/language-server-protocol/specification#exit
+    /// [`Server::serve`]: crate::Server::serve
+    ///
+    /// # Compatibility
+    ///
+    /// Monitoring is only supported on Linux. On other platforms this setting has no effect.
+    pub fn monitor_parent_process(mut self, interval: Duration) -> Self {
+        self.hooks.parent_monitor = Some(interval);
+        self
+    }
+
+    /// Sets how long requests sent through the [`Client`] wait for a response by default.
+    ///
+    /// Requests which the client does not answer in time are cancelled with a
+    /// [`$/cancelRequest`] notification and return `Err` with [`Error::request_timed_out`]. The
+    /// timeout can be overridden per request with [`Client::with_timeout`]. By default, requests
+    /// wait for a response indefinitely.
+    ///
+    /// [`Client`]: crate::Client
+    /// [`$/cancelRequest`]: https://microsoft.github.io/language-server-protocol/specification#cancelRequest
+    /// [`Error::request_timed_out`]: crate::jsonrpc::Error::request_timed_out
+    /// [`Client::with_timeout`]: crate::Client::with_timeout
+    pub fn client_request_timeout(self, timeout: Duration) -> Self {
+        self.hooks.client.set_request_timeout(Some(timeout));
+        self
+    }
+
+    /// Sets how the [`Client`] generates the IDs of the requests it sends to the language client.
+    ///
+    /// By default, IDs are numbers counting up from `0`, like those of most clients. Prefixed
+    /// string IDs such as `"lookml-42"` keep the requests of the server apart from the requests of
+    /// the client in logs and across proxies. Requests with string IDs are cancelled with string
+    /// IDs as well.
+    ///
+    /// [`Client`]: crate::Client
+    pub fn client_request_ids(self, strategy: crate::RequestIdStrategy) -> Self {
+        self.hooks.client.set_request_id_strategy(strategy);
+        self
+    }
+
+    /// Sets how long a [`shutdown`] request waits for pending requests to finish.
+    ///
+    /// Upon receiving `shutdown`, the service stops accepting new requests and waits for the ones
+    /// still being handled before calling [`LanguageServer::shutdown`]. Requests which have not
+    /// finished once the grace period elapses are cancelled. Defaults to 5 seconds.
+    ///
+    /// [`shutdown`]: https://microsoft.github.io/language-server-protocol/specification#shutdown
+    /// [`LanguageServer::shutdown`]: crate::LanguageServer::shutdown
+    pub fn shutdown_grace_period(mut self, grace_period: Duration) -> Self {
+        self.hooks.shutdown_grace_period = grace_period;
+        self
+    }
+
+    /// Sets the capacity of the queue of messages sent through the [`Client`] and what happens to
+    /// messages sent while it is full.
+    ///
+    /// Log messages ([`window/logMessage`], [`$/logTrace`] and [`telemetry/event`]) wait in the
+    /// queue until no other message does, and the [`Server`] writes responses ahead of any queued
+    /// message, so that a burst of logs does not hold up more important messages. Defaults to a
+    /// capacity of 64 messages and [`OverflowPolicy::Block`]. The number of queued, dropped and
+    /// rejected messages is reported by [`LspService::metrics`].
+    ///
+    /// [`Client`]: crate::Client
+    /// [`window/logMessage`]: https://microsoft.github.io/language-server-protocol/specification#window_logMessage
+    /// [`$/logTrace`]: https://microsoft.github.io/language-server-protocol/specification#logTrace
+    /// [`telemetry/event`]: https://microsoft.github.io/language-server-protocol/specification#telemetry_event
+    /// [`Server`]: crate::Server
+    /// [`OverflowPolicy::Block`]: crate::OverflowPolicy::Block
+    pub fn outgoing_queue(self, capacity: usize, overflow: crate::OverflowPolicy) -> Self {
+        self.outgoing.configure(capacity, overflow);
+        self
+    }
+
+    /// Advertises the `notebookDocumentSync` capability with the given options, so that the client
+    /// synchronizes the selected notebooks through the [`LanguageServer`] notebook handlers.
+    ///
+    /// The capability is added to the result of [`LanguageServer::initialize`], since it is not
+    /// part of [`lsp::ServerCapabilities`] yet. See the [`notebook`] module for details.
+    ///
+    /// [`LanguageServer`]: crate::LanguageServer
+    /// [`LanguageServer::initialize`]: crate::LanguageServer::initialize
+    /// [`notebook`]: crate::notebook
+    pub fn notebook_document_sync(mut self, options: crate::notebook::NotebookDocumentSyncOptions) -> Self {
+        self.hooks.notebook_document_sync = Some(options);
+        self
+    }
+
+    /// Routes the requests and notifications declared in an extension trait to the server.
+    ///
+    /// The router is generated by annotating the trait with [`rpc`], and is created from the server
+    /// returned by the `init` closure passed to [`LspService::new`], e.g.
+    /// `service.extension(LookmlExtensionsRouter::<Backend>::new)`. See the [`extension`] module
+    /// for details.
+    ///
+    /// [`rpc`]: crate::rpc
+    /// [`extension`]: crate::extension
+    ///
+    /// # Panics
+    ///
+    /// Panics if the server of this service is not a `T`.
+    pub fn extension<T, F, R>(mut self, router: F) -> Self
+    where
+        T: crate::LanguageServer,
+        F: FnOnce(Arc<T>) -> R,
+        R: crate::extension::Router,
+    {
+        match self.backend.clone().downcast::<T>() {
+            Ok(server) => self.hooks.extensions.push(Box::new(router(server))),
+            Err(_) => panic!("language server is not a `{}`", std::any::type_name::<T>()),
+        }
+        self
+    }
+
+    /// Returns a handle to the metrics of this service.
+    ///
+    /// The service records how many requests and notifications of each method it handled and how
+    /// long they took. Pass the handle to [`Server::metrics`] to also count the messages and bytes
+    /// exchanged with the client. Clients can query the same metrics with the custom
+    /// [`$/lspower/status`](crate::metrics::StatusRequest) request.
+    ///
+    /// [`Server::metrics`]: crate::Server::metrics
+    pub fn metrics(&self) -> crate::metrics::Metrics {
+        self.metrics.clone()
+    }
 }
 
 impl Service<crate::jsonrpc::Incoming> for LspService {
@@ -104,9 +375,10 @@ impl Service<crate::jsonrpc::Incoming> for LspService {
     type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
     type Response = Option<crate::jsonrpc::Outgoing>;
 
-    fn poll_ready(&mut self, _: &mut Context) -> Poll<Result<(), Self::Error>> {
+    fn poll_ready(&mut self, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        self.state.register(cx.waker());
         if self.state.get() == crate::server::StateKind::Exited {
-            Poll::Ready(Err(ExitedError))
+            Poll::Ready(Err(ExitedError::new(&self.state)))
         } else {
             Poll::Ready(Ok(()))
         }
@@ -114,11 +386,40 @@ impl Service<crate::jsonrpc::Incoming> for LspService {
 
     fn call(&mut self, request: crate::jsonrpc::Incoming) -> Self::Future {
         if self.state.get() == crate::server::StateKind::Exited {
-            future::err(ExitedError).boxed()
+            future::err(ExitedError::new(&self.state)).boxed()
         } else {
             match request {
+                crate::jsonrpc::Incoming::Request(req) if req.method() == crate::metrics::StatusRequest::METHOD => {
+                    let response = req.id().cloned().map(|id| {
+                        let status = serde_json::value::to_raw_value(&self.metrics.status()).map_err(|e| {
+                            log::error!("failed to serialize the status: {}", e);
+                            crate::jsonrpc::Error::internal_error()
+                        });
+                        crate::jsonrpc::Outgoing::Response(crate::jsonrpc::Response::from_raw_parts(id, status))
+                    });
+                    future::ok(response).boxed()
+                },
                 crate::jsonrpc::Incoming::Request(req) => {
-                    super::generated_impl::handle_request(self.server.clone(), &self.state, &self.pending_server, req)
+                    let metrics = self.metrics.clone();
+                    let method = req.method().to_string();
+                    let start = Instant::now();
+                    let response = super::generated_impl::handle_request(
+                        self.server.clone(),
+                        &self.state,
+                        &self.pending_server,
+                        req,
+                        &self.hooks,
+                    );
+                    Box::pin(async move {
+                        let response = response.await;
+                        let error = match response {
+                            Ok(Some(crate::jsonrpc::Outgoing::Response(ref response))) => response.is_error(),
+                            Ok(_) => false,
+                            Err(_) => true,
+                        };
+                        metrics.record_method(method, start.elapsed(), error);
+                        response
+                    })
                 },
                 crate::jsonrpc::Incoming::Response(res) => {
                     log::trace!("received client response: {:?}", res);
@@ -136,6 +437,10 @@ impl Debug for LspService {
             .field("pending_server", &self.pending_server)
             .field("pending_client", &self.pending_client)
             .field("state", &self.state)
+            .field("outgoing", &self.outgoing)
+            .field("parent_monitor", &self.hooks.parent_monitor)
+            .field("shutdown_grace_period", &self.hooks.shutdown_grace_period)
+            .field("extensions", &self.hooks.extensions)
             .finish()
     }
 }
@@ -184,6 +489,102 @@ mod tests {
         assert_eq!(service.call(Incoming::Response(incoming)).await, Ok(None));
     }
 
+    #[tokio::test]
+    async fn retains_initialize_params() {
+        let mut client = None;
+        let (service, _) = LspService::new(|c| {
+            client = Some(c);
+            Mock
+        });
+        let client = client.unwrap();
+        let mut service = Spawn::new(service);
+        assert!(client.initialize_params().is_none());
+
+        let initialize: crate::jsonrpc::Incoming = serde_json::from_str(INITIALIZE_REQUEST).unwrap();
+        assert_eq!(service.poll_ready(), Poll::Ready(Ok(())));
+        assert!(service.call(initialize).await.is_ok());
+
+        assert_eq!(client.capabilities(), Some(lsp::ClientCapabilities::default()));
+    }
+
+    #[tokio::test]
+    async fn shares_state() {
+        let index = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let clients: Vec<_> = (0 .. 2)
+            .map(|connection| {
+                let mut client = None;
+                let _ = LspService::with_state(index.clone(), |c, index| {
+                    index.lock().unwrap().push(connection);
+                    c.insert_data(connection);
+                    client = Some(c);
+                    Mock
+                });
+                client.unwrap()
+            })
+            .collect();
+
+        assert_eq!(*index.lock().unwrap(), [0, 1]);
+        for (connection, client) in clients.iter().enumerate() {
+            let state = client.state::<std::sync::Mutex<Vec<usize>>>().unwrap();
+            assert!(Arc::ptr_eq(&state, &index));
+            assert_eq!(client.data::<usize>(), Some(Arc::new(connection)));
+        }
+    }
+
+    #[tokio::test]
+    async fn did_change_configuration_updates_settings() {
+        let mut client = None;
+        let (service, _) = LspService::new(|c| {
+            client = Some(c);
+            Mock
+        });
+        let client = client.unwrap();
+        let mut service = Spawn::new(service);
+
+        let initialize: crate::jsonrpc::Incoming = serde_json::from_str(INITIALIZE_REQUEST).unwrap();
+        assert_eq!(service.poll_ready(), Poll::Ready(Ok(())));
+        assert!(service.call(initialize).await.is_ok());
+
+        let raw = json!({
+            "jsonrpc": "2.0",
+            "method": "workspace/didChangeConfiguration",
+            "params": { "settings": { "lookml": { "enabled": true } } },
+        });
+        let did_change_configuration = serde_json::from_value(raw).unwrap();
+        assert_eq!(service.poll_ready(), Poll::Ready(Ok(())));
+        assert_eq!(service.call(did_change_configuration).await, Ok(None));
+
+        let settings = client.settings::<Option<bool>>("lookml.enabled").await;
+        assert_eq!(settings, Ok(Some(true)));
+    }
+
+    #[tokio::test]
+    async fn status_request() {
+        let (service, _) = LspService::new(|_| Mock);
+        let metrics = service.metrics();
+        let mut service = Spawn::new(service);
+
+        let initialize: crate::jsonrpc::Incoming = serde_json::from_str(INITIALIZE_REQUEST).unwrap();
+        assert_eq!(service.poll_ready(), Poll::Ready(Ok(())));
+        assert!(service.call(initialize.clone()).await.is_ok());
+        assert!(service.call(initialize).await.is_ok());
+
+        let raw = json!({ "jsonrpc": "2.0", "method": "$/lspower/status", "id": 2 });
+        let status = serde_json::from_value(raw).unwrap();
+        assert_eq!(service.poll_ready(), Poll::Ready(Ok(())));
+        let response = match service.call(status).await {
+            Ok(Some(crate::jsonrpc::Outgoing::Response(response))) => response,
+            other => panic!("unexpected response: {:?}", other),
+        };
+        let status: crate::metrics::Status = serde_json::from_value(response.into_parts().1.unwrap()).unwrap();
+
+        assert_eq!(status, metrics.status());
+        assert_eq!(status.in_flight_requests, 0);
+        assert_eq!(status.methods.len(), 1);
+        assert_eq!(status.methods["initialize"].count, 2);
+        assert_eq!(status.methods["initialize"].errors, 1);
+    }
+
     #[test]
     fn debug() {
         let (service, _) = LspService::new(|_| Mock::default());
@@ -243,8 +644,159 @@ mod tests {
         assert_eq!(service.poll_ready(), Poll::Ready(Ok(())));
         assert_eq!(service.call(exit).await, Ok(None));
 
-        assert_eq!(service.poll_ready(), Poll::Ready(Err(ExitedError)));
-        assert_eq!(service.call(initialized).await, Err(ExitedError));
+        let exited = ExitedError { shut_down: false };
+        assert_eq!(service.poll_ready(), Poll::Ready(Err(exited.clone())));
+        assert_eq!(service.call(initialized).await, Err(exited));
+    }
+
+    #[tokio::test]
+    async fn exit_notification_after_shutdown() {
+        let (service, _) = LspService::new(|_| Mock::default());
+        let mut service = Spawn::new(service);
+
+        let initialize: crate::jsonrpc::Incoming = serde_json::from_str(INITIALIZE_REQUEST).unwrap();
+        assert_eq!(service.poll_ready(), Poll::Ready(Ok(())));
+        assert!(service.call(initialize).await.is_ok());
+
+        let shutdown: crate::jsonrpc::Incoming = serde_json::from_str(SHUTDOWN_REQUEST).unwrap();
+        assert_eq!(service.poll_ready(), Poll::Ready(Ok(())));
+        assert!(service.call(shutdown).await.is_ok());
+
+        let exit: crate::jsonrpc::Incoming = serde_json::from_str(EXIT_NOTIF).unwrap();
+        assert_eq!(service.poll_ready(), Poll::Ready(Ok(())));
+        assert_eq!(service.call(exit).await, Ok(None));
+
+        let exited = ExitedError { shut_down: true };
+        assert_eq!(service.poll_ready(), Poll::Ready(Err(exited)));
+    }
+
+    #[tokio::test]
+    async fn shutdown_drains_pending_requests() {
+        #[derive(Debug)]
+        struct Slow;
+
+        #[async_trait]
+        impl crate::LanguageServer for Slow {
+            async fn initialize(&self, _: lsp::InitializeParams) -> crate::jsonrpc::Result<lsp::InitializeResult> {
+                Ok(lsp::InitializeResult::default())
+            }
+
+            async fn shutdown(&self) -> crate::jsonrpc::Result<()> {
+                Ok(())
+            }
+
+            async fn hover(&self, _: lsp::HoverParams) -> crate::jsonrpc::Result<Option<lsp::Hover>> {
+                tokio::time::sleep(Duration::from_secs(50)).await;
+                Ok(None)
+            }
+        }
+
+        let (service, _) = LspService::new(|_| Slow);
+        let service = service.shutdown_grace_period(Duration::from_millis(30));
+        let mut service = Spawn::new(service);
+
+        let initialize: crate::jsonrpc::Incoming = serde_json::from_str(INITIALIZE_REQUEST).unwrap();
+        assert_eq!(service.poll_ready(), Poll::Ready(Ok(())));
+        assert!(service.call(initialize).await.is_ok());
+
+        let raw = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/hover",
+            "params": {
+                "textDocument": { "uri": "inmemory::///test" },
+                "position": { "line": 0, "character": 0 }
+            },
+            "id": 2
+        });
+        let hover: crate::jsonrpc::Incoming = serde_json::from_value(raw).unwrap();
+        assert_eq!(service.poll_ready(), Poll::Ready(Ok(())));
+        let hover = tokio::spawn(service.call(hover));
+
+        let shutdown: crate::jsonrpc::Incoming = serde_json::from_str(SHUTDOWN_REQUEST).unwrap();
+        let raw = json!({ "jsonrpc": "2.0", "result": null, "id": 1 });
+        let ok = serde_json::from_value(raw).unwrap();
+        assert_eq!(service.poll_ready(), Poll::Ready(Ok(())));
+        assert_eq!(service.call(shutdown).await, Ok(Some(ok)));
+
+        let raw = json!({ "jsonrpc": "2.0", "error": { "code": -32800, "message": "Canceled" }, "id": 2 });
+        let cancelled = serde_json::from_value(raw).unwrap();
+        assert_eq!(hover.await.unwrap(), Ok(Some(cancelled)));
+    }
+
+    #[tokio::test]
+    async fn cancel_request() {
+        #[derive(Debug)]
+        struct Slow;
+
+        #[async_trait]
+        impl crate::LanguageServer for Slow {
+            async fn initialize(&self, _: lsp::InitializeParams) -> crate::jsonrpc::Result<lsp::InitializeResult> {
+                Ok(lsp::InitializeResult::default())
+            }
+
+            async fn shutdown(&self) -> crate::jsonrpc::Result<()> {
+                Ok(())
+            }
+
+            async fn hover(&self, _: lsp::HoverParams) -> crate::jsonrpc::Result<Option<lsp::Hover>> {
+                tokio::time::sleep(Duration::from_secs(50)).await;
+                Ok(None)
+            }
+        }
+
+        let (service, _) = LspService::new(|_| Slow);
+        let mut service = Spawn::new(service);
+
+        let initialize: crate::jsonrpc::Incoming = serde_json::from_str(INITIALIZE_REQUEST).unwrap();
+        assert_eq!(service.poll_ready(), Poll::Ready(Ok(())));
+        assert!(service.call(initialize).await.is_ok());
+
+        let raw = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/hover",
+            "params": {
+                "textDocument": { "uri": "inmemory::///test" },
+                "position": { "line": 0, "character": 0 }
+            },
+            "id": 2
+        });
+        let hover: crate::jsonrpc::Incoming = serde_json::from_value(raw).unwrap();
+        assert_eq!(service.poll_ready(), Poll::Ready(Ok(())));
+        let hover = tokio::spawn(service.call(hover));
+
+        let raw = json!({ "jsonrpc": "2.0", "method": "$/cancelRequest", "params": { "id": 2 } });
+        let cancel: crate::jsonrpc::Incoming = serde_json::from_value(raw).unwrap();
+        assert_eq!(service.poll_ready(), Poll::Ready(Ok(())));
+        assert_eq!(service.call(cancel).await, Ok(None));
+
+        let raw = json!({ "jsonrpc": "2.0", "error": { "code": -32800, "message": "Canceled" }, "id": 2 });
+        let cancelled = serde_json::from_value(raw).unwrap();
+        assert_eq!(hover.await.unwrap(), Ok(Some(cancelled)));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn exits_when_parent_process_is_gone() {
+        let mut child = std::process::Command::new("sleep").arg("30").spawn().unwrap();
+
+        let (service, _) = LspService::new(|_| Mock::default());
+        let service = service.monitor_parent_process(Duration::from_millis(10));
+        let mut service = Spawn::new(service);
+
+        let raw = json!({
+            "jsonrpc": "2.0",
+            "method": "initialize",
+            "params": { "capabilities": {}, "processId": child.id() },
+            "id": 1
+        });
+        let initialize: crate::jsonrpc::Incoming = serde_json::from_value(raw).unwrap();
+        assert_eq!(service.poll_ready(), Poll::Ready(Ok(())));
+        assert!(service.call(initialize).await.is_ok());
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(service.poll_ready(), Poll::Ready(Err(ExitedError { shut_down: false })));
     }
 
     mod exited_error {
@@ -252,12 +804,45 @@ mod tests {
 
         #[test]
         fn display() {
-            let error = ExitedError;
+            let error = ExitedError { shut_down: false };
             let display = format!("{}", error);
             assert_eq!("language server has exited", display);
         }
     }
 
+    #[tokio::test]
+    async fn outgoing_queue() {
+        use futures::StreamExt;
+
+        let mut client = None;
+        let (service, mut messages) = LspService::new(|c| {
+            client = Some(c);
+            Mock
+        });
+        let service = service.outgoing_queue(2, crate::OverflowPolicy::Error);
+        let (client, metrics) = (client.unwrap(), service.metrics());
+        let mut service = Spawn::new(service);
+
+        let initialize: crate::jsonrpc::Incoming = serde_json::from_str(INITIALIZE_REQUEST).unwrap();
+        assert_eq!(service.poll_ready(), Poll::Ready(Ok(())));
+        assert!(service.call(initialize).await.is_ok());
+
+        client.log_message(lsp::MessageType::Log, "first").await;
+        client.show_message(lsp::MessageType::Info, "second").await;
+        client.log_message(lsp::MessageType::Log, "third").await;
+
+        let status = metrics.status();
+        assert_eq!((status.queued_messages, status.rejected_messages), (2, 1));
+
+        let method = |message: Option<crate::jsonrpc::Outgoing>| match message {
+            Some(crate::jsonrpc::Outgoing::Request(request)) => request.method().to_string(),
+            other => panic!("unexpected message: {:?}", other),
+        };
+        assert_eq!(method(messages.next().await), "window/showMessage");
+        assert_eq!(method(messages.next().await), "window/logMessage");
+        assert_eq!(metrics.status().queued_messages, 0);
+    }
+
     mod message_stream {
         use super::*;
         use futures::StreamExt;
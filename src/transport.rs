@@ -12,27 +12,101 @@ use tokio_util::codec::{FramedRead, FramedWrite};
 
 use super::{
     codec::LanguageServerCodec,
-    jsonrpc::{self, Incoming, Outgoing, Response},
+    jsonrpc::{self, ClientRequest, Id, Incoming, Outgoing, Response},
+    metrics::Metrics,
+    service::ExitedError,
 };
 use futures::{
     channel::mpsc,
     future::{self, Either, FutureExt, TryFutureExt},
     sink::SinkExt,
-    stream::{self, Empty, Stream, StreamExt},
+    stream::{self, Empty, PollNext, Stream, StreamExt},
 };
+use futures_timer::Delay;
 use std::{
     error::Error,
     pin::Pin,
+    sync::{Arc, Mutex, PoisonError},
     task::{Context, Poll},
+    time::{Duration, Instant},
 };
 use tower_service::Service;
 
+/// Method of the requests probing whether the client is still alive, see [`Server::keepalive`].
+const KEEPALIVE_METHOD: &str = "$/lspower/keepalive";
+
+/// Reason why [`Server::serve`] stopped serving requests.
+#[derive(Debug)]
+pub enum ServeOutcome {
+    /// The server exited after receiving a [`shutdown`] request.
+    ///
+    /// [`shutdown`]: https://microsoft.github.io/language-server-protocol/specification#shutdown
+    Exited,
+    /// The server exited without receiving a [`shutdown`] request first.
+    ///
+    /// This is the case when the client sends an [`exit`] notification straight away, or when the
+    /// service exits on its own (e.g., because the client process has disappeared).
+    ///
+    /// [`shutdown`]: https://microsoft.github.io/language-server-protocol/specification#shutdown
+    /// [`exit`]: https://microsoft.github.io/language-server-protocol/specification#exit
+    ExitedWithoutShutdown,
+    /// The input stream was closed before the server exited.
+    Eof,
+    /// No message was received from the client within the [idle timeout](Server::idle_timeout).
+    IdleTimeout,
+    /// The service failed with an error other than [`ExitedError`].
+    Error(Box<dyn Error + Send + Sync>),
+}
+
+impl ServeOutcome {
+    /// Returns the exit code the server process should terminate with.
+    ///
+    /// Following the specification, this is `0` if the server was shut down before exiting and `1`
+    /// in every other case.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ServeOutcome::Exited => 0,
+            _ => 1,
+        }
+    }
+}
+
+impl From<Box<dyn Error + Send + Sync>> for ServeOutcome {
+    fn from(error: Box<dyn Error + Send + Sync>) -> Self {
+        match error.downcast_ref::<ExitedError>() {
+            Some(exited) if exited.was_shut_down() => ServeOutcome::Exited,
+            Some(_) => ServeOutcome::ExitedWithoutShutdown,
+            None => ServeOutcome::Error(error),
+        }
+    }
+}
+
+/// Returns handles to the standard input and output of the process, to be passed to
+/// [`Server::new`].
+///
+/// With the `runtime-agnostic` feature, reads and writes are performed on a thread pool, so that
+/// the handles can be used with any executor (e.g., `async-std` or `smol`).
+pub fn stdio() -> (impl AsyncRead + Unpin, impl AsyncWrite) {
+    #[cfg(feature = "runtime-agnostic")]
+    let stdio = (
+        blocking::Unblock::new(std::io::stdin()),
+        blocking::Unblock::new(std::io::stdout()),
+    );
+    #[cfg(feature = "runtime-tokio")]
+    let stdio = (tokio::io::stdin(), tokio::io::stdout());
+    stdio
+}
+
 /// Server for processing requests and responses on standard I/O or TCP.
 #[derive(Debug)]
 pub struct Server<I, O, S = Nothing> {
     stdin: I,
     stdout: O,
     interleave: S,
+    metrics: Option<Metrics>,
+    idle_timeout: Option<Duration>,
+    keepalive: Option<Duration>,
+    raw_params: bool,
 }
 
 impl<I, O> Server<I, O, Nothing>
@@ -46,6 +120,10 @@ where
             stdin,
             stdout,
             interleave: Nothing::new(),
+            metrics: None,
+            idle_timeout: None,
+            keepalive: None,
+            raw_params: false,
         }
     }
 }
@@ -65,11 +143,78 @@ where
             stdin: self.stdin,
             stdout: self.stdout,
             interleave: stream,
+            metrics: self.metrics,
+            idle_timeout: self.idle_timeout,
+            keepalive: self.keepalive,
+            raw_params: self.raw_params,
         }
     }
 
+    /// Counts the messages exchanged with the client and their sizes in the given metrics.
+    ///
+    /// The handle is usually obtained from [`LspService::metrics`], so that the counts are reported
+    /// together with the per-method statistics of the service.
+    ///
+    /// [`LspService::metrics`]: crate::LspService::metrics
+    pub fn metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Stops serving once no message has been received from the client for the given duration.
+    ///
+    /// Over a network connection, a client which disappears without closing the connection (e.g.,
+    /// because its machine went to sleep) otherwise leaves [`Server::serve`] waiting for input
+    /// forever. Since an idle client may still be alive, this is best combined with
+    /// [`Server::keepalive`], which makes live clients send a message regularly. Serving stops with
+    /// [`ServeOutcome::IdleTimeout`]. By default, the server waits indefinitely.
+    ///
+    /// The client is not considered idle while the service is still handling one of its messages,
+    /// so that a slow request does not stop the server while the client is waiting for it.
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Probes the client with a `$/lspower/keepalive` request once no message has been received
+    /// from it for the given interval, and again after every further interval without a message.
+    ///
+    /// Clients answer requests for unknown `$/` methods with an error, as required by the
+    /// specification, and these answers are not passed on to the service. A dead connection is
+    /// then detected either when writing a probe fails, or by the [`Server::idle_timeout`] if the
+    /// probes go unanswered.
+    ///
+    /// Probes are only sent after the client has sent the `initialized` notification and before it
+    /// requests a `shutdown`, since no other requests may be sent to it outside of that window.
+    pub fn keepalive(mut self, interval: Duration) -> Self {
+        self.keepalive = Some(interval);
+        self
+    }
+
+    /// Keeps the parameters of requests as raw JSON, instead of deserializing them into the types
+    /// of [`lsp`] as they are read.
+    ///
+    /// This is required to serve a [`ProxyService`], which forwards the parameters to its backends
+    /// and would otherwise drop every field unknown to [`lsp`] (e.g., newer client capabilities).
+    /// An [`LspService`] still accepts such requests, deserializing their parameters once they are
+    /// handled.
+    ///
+    /// [`ProxyService`]: crate::proxy::ProxyService
+    /// [`LspService`]: crate::LspService
+    pub fn raw_params(mut self) -> Self {
+        self.raw_params = true;
+        self
+    }
+
     /// Spawns the service with messages read through `stdin` and responses written to `stdout`.
-    pub async fn serve<T>(self, mut service: T)
+    ///
+    /// Returns once the service has exited, the service has failed, `stdin` has been closed, or the
+    /// [idle timeout](Server::idle_timeout) has elapsed, after all pending responses have been
+    /// written to `stdout`.
+    ///
+    /// Messages are read, handled and written on the task awaiting the returned future, which
+    /// spawns no tasks of its own and can therefore be awaited on any executor.
+    pub async fn serve<T>(self, mut service: T) -> ServeOutcome
     where
         T: Service<Incoming, Response = Option<Outgoing>> + Send + 'static,
         T::Error: Into<Box<dyn Error + Send + Sync>>,
@@ -77,47 +222,184 @@ where
     {
         let (mut sender, receiver) = mpsc::channel(16);
 
-        let mut framed_stdin = FramedRead::new(self.stdin, LanguageServerCodec::default());
-        let framed_stdout = FramedWrite::new(self.stdout, LanguageServerCodec::default());
+        let codec = LanguageServerCodec::default()
+            .with_metrics(self.metrics.clone())
+            .with_raw_params(self.raw_params);
+        let mut framed_stdin = FramedRead::new(self.stdin, codec);
+        let codec = LanguageServerCodec::default().with_metrics(self.metrics);
+        let framed_stdout = FramedWrite::new(self.stdout, codec);
         let responses = receiver.buffered(4).filter_map(future::ready);
         let interleave = self.interleave.fuse();
 
-        let printer = stream::select(responses, interleave)
+        // Responses are written ahead of interleaved messages, so that a burst of notifications
+        // from the server does not hold up the responses the client is waiting for.
+        let prefer_responses = |_: &mut ()| PollNext::Left;
+        let printer = stream::select_with_strategy(responses, interleave, prefer_responses)
             .map(Ok)
             .forward(framed_stdout.sink_map_err(|e| log::error!("failed to encode message: {}", e)))
             .map(|_| ());
 
+        let (idle_timeout, keepalive) = (self.idle_timeout, self.keepalive);
+        let handling = Arc::new(Mutex::new(Handling {
+            count: 0,
+            finished: Instant::now(),
+        }));
         let reader = async move {
-            while let Some(msg) = framed_stdin.next().await {
+            let mut last_received = Instant::now();
+            let mut last_probe: Option<Instant> = None;
+            let mut probes = 0;
+            let mut initialized = false;
+            loop {
+                // Stop reading as soon as the service reports an error, even if no further message
+                // arrives (e.g., the service exited because the client process has disappeared).
+                let exited = future::poll_fn(|cx| match service.poll_ready(cx) {
+                    Poll::Ready(Err(err)) => Poll::Ready(err),
+                    _ => Poll::Pending,
+                });
+                let received = future::select(exited, framed_stdin.next());
+
+                let active = last_active(&handling, last_received);
+                let timeout = idle_timeout.map(|timeout| active + timeout);
+                let probe = keepalive
+                    .filter(|_| initialized)
+                    .map(|interval| next_probe(interval, active, last_probe));
+                let timer = match (timeout, probe) {
+                    (Some(timeout), Some(probe)) => Some(timeout.min(probe)),
+                    (timeout, probe) => timeout.or(probe),
+                };
+                let timer = match timer {
+                    Some(at) => Either::Left(Delay::new(at.saturating_duration_since(Instant::now()))),
+                    None => Either::Right(future::pending()),
+                };
+
+                let msg = match future::select(received, timer).await {
+                    Either::Left((Either::Left((err, _)), _)) => return into_outcome(err.into()),
+                    Either::Left((Either::Right((Some(msg), _)), _)) => msg,
+                    Either::Left((Either::Right((None, _)), _)) => {
+                        log::info!("input stream closed, stopping");
+                        return ServeOutcome::Eof;
+                    },
+                    Either::Right(((), _)) => {
+                        // Messages may have finished being handled while waiting.
+                        let active = last_active(&handling, last_received);
+                        let idle = active.elapsed();
+                        if matches!(idle_timeout, Some(timeout) if idle >= timeout) {
+                            log::warn!("no message received from the client for {:?}, stopping", idle);
+                            return ServeOutcome::IdleTimeout;
+                        }
+                        let probe = keepalive
+                            .filter(|_| initialized)
+                            .map(|interval| next_probe(interval, active, last_probe));
+                        if matches!(probe, Some(probe) if probe <= Instant::now()) {
+                            log::debug!("no message received from the client for {:?}, probing it", idle);
+                            let id = Id::String(format!("{}/{}", KEEPALIVE_METHOD, probes));
+                            let request = ClientRequest::raw_request(id, KEEPALIVE_METHOD, serde_json::json!({}));
+                            let probe_fut = future::ready(Some(Outgoing::Request(request)));
+                            if sender.send(Either::Right(probe_fut)).await.is_err() {
+                                return into_outcome(output_closed());
+                            }
+                            last_probe = Some(Instant::now());
+                            probes += 1;
+                        }
+                        continue;
+                    },
+                };
+                last_received = Instant::now();
+
                 let request = match msg {
+                    Ok(Incoming::Response(response)) if is_keepalive(&response) => continue,
                     Ok(req) => req,
                     Err(err) => {
                         log::error!("failed to decode message: {}", err);
                         let response = Response::error(None, jsonrpc::Error::parse_error());
                         let response_fut = future::ready(Some(Outgoing::Response(response)));
-                        sender.send(Either::Right(response_fut)).await.unwrap();
+                        if sender.send(Either::Right(response_fut)).await.is_err() {
+                            return into_outcome(output_closed());
+                        }
                         continue;
                     },
                 };
 
-                if let Err(err) = future::poll_fn(|cx| service.poll_ready(cx)).await {
-                    log::error!("{}", display_sources(err.into().as_ref()));
-                    return;
+                match request.method() {
+                    Some("initialized") => initialized = true,
+                    Some("shutdown") => initialized = false,
+                    _ => {},
                 }
 
-                let response_fut = service.call(request).unwrap_or_else(|err| {
-                    log::error!("{}", display_sources(err.into().as_ref()));
-                    None
-                });
+                if let Err(err) = future::poll_fn(|cx| service.poll_ready(cx)).await {
+                    return into_outcome(err.into());
+                }
 
-                sender.send(Either::Left(response_fut)).await.unwrap();
+                handling.lock().unwrap_or_else(PoisonError::into_inner).count += 1;
+                let finished = handling.clone();
+                let response_fut = service
+                    .call(request)
+                    .unwrap_or_else(|err| {
+                        log::error!("{}", display_sources(err.into().as_ref()));
+                        None
+                    })
+                    .inspect(move |_| {
+                        let mut handling = finished.lock().unwrap_or_else(PoisonError::into_inner);
+                        handling.count -= 1;
+                        handling.finished = Instant::now();
+                    });
+
+                if sender.send(Either::Left(response_fut)).await.is_err() {
+                    return into_outcome(output_closed());
+                }
             }
         };
 
-        futures::join!(reader, printer);
+        let (outcome, ()) = future::join(reader, printer).await;
+        outcome
     }
 }
 
+fn into_outcome(error: Box<dyn Error + Send + Sync>) -> ServeOutcome {
+    let outcome = ServeOutcome::from(error);
+    match outcome {
+        ServeOutcome::Exited => log::info!("language server exited"),
+        ServeOutcome::ExitedWithoutShutdown => log::warn!("language server exited without shutting down"),
+        ServeOutcome::Error(ref err) => log::error!("{}", display_sources(err.as_ref())),
+        ServeOutcome::Eof | ServeOutcome::IdleTimeout => {},
+    }
+    outcome
+}
+
+/// Messages still being handled by the service, see [`Server::idle_timeout`].
+#[derive(Debug)]
+struct Handling {
+    count: usize,
+    /// When the service last finished handling a message.
+    finished: Instant,
+}
+
+/// Returns when the client was last active, which is now while any of its messages are still
+/// being handled.
+fn last_active(handling: &Mutex<Handling>, last_received: Instant) -> Instant {
+    let handling = handling.lock().unwrap_or_else(PoisonError::into_inner);
+    if handling.count > 0 {
+        Instant::now()
+    } else {
+        last_received.max(handling.finished)
+    }
+}
+
+/// Returns when to probe the client next, given when it was last active and last probed.
+fn next_probe(interval: Duration, active: Instant, last_probe: Option<Instant>) -> Instant {
+    last_probe.map_or(active, |probe| probe.max(active)) + interval
+}
+
+/// Returns whether the given response answers a keepalive probe.
+fn is_keepalive(response: &Response) -> bool {
+    matches!(response.id(), Some(Id::String(id)) if id.starts_with(KEEPALIVE_METHOD))
+}
+
+/// Error returned once writing to `stdout` has failed and no further responses can be sent.
+fn output_closed() -> Box<dyn Error + Send + Sync> {
+    "failed to write to the output stream".into()
+}
+
 fn display_sources(error: &dyn Error) -> String {
     if let Some(source) = error.source() {
         format!("{}: {}", error, display_sources(source))
@@ -149,15 +431,71 @@ impl Stream for Nothing {
 mod tests {
     use super::*;
     use futures::{future, future::Ready, stream};
+    use futures_timer::Delay;
+    use std::time::Duration;
 
     #[cfg(feature = "runtime-agnostic")]
-    use futures::io::Cursor;
+    use futures::io::{AsyncReadExt, AsyncWriteExt, Cursor};
     #[cfg(feature = "runtime-tokio")]
     use std::io::Cursor;
+    #[cfg(feature = "runtime-tokio")]
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
     const REQUEST: &str = r#"{"jsonrpc":"2.0","method":"initialize","params":{},"id":1}"#;
     const RESPONSE: &str = r#"{"jsonrpc":"2.0","result":{"capabilities":{}},"id":1}"#;
 
+    /// Runs each of the given tests on tokio and, in the `runtime-agnostic` build, on async-std and
+    /// smol as well.
+    macro_rules! runtime_tests {
+        ($($test:ident),* $(,)?) => {
+            mod on_tokio {
+                $(
+                    #[tokio::test]
+                    async fn $test() {
+                        super::$test().await
+                    }
+                )*
+            }
+
+            #[cfg(feature = "runtime-agnostic")]
+            mod on_async_std {
+                $(
+                    #[async_std::test]
+                    async fn $test() {
+                        super::$test().await
+                    }
+                )*
+            }
+
+            #[cfg(feature = "runtime-agnostic")]
+            mod on_smol {
+                $(
+                    #[test]
+                    fn $test() {
+                        smol::block_on(super::$test())
+                    }
+                )*
+            }
+        };
+    }
+
+    runtime_tests!(
+        handles_invalid_json,
+        interleaves_messages,
+        serves_layered_service,
+        counts_messages,
+        serves_on_stdio,
+        serve_outcome,
+        serve_outcome_error,
+        stops_when_output_fails,
+        stops_when_service_exits,
+        stops_when_idle,
+        not_idle_while_handling,
+        sends_keepalive_probes,
+        probes_only_when_initialized,
+        consumes_keepalive_responses,
+    );
+
     #[derive(Debug)]
     struct MockService;
 
@@ -170,16 +508,41 @@ mod tests {
             Poll::Ready(Ok(()))
         }
 
-        fn call(&mut self, _: Incoming) -> Self::Future {
+        fn call(&mut self, incoming: Incoming) -> Self::Future {
+            if incoming.id().is_none() {
+                return future::ok(None);
+            }
             let value = serde_json::from_str(RESPONSE).unwrap();
             future::ok(Some(Outgoing::Response(value)))
         }
     }
 
+    #[derive(Debug)]
+    struct Mock;
+
+    #[crate::async_trait]
+    impl crate::LanguageServer for Mock {
+        async fn initialize(&self, _: lsp::InitializeParams) -> jsonrpc::Result<lsp::InitializeResult> {
+            Ok(lsp::InitializeResult::default())
+        }
+
+        async fn shutdown(&self) -> jsonrpc::Result<()> {
+            Ok(())
+        }
+    }
+
     fn mock_request() -> Vec<u8> {
         format!("Content-Length: {}\r\n\r\n{}", REQUEST.len(), REQUEST).into_bytes()
     }
 
+    fn mock_message(message: &str) -> Vec<u8> {
+        format!("Content-Length: {}\r\n\r\n{}", message.len(), message).into_bytes()
+    }
+
+    fn initialized() -> Vec<u8> {
+        mock_message(r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#)
+    }
+
     fn mock_response() -> Vec<u8> {
         format!("Content-Length: {}\r\n\r\n{}", RESPONSE.len(), RESPONSE).into_bytes()
     }
@@ -188,21 +551,32 @@ mod tests {
         (Cursor::new(mock_request()), Vec::new())
     }
 
-    #[tokio::test]
+    /// Returns the read and write halves of an in-memory pipe.
+    #[cfg(feature = "runtime-agnostic")]
+    fn pipe() -> (piper::Reader, piper::Writer) {
+        piper::pipe(1024)
+    }
+
+    /// Returns the read and write halves of an in-memory pipe.
+    #[cfg(feature = "runtime-tokio")]
+    fn pipe() -> (tokio::io::DuplexStream, tokio::io::DuplexStream) {
+        tokio::io::duplex(1024)
+    }
+
     async fn handles_invalid_json() {
         let invalid = r#"{"jsonrpc":"2.0","method":"#;
         let message = format!("Content-Length: {}\r\n\r\n{}", invalid.len(), invalid).into_bytes();
         let (mut stdin, mut stdout) = (Cursor::new(message), Vec::new());
 
-        Server::new(&mut stdin, &mut stdout).serve(MockService).await;
+        let outcome = Server::new(&mut stdin, &mut stdout).serve(MockService).await;
 
+        assert!(matches!(outcome, ServeOutcome::Eof));
         assert_eq!(stdin.position(), 48);
         let err = r#"{"jsonrpc":"2.0","error":{"code":-32700,"message":"Parse error"},"id":null}"#;
         let output = format!("Content-Length: {}\r\n\r\n{}", err.len(), err).into_bytes();
         assert_eq!(stdout, output);
     }
 
-    #[tokio::test]
     async fn interleaves_messages() {
         let message = Outgoing::Response(serde_json::from_str(RESPONSE).unwrap());
         let messages = stream::iter(vec![message]);
@@ -218,15 +592,305 @@ mod tests {
         assert_eq!(stdout, output);
     }
 
-    #[tokio::test]
+    async fn serves_layered_service() {
+        use crate::layers::{LatencyLayer, Layer, LoggingLayer};
+
+        let latency = LatencyLayer::new();
+        let metrics = latency.metrics();
+        let service = LoggingLayer::default().layer(latency.layer(MockService));
+
+        let (mut stdin, mut stdout) = mock_stdio();
+        let outcome = Server::new(&mut stdin, &mut stdout).serve(service).await;
+
+        assert!(matches!(outcome, ServeOutcome::Eof));
+        assert_eq!(stdout, mock_response());
+        assert_eq!(metrics.get("initialize").unwrap().count, 1);
+    }
+
+    async fn counts_messages() {
+        use crate::LspService;
+
+        let request = r#"{"jsonrpc":"2.0","method":"initialize","params":{"capabilities":{}},"id":1}"#;
+        let message = format!("Content-Length: {}\r\n\r\n{}", request.len(), request).into_bytes();
+        let (mut stdin, mut stdout) = (Cursor::new(message.clone()), Vec::new());
+
+        let (service, _) = LspService::new(|_| Mock);
+        let metrics = service.metrics();
+        Server::new(&mut stdin, &mut stdout)
+            .metrics(metrics.clone())
+            .serve(service)
+            .await;
+
+        let status = metrics.status();
+        assert_eq!(
+            (status.messages_received, status.bytes_received),
+            (1, message.len() as u64)
+        );
+        assert_eq!((status.messages_sent, status.bytes_sent), (1, stdout.len() as u64));
+        assert_eq!(status.methods["initialize"].count, 1);
+    }
+
     async fn serves_on_stdio() {
         let (mut stdin, mut stdout) = mock_stdio();
-        Server::new(&mut stdin, &mut stdout).serve(MockService).await;
+        let outcome = Server::new(&mut stdin, &mut stdout).serve(MockService).await;
 
+        assert!(matches!(outcome, ServeOutcome::Eof));
+        assert_eq!(outcome.exit_code(), 1);
         assert_eq!(stdin.position(), 80);
         assert_eq!(stdout, mock_response());
     }
 
+    async fn serve_outcome() {
+        use crate::LspService;
+
+        const INITIALIZE: &str = r#"{"jsonrpc":"2.0","method":"initialize","params":{"capabilities":{}},"id":1}"#;
+        const SHUTDOWN: &str = r#"{"jsonrpc":"2.0","method":"shutdown","id":2}"#;
+        const EXIT: &str = r#"{"jsonrpc":"2.0","method":"exit"}"#;
+
+        async fn run(messages: &[&str]) -> ServeOutcome {
+            let (mut stdin, mut client_stdin) = pipe();
+            let (mut client_stdout, mut stdout) = pipe();
+            let (service, _) = LspService::new(|_| Mock);
+            let serve = Server::new(&mut stdin, &mut stdout).serve(service);
+
+            let client = async move {
+                for message in messages {
+                    let message = format!("Content-Length: {}\r\n\r\n{}", message.len(), message);
+                    client_stdin.write_all(message.as_bytes()).await.unwrap();
+                    if message.contains(r#""id""#) {
+                        // Wait for the response, just like a client would.
+                        let _ = client_stdout.read(&mut [0; 1024]).await.unwrap();
+                    }
+                }
+                client_stdin
+            };
+
+            let (outcome, _client_stdin) = future::join(serve, client).await;
+            outcome
+        }
+
+        let outcome = run(&[INITIALIZE, SHUTDOWN, EXIT]).await;
+        assert!(matches!(outcome, ServeOutcome::Exited));
+        assert_eq!(outcome.exit_code(), 0);
+
+        let outcome = run(&[INITIALIZE, EXIT]).await;
+        assert!(matches!(outcome, ServeOutcome::ExitedWithoutShutdown));
+        assert_eq!(outcome.exit_code(), 1);
+    }
+
+    async fn serve_outcome_error() {
+        #[derive(Debug)]
+        struct FailingService;
+
+        impl Service<Incoming> for FailingService {
+            type Error = String;
+            type Future = Ready<Result<Self::Response, Self::Error>>;
+            type Response = Option<Outgoing>;
+
+            fn poll_ready(&mut self, _: &mut Context) -> Poll<Result<(), Self::Error>> {
+                Poll::Ready(Err("failed".into()))
+            }
+
+            fn call(&mut self, _: Incoming) -> Self::Future {
+                future::ok(None)
+            }
+        }
+
+        let (mut stdin, mut stdout) = mock_stdio();
+        let outcome = Server::new(&mut stdin, &mut stdout).serve(FailingService).await;
+        assert!(matches!(outcome, ServeOutcome::Error(ref err) if err.to_string() == "failed"));
+        assert!(stdout.is_empty());
+    }
+
+    async fn stops_when_output_fails() {
+        let (mut stdin, mut client_stdin) = pipe();
+        let (client_stdout, mut stdout) = pipe();
+        drop(client_stdout);
+
+        let serve = Server::new(&mut stdin, &mut stdout).serve(MockService);
+        let client = async move {
+            for _ in 0 .. 4 {
+                client_stdin.write_all(&mock_request()).await.unwrap();
+                Delay::new(Duration::from_millis(20)).await;
+            }
+        };
+
+        let (outcome, ()) = future::join(serve, client).await;
+        assert!(matches!(outcome, ServeOutcome::Error(ref err) if err.to_string() == output_closed().to_string()));
+    }
+
+    async fn stops_when_service_exits() {
+        use std::sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        };
+
+        #[derive(Debug, Default)]
+        struct ExitingService(Arc<AtomicBool>);
+
+        impl Service<Incoming> for ExitingService {
+            type Error = String;
+            type Future = Ready<Result<Self::Response, Self::Error>>;
+            type Response = Option<Outgoing>;
+
+            fn poll_ready(&mut self, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+                if self.0.load(Ordering::SeqCst) {
+                    Poll::Ready(Err("exited".into()))
+                } else {
+                    cx.waker().wake_by_ref();
+                    Poll::Ready(Ok(()))
+                }
+            }
+
+            fn call(&mut self, _: Incoming) -> Self::Future {
+                future::ok(None)
+            }
+        }
+
+        // Keep the write half alive so that reading from `stdin` never reaches EOF.
+        let (mut stdin, _client_stdin) = pipe();
+        let mut stdout = Vec::new();
+        let service = ExitingService::default();
+        let exited = service.0.clone();
+
+        let serve = Server::new(&mut stdin, &mut stdout).serve(service);
+        let exit = async move {
+            Delay::new(Duration::from_millis(10)).await;
+            exited.store(true, Ordering::SeqCst);
+        };
+        future::join(serve, exit).await;
+
+        assert!(stdout.is_empty());
+    }
+
+    async fn stops_when_idle() {
+        // Keep the write half alive so that reading from `stdin` never reaches EOF.
+        let (mut stdin, _client_stdin) = pipe();
+        let mut stdout = Vec::new();
+
+        let outcome = Server::new(&mut stdin, &mut stdout)
+            .idle_timeout(Duration::from_millis(30))
+            .serve(MockService)
+            .await;
+
+        assert!(matches!(outcome, ServeOutcome::IdleTimeout));
+        assert_eq!(outcome.exit_code(), 1);
+        assert!(stdout.is_empty());
+    }
+
+    async fn not_idle_while_handling() {
+        use futures::future::BoxFuture;
+
+        #[derive(Debug)]
+        struct SlowService;
+
+        impl Service<Incoming> for SlowService {
+            type Error = String;
+            type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+            type Response = Option<Outgoing>;
+
+            fn poll_ready(&mut self, _: &mut Context) -> Poll<Result<(), Self::Error>> {
+                Poll::Ready(Ok(()))
+            }
+
+            fn call(&mut self, _: Incoming) -> Self::Future {
+                Box::pin(async {
+                    Delay::new(Duration::from_millis(60)).await;
+                    Ok(Some(Outgoing::Response(serde_json::from_str(RESPONSE).unwrap())))
+                })
+            }
+        }
+
+        let (mut stdin, mut client_stdin) = pipe();
+        let mut stdout = Vec::new();
+        client_stdin.write_all(&mock_request()).await.unwrap();
+
+        let start = Instant::now();
+        let outcome = Server::new(&mut stdin, &mut stdout)
+            .idle_timeout(Duration::from_millis(30))
+            .serve(SlowService)
+            .await;
+
+        assert!(matches!(outcome, ServeOutcome::IdleTimeout));
+        assert!(start.elapsed() >= Duration::from_millis(90), "{:?}", start.elapsed());
+        assert_eq!(stdout, mock_response());
+    }
+
+    async fn sends_keepalive_probes() {
+        let (mut stdin, mut client_stdin) = pipe();
+        let mut stdout = Vec::new();
+        client_stdin.write_all(&initialized()).await.unwrap();
+
+        let outcome = Server::new(&mut stdin, &mut stdout)
+            .idle_timeout(Duration::from_millis(100))
+            .keepalive(Duration::from_millis(20))
+            .serve(MockService)
+            .await;
+
+        assert!(matches!(outcome, ServeOutcome::IdleTimeout));
+        let stdout = String::from_utf8(stdout).unwrap();
+        let probe = r#"{"jsonrpc":"2.0","method":"$/lspower/keepalive","params":{},"id":"$/lspower/keepalive/0"}"#;
+        assert!(stdout.starts_with(&format!("Content-Length: {}\r\n\r\n{}", probe.len(), probe)));
+        assert!(stdout.matches(KEEPALIVE_METHOD).count() >= 4);
+    }
+
+    async fn probes_only_when_initialized() {
+        let (mut stdin, mut client_stdin) = pipe();
+        let mut stdout = Vec::new();
+
+        let outcome = Server::new(&mut stdin, &mut stdout)
+            .idle_timeout(Duration::from_millis(60))
+            .keepalive(Duration::from_millis(20))
+            .serve(MockService)
+            .await;
+        assert!(matches!(outcome, ServeOutcome::IdleTimeout));
+        assert!(stdout.is_empty());
+
+        let shutdown = r#"{"jsonrpc":"2.0","method":"shutdown","id":1}"#;
+        client_stdin.write_all(&initialized()).await.unwrap();
+        client_stdin.write_all(&mock_message(shutdown)).await.unwrap();
+        let outcome = Server::new(&mut stdin, &mut stdout)
+            .idle_timeout(Duration::from_millis(60))
+            .keepalive(Duration::from_millis(20))
+            .serve(MockService)
+            .await;
+        assert!(matches!(outcome, ServeOutcome::IdleTimeout));
+        assert_eq!(stdout, mock_response());
+    }
+
+    async fn consumes_keepalive_responses() {
+        let (mut stdin, mut client_stdin) = pipe();
+        let (mut client_stdout, mut stdout) = pipe();
+
+        let serve = Server::new(&mut stdin, &mut stdout)
+            .idle_timeout(Duration::from_millis(60))
+            .keepalive(Duration::from_millis(20))
+            .serve(MockService);
+        let client = async move {
+            client_stdin.write_all(&initialized()).await.unwrap();
+
+            // Answer each probe like a client which doesn't know the method, for longer than the
+            // idle timeout.
+            for _ in 0 .. 5 {
+                let mut buf = [0; 1024];
+                let n = client_stdout.read(&mut buf).await.unwrap();
+                let probe = String::from_utf8_lossy(&buf[.. n]).into_owned();
+                assert!(probe.contains(KEEPALIVE_METHOD), "{}", probe);
+
+                let id = probe.rsplit(r#""id":"#).next().unwrap().trim_end_matches('}');
+                let response = format!(
+                    r#"{{"jsonrpc":"2.0","error":{{"code":-32601,"message":"Method not found"}},"id":{}}}"#,
+                    id
+                );
+                let message = format!("Content-Length: {}\r\n\r\n{}", response.len(), response);
+                client_stdin.write_all(message.as_bytes()).await.unwrap();
+            }
+        };
+
+        let (outcome, ()) = future::join(serve, client).await;
+        assert!(matches!(outcome, ServeOutcome::Eof));
+    }
+
     #[derive(Debug)]
     struct CustomError;
 
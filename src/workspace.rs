@@ -0,0 +1,825 @@
+//! Server instances per workspace folder.
+//!
+//! In a multi-root workspace, a single [`LanguageServer`] has to keep the state of every folder
+//! apart by itself. [`WorkspaceFolders`] instead creates one instance of the server per folder,
+//! and implements [`LanguageServer`] by routing each message to the instances it concerns:
+//!
+//! * requests and notifications for a document (e.g., `textDocument/hover`) go to the instance of
+//!   the innermost folder containing it, or to the instance without folder if the editor opened
+//!   none, and are rejected if no instance owns the document,
+//! * resolve requests (e.g., `completionItem/resolve`) go back to the instance which returned the
+//!   item,
+//! * `workspace/executeCommand` requests go to a single instance, as detailed below, and
+//! * any other message (e.g., `workspace/symbol` or `workspace/didChangeConfiguration`) goes to all
+//!   instances, combining their results.
+//!
+//! Each instance is initialized with its folder as the only folder of the workspace. Instances are
+//! created and shut down as folders are added to and removed from the workspace, for which the
+//! server advertises the `workspace.workspaceFolders` capability. Documents which are open while
+//! the folders change are closed on the instance which owned them and opened on their new owner.
+//!
+//! ```rust
+//! use lspower::{
+//!     jsonrpc::Result,
+//!     lsp::*,
+//!     workspace::WorkspaceFolders,
+//!     Client,
+//!     LanguageServer,
+//!     LspService,
+//! };
+//!
+//! struct Project {
+//!     client: Client,
+//!     root: Option<Url>,
+//! }
+//!
+//! #[lspower::async_trait]
+//! impl LanguageServer for Project {
+//!     async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+//!         Ok(InitializeResult::default())
+//!     }
+//!
+//!     async fn shutdown(&self) -> Result<()> {
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let (service, messages) = LspService::new(|client| {
+//!     WorkspaceFolders::new(client, |client, folder| Project {
+//!         client,
+//!         root: folder.map(|folder| folder.uri.clone()),
+//!     })
+//! });
+//! ```
+//!
+//! Since `workspace/executeCommand` requests carry no document, each command is executed by the
+//! first instance which advertised it in its `executeCommandProvider` capability, or by the first
+//! instance if none did (e.g., because the command was registered dynamically).
+//!
+//! [`LanguageServer`]: crate::LanguageServer
+
+use crate::{
+    jsonrpc::{Error, Result},
+    notebook::{DidCloseNotebookDocumentParams, DidOpenNotebookDocumentParams, NotebookDocumentIdentifier},
+    proxy::{merge, OpenDocuments},
+    Client,
+    LanguageServer,
+};
+use futures::future::{self, BoxFuture, Either};
+use lsp::request::Request;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use std::{
+    fmt::{self, Debug, Formatter},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+        Mutex,
+        PoisonError,
+        RwLock,
+    },
+};
+
+type Init<S> = Box<dyn Fn(Client, Option<&lsp::WorkspaceFolder>) -> S + Send + Sync>;
+
+/// Language server which creates an instance of `S` per workspace folder.
+///
+/// See the [module documentation](self) for details.
+pub struct WorkspaceFolders<S> {
+    client: Client,
+    init: Init<S>,
+    params: RwLock<Option<lsp::InitializeParams>>,
+    instances: RwLock<Vec<Instance<S>>>,
+    documents: Mutex<OpenDocuments>,
+    next_id: AtomicUsize,
+}
+
+struct Instance<S> {
+    id: usize,
+    folder: Option<lsp::WorkspaceFolder>,
+    /// Commands advertised by the instance upon initialization.
+    commands: Vec<String>,
+    server: Arc<S>,
+}
+
+impl<S> Clone for Instance<S> {
+    fn clone(&self) -> Self {
+        Instance {
+            id: self.id,
+            folder: self.folder.clone(),
+            commands: self.commands.clone(),
+            server: self.server.clone(),
+        }
+    }
+}
+
+impl<S: LanguageServer> WorkspaceFolders<S> {
+    /// Creates a new `WorkspaceFolders`, which creates the instance for each folder with `init`.
+    ///
+    /// Instances are created once the workspace folders are known, i.e., upon receiving the
+    /// `initialize` request. If the editor opened no folder, or once all folders are removed, a
+    /// single instance is created with `None` as folder, which receives the messages for all
+    /// documents.
+    pub fn new<F>(client: Client, init: F) -> Self
+    where
+        F: Fn(Client, Option<&lsp::WorkspaceFolder>) -> S + Send + Sync + 'static,
+    {
+        WorkspaceFolders {
+            client,
+            init: Box::new(init),
+            params: RwLock::new(None),
+            instances: RwLock::new(Vec::new()),
+            documents: Mutex::new(OpenDocuments::default()),
+            next_id: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the workspace folders which currently have an instance.
+    pub fn folders(&self) -> Vec<lsp::WorkspaceFolder> {
+        let instances = self.instances.read().unwrap_or_else(PoisonError::into_inner);
+        instances
+            .iter()
+            .filter_map(|instance| instance.folder.clone())
+            .collect()
+    }
+
+    /// Returns the instance which the messages for the document with the given URI are routed to,
+    /// if any.
+    pub fn instance(&self, uri: &lsp::Url) -> Option<Arc<S>> {
+        let instances = self.instances.read().unwrap_or_else(PoisonError::into_inner);
+        owner(&instances, uri).map(|instance| instance.server.clone())
+    }
+
+    fn create(&self, folder: Option<lsp::WorkspaceFolder>) -> Instance<S> {
+        Instance {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            commands: Vec::new(),
+            server: Arc::new((self.init)(self.client.clone(), folder.as_ref())),
+            folder,
+        }
+    }
+
+    /// Creates and initializes an instance per workspace folder, answering with their combined
+    /// capabilities.
+    pub(crate) async fn initialize_folders(&self, params: lsp::InitializeParams) -> Result<lsp::InitializeResult> {
+        let folders = match (&params.workspace_folders, &params.root_uri) {
+            (Some(folders), _) if !folders.is_empty() => folders.iter().cloned().map(Some).collect(),
+            (_, Some(root)) => vec![Some(folder(root.clone()))],
+            _ => vec![None],
+        };
+        let mut instances: Vec<_> = folders.into_iter().map(|folder| self.create(folder)).collect();
+
+        let results = future::join_all(instances.iter().map(|instance| {
+            let params = instance_params(&params, instance.folder.as_ref());
+            instance.server.initialize(params)
+        }))
+        .await;
+
+        let mut capabilities = Vec::with_capacity(results.len());
+        let mut server_info = None;
+        for (instance, result) in instances.iter_mut().zip(results) {
+            let result = result?;
+            crate::capabilities::check(&*instance.server, &result.capabilities);
+            instance.commands = commands(&result.capabilities);
+            capabilities.push(serde_json::to_value(result.capabilities).unwrap_or(Value::Null));
+            server_info = server_info.or(result.server_info);
+        }
+
+        let mut capabilities: lsp::ServerCapabilities = serde_json::from_value(merge::capabilities(capabilities))
+            .map_err(|e| {
+                log::error!("failed to combine the capabilities of the instances: {}", e);
+                Error::internal_error()
+            })?;
+        let workspace = capabilities.workspace.get_or_insert_with(Default::default);
+        workspace.workspace_folders = Some(lsp::WorkspaceFoldersServerCapabilities {
+            supported: Some(true),
+            change_notifications: Some(lsp::OneOf::Left(true)),
+        });
+
+        *self.params.write().unwrap_or_else(PoisonError::into_inner) = Some(params);
+        *self.instances.write().unwrap_or_else(PoisonError::into_inner) = instances;
+        Ok(lsp::InitializeResult {
+            capabilities,
+            server_info,
+        })
+    }
+
+    /// Shuts down all instances, returning the first error if any of them fails.
+    pub(crate) async fn shutdown_folders(&self) -> Result<()> {
+        let instances = self.instances.read().unwrap_or_else(PoisonError::into_inner).clone();
+        let results = future::join_all(instances.iter().map(|instance| instance.server.shutdown())).await;
+        results.into_iter().collect()
+    }
+
+    /// Shuts down the instances of the removed folders, along with the instance without folder if
+    /// any folder is added, and creates and initializes instances for the added folders, moving the
+    /// open documents to their new owners.
+    pub(crate) async fn change_folders(&self, params: lsp::DidChangeWorkspaceFoldersParams) {
+        let owners = self.owners();
+        let removed: Vec<_> = {
+            let mut instances = self.instances.write().unwrap_or_else(PoisonError::into_inner);
+            let (removed, kept) = instances
+                .drain(..)
+                .partition(|instance: &Instance<S>| match &instance.folder {
+                    Some(folder) => params.event.removed.iter().any(|removed| removed.uri == folder.uri),
+                    None => !params.event.added.is_empty(),
+                });
+            *instances = kept;
+            removed
+        };
+        for instance in removed {
+            if let Err(error) = instance.server.shutdown().await {
+                log::warn!("failed to shut down the instance of a removed folder: {}", error);
+            }
+        }
+
+        let initialize_params = match &*self.params.read().unwrap_or_else(PoisonError::into_inner) {
+            Some(initialize_params) => initialize_params.clone(),
+            None => return,
+        };
+        let mut added: Vec<_> = params.event.added.into_iter().map(Some).collect();
+        if added.is_empty() && self.instances.read().unwrap_or_else(PoisonError::into_inner).is_empty() {
+            added.push(None);
+        }
+        for folder in added {
+            let mut instance = self.create(folder);
+            let params = instance_params(&initialize_params, instance.folder.as_ref());
+            match instance.server.initialize(params).await {
+                Ok(result) => {
+                    crate::capabilities::check(&*instance.server, &result.capabilities);
+                    instance.commands = commands(&result.capabilities);
+                },
+                Err(error) => {
+                    log::error!("failed to initialize the instance of an added folder: {}", error);
+                    continue;
+                },
+            }
+            instance.server.initialized(lsp::InitializedParams {}).await;
+            let mut instances = self.instances.write().unwrap_or_else(PoisonError::into_inner);
+            instances.push(instance);
+        }
+
+        self.reopen(owners).await;
+    }
+
+    /// Returns the open text documents outside of notebooks and the open notebooks, along with the
+    /// ID of the instance owning them.
+    fn owners(&self) -> Vec<(lsp::Url, Option<usize>)> {
+        let instances = self.instances.read().unwrap_or_else(PoisonError::into_inner);
+        let documents = self.documents.lock().unwrap_or_else(PoisonError::into_inner);
+        let cells: Vec<_> = documents
+            .notebooks
+            .values()
+            .flat_map(|notebook| notebook.cells.iter().map(|cell| &cell.document))
+            .collect();
+        documents
+            .documents
+            .keys()
+            .filter(|uri| !cells.contains(uri))
+            .chain(documents.notebooks.keys())
+            .map(|uri| (uri.clone(), owner(&instances, uri).map(|instance| instance.id)))
+            .collect()
+    }
+
+    /// Closes the given documents on the instances which owned them, if these still exist, and
+    /// opens them on the instances which own them now.
+    async fn reopen(&self, owners: Vec<(lsp::Url, Option<usize>)>) {
+        for (uri, id) in owners {
+            let (previous, current) = {
+                let instances = self.instances.read().unwrap_or_else(PoisonError::into_inner);
+                let previous = instances.iter().find(|instance| Some(instance.id) == id).cloned();
+                (previous, owner(&instances, &uri).cloned())
+            };
+            if previous.as_ref().map(|instance| instance.id) == current.as_ref().map(|instance| instance.id) {
+                continue;
+            }
+
+            let (open, close) = {
+                let documents = self.documents.lock().unwrap_or_else(PoisonError::into_inner);
+                match documents.notebooks.get(&uri) {
+                    Some(notebook) => {
+                        let cells: Vec<_> = notebook
+                            .cells
+                            .iter()
+                            .filter_map(|cell| documents.documents.get(&cell.document))
+                            .collect();
+                        let open = DidOpenNotebookDocumentParams {
+                            notebook_document: notebook.clone(),
+                            cell_text_documents: cells.iter().map(|document| item(document)).collect(),
+                        };
+                        let close = DidCloseNotebookDocumentParams {
+                            notebook_document: NotebookDocumentIdentifier { uri: uri.clone() },
+                            cell_text_documents: cells
+                                .iter()
+                                .map(|document| lsp::TextDocumentIdentifier::new(document.uri().clone()))
+                                .collect(),
+                        };
+                        (Either::Right(open), Either::Right(close))
+                    },
+                    None => match documents.documents.get(&uri) {
+                        Some(document) => {
+                            let open = lsp::DidOpenTextDocumentParams {
+                                text_document: item(document),
+                            };
+                            let close = lsp::DidCloseTextDocumentParams {
+                                text_document: lsp::TextDocumentIdentifier::new(uri.clone()),
+                            };
+                            (Either::Left(open), Either::Left(close))
+                        },
+                        None => continue,
+                    },
+                }
+            };
+
+            if let Some(previous) = previous {
+                match close {
+                    Either::Left(params) => previous.server.did_close(params).await,
+                    Either::Right(params) => previous.server.did_close_notebook_document(params).await,
+                }
+            }
+            if let Some(current) = current {
+                match open {
+                    Either::Left(params) => current.server.did_open(params).await,
+                    Either::Right(params) => current.server.did_open_notebook_document(params).await,
+                }
+            }
+        }
+    }
+
+    /// Forwards a request to the instances it is routed to, combining their results.
+    pub(crate) async fn route_request<P, R, F>(&self, method: &str, params: P, handler: F) -> Result<R>
+    where
+        P: Serialize + DeserializeOwned,
+        R: Serialize + DeserializeOwned + Send + 'static,
+        F: Fn(Arc<S>, P) -> BoxFuture<'static, Result<R>> + Send,
+    {
+        let mut params = serde_json::to_value(params).map_err(|e| Error::invalid_params(e.to_string()))?;
+        let instances = {
+            let instances = self.instances.read().unwrap_or_else(PoisonError::into_inner);
+            let origin = Some(method)
+                .filter(|method| merge::RESOLVE_REQUESTS.contains(method))
+                .and_then(|_| merge::untag(&mut params));
+            match origin {
+                Some(id) => instances.iter().filter(|instance| instance.id == id).cloned().collect(),
+                None if method == lsp::request::ExecuteCommand::METHOD => {
+                    let command = params["command"].as_str().unwrap_or_default();
+                    let advertised = instances
+                        .iter()
+                        .find(|instance| instance.commands.iter().any(|c| c == command));
+                    advertised.or_else(|| instances.first()).cloned().into_iter().collect()
+                },
+                None => {
+                    let documents = self.documents.lock().unwrap_or_else(PoisonError::into_inner);
+                    route(&instances, &documents, &params)
+                },
+            }
+        };
+        if instances.is_empty() {
+            return Err(Error::invalid_params("Document outside of the workspace folders"));
+        }
+
+        let results = future::join_all(instances.iter().map(|instance| {
+            let params = serde_json::from_value(params.clone());
+            let server = instance.server.clone();
+            match params {
+                Ok(params) => handler(server, params),
+                Err(e) => Box::pin(future::err(Error::invalid_params(e.to_string()))),
+            }
+        }))
+        .await;
+
+        let resolvable = merge::RESOLVABLE_REQUESTS.contains(&method) || merge::RESOLVE_REQUESTS.contains(&method);
+        if instances.len() == 1 && !resolvable {
+            return results.into_iter().next().unwrap();
+        }
+
+        let mut first_error = None;
+        let mut values = Vec::with_capacity(results.len());
+        for (instance, result) in instances.iter().zip(results) {
+            match result.map(serde_json::to_value) {
+                Ok(Ok(mut value)) => {
+                    if merge::RESOLVE_REQUESTS.contains(&method) {
+                        merge::tag(&mut value, instance.id);
+                    } else if merge::RESOLVABLE_REQUESTS.contains(&method) {
+                        merge::tag_items(&mut value, instance.id);
+                    }
+                    values.push(value);
+                },
+                Ok(Err(error)) => log::error!("failed to serialize the result of {}: {}", method, error),
+                Err(error) => {
+                    first_error.get_or_insert(error);
+                },
+            }
+        }
+
+        match first_error {
+            Some(error) if values.is_empty() => Err(error),
+            _ => serde_json::from_value(merge::results(values)).map_err(|e| {
+                log::error!("failed to combine the results of {}: {}", method, e);
+                Error::internal_error()
+            }),
+        }
+    }
+
+    /// Forwards a notification to the instances it is routed to, keeping track of the documents
+    /// and notebooks opened by the editor.
+    pub(crate) async fn route_notification<P, F>(&self, method: &str, params: P, handler: F)
+    where
+        P: Serialize + DeserializeOwned,
+        F: Fn(Arc<S>, P) -> BoxFuture<'static, ()> + Send,
+    {
+        let params = match serde_json::to_value(params) {
+            Ok(params) => params,
+            Err(_) => return,
+        };
+        let instances = {
+            let instances = self.instances.read().unwrap_or_else(PoisonError::into_inner);
+            let mut documents = self.documents.lock().unwrap_or_else(PoisonError::into_inner);
+            let routed = route(&instances, &documents, &params);
+            documents.track(method, params.clone());
+            routed
+        };
+        if instances.is_empty() {
+            log::warn!("ignoring {} for a document outside of the workspace folders", method);
+        }
+        future::join_all(instances.into_iter().filter_map(|instance| {
+            let params = serde_json::from_value(params.clone()).ok()?;
+            Some(handler(instance.server, params))
+        }))
+        .await;
+    }
+}
+
+impl<S> Debug for WorkspaceFolders<S> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let instances = self.instances.read().unwrap_or_else(PoisonError::into_inner);
+        let folders: Vec<_> = instances.iter().map(|instance| &instance.folder).collect();
+        f.debug_struct(stringify!(WorkspaceFolders))
+            .field("client", &self.client)
+            .field("folders", &folders)
+            .finish()
+    }
+}
+
+/// Returns the folder of a workspace only known by its root URI.
+fn folder(uri: lsp::Url) -> lsp::WorkspaceFolder {
+    let name = uri
+        .path_segments()
+        .and_then(|segments| segments.rev().find(|segment| !segment.is_empty()))
+        .unwrap_or_default()
+        .to_owned();
+    lsp::WorkspaceFolder { uri, name }
+}
+
+/// Returns the commands advertised in the given capabilities.
+fn commands(capabilities: &lsp::ServerCapabilities) -> Vec<String> {
+    let provider = capabilities.execute_command_provider.as_ref();
+    provider.map(|provider| provider.commands.clone()).unwrap_or_default()
+}
+
+/// Returns the parameters of the `initialize` request for the instance of the given folder, whose
+/// workspace consists of this folder only, or has no folder if `folder` is `None`.
+fn instance_params(params: &lsp::InitializeParams, folder: Option<&lsp::WorkspaceFolder>) -> lsp::InitializeParams {
+    let mut params = params.clone();
+    #[allow(deprecated)]
+    let root_path = &mut params.root_path;
+    *root_path = folder.and_then(|folder| folder.uri.to_file_path().ok().map(|path| path.display().to_string()));
+    params.root_uri = folder.map(|folder| folder.uri.clone());
+    params.workspace_folders = folder.map(|folder| vec![folder.clone()]);
+    params
+}
+
+/// Returns the item opening the given document.
+fn item(document: &crate::proxy::Document) -> lsp::TextDocumentItem {
+    lsp::TextDocumentItem {
+        uri: document.uri().clone(),
+        language_id: document.language_id().to_owned(),
+        version: document.version(),
+        text: document.text().to_owned(),
+    }
+}
+
+/// Returns the instances which a message with the given parameters is routed to.
+///
+/// Messages for the cell of a notebook are routed like the messages for the notebook.
+fn route<S>(instances: &[Instance<S>], documents: &OpenDocuments, params: &Value) -> Vec<Instance<S>> {
+    let uri = ["textDocument", "notebookDocument", "item"]
+        .iter()
+        .find_map(|key| params[key]["uri"].as_str())
+        .and_then(|uri| lsp::Url::parse(uri).ok());
+    match uri {
+        Some(uri) => {
+            let notebook = documents
+                .notebooks
+                .values()
+                .find(|notebook| notebook.cells.iter().any(|cell| cell.document == uri));
+            let uri = notebook.map_or(&uri, |notebook| &notebook.uri);
+            owner(instances, uri).cloned().into_iter().collect()
+        },
+        None => instances.to_vec(),
+    }
+}
+
+/// Returns the instance of the innermost folder containing the given document, falling back to
+/// the instance without folder, if any.
+fn owner<'a, S>(instances: &'a [Instance<S>], uri: &lsp::Url) -> Option<&'a Instance<S>> {
+    instances
+        .iter()
+        .filter_map(|instance| {
+            let folder = &instance.folder.as_ref()?.uri;
+            Some((instance, folder.path().len())).filter(|_| contains(folder, uri))
+        })
+        .max_by_key(|(_, len)| *len)
+        .map(|(instance, _)| instance)
+        .or_else(|| instances.iter().find(|instance| instance.folder.is_none()))
+}
+
+/// Returns whether the folder with the given URI contains the document with the given URI.
+fn contains(folder: &lsp::Url, uri: &lsp::Url) -> bool {
+    let path = folder.path().trim_end_matches('/');
+    folder.scheme() == uri.scheme()
+        && folder.host() == uri.host()
+        && uri.path().starts_with(path)
+        && uri.path()[path.len() ..].starts_with('/')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LspService;
+    use serde_json::json;
+
+    #[derive(Debug)]
+    struct Mock {
+        folder: String,
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl LanguageServer for Mock {
+        async fn initialize(&self, params: lsp::InitializeParams) -> Result<lsp::InitializeResult> {
+            let folders = params.workspace_folders.unwrap_or_default();
+            let names: Vec<_> = folders.iter().map(|folder| folder.name.as_str()).collect();
+            assert!(names.len() <= 1);
+            assert_eq!(names.concat(), self.folder);
+            let capabilities = json!({
+                "hoverProvider": true,
+                "executeCommandProvider": { "commands": [format!("{}.run", self.folder)] },
+            });
+            Ok(serde_json::from_value(json!({ "capabilities": capabilities })).unwrap())
+        }
+
+        async fn shutdown(&self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn did_open(&self, params: lsp::DidOpenTextDocumentParams) {
+            let mut log = self.log.lock().unwrap();
+            log.push(format!("{} opened {}", self.folder, params.text_document.uri.path()));
+        }
+
+        async fn did_close(&self, params: lsp::DidCloseTextDocumentParams) {
+            let mut log = self.log.lock().unwrap();
+            log.push(format!("{} closed {}", self.folder, params.text_document.uri.path()));
+        }
+
+        async fn hover(&self, _: lsp::HoverParams) -> Result<Option<lsp::Hover>> {
+            Ok(Some(lsp::Hover {
+                contents: lsp::HoverContents::Scalar(lsp::MarkedString::String(self.folder.clone())),
+                range: None,
+            }))
+        }
+
+        async fn symbol(&self, _: lsp::WorkspaceSymbolParams) -> Result<Option<Vec<lsp::SymbolInformation>>> {
+            Ok(Some(vec![serde_json::from_value(json!({
+                "name": self.folder,
+                "kind": 5,
+                "location": { "uri": "file:///model.lkml", "range": lsp::Range::default() },
+            }))
+            .unwrap()]))
+        }
+
+        async fn execute_command(&self, _: lsp::ExecuteCommandParams) -> Result<Option<Value>> {
+            Ok(Some(json!(self.folder)))
+        }
+
+        async fn completion(&self, _: lsp::CompletionParams) -> Result<Option<lsp::CompletionResponse>> {
+            let item = lsp::CompletionItem {
+                data: Some(json!(self.folder)),
+                ..lsp::CompletionItem::new_simple(self.folder.clone(), String::new())
+            };
+            Ok(Some(lsp::CompletionResponse::Array(vec![item])))
+        }
+
+        async fn completion_resolve(&self, mut item: lsp::CompletionItem) -> Result<lsp::CompletionItem> {
+            assert_eq!(item.data, Some(json!(self.folder)));
+            item.detail = Some(self.folder.clone());
+            Ok(item)
+        }
+    }
+
+    fn folder(name: &str) -> lsp::WorkspaceFolder {
+        lsp::WorkspaceFolder {
+            uri: format!("file:///projects/{}", name).parse().unwrap(),
+            name: name.rsplit('/').next().unwrap().into(),
+        }
+    }
+
+    fn position(uri: &str) -> lsp::TextDocumentPositionParams {
+        lsp::TextDocumentPositionParams {
+            text_document: lsp::TextDocumentIdentifier {
+                uri: uri.parse().unwrap(),
+            },
+            position: Default::default(),
+        }
+    }
+
+    async fn initialize(names: &[&str]) -> (WorkspaceFolders<Mock>, lsp::InitializeResult) {
+        let mut client = None;
+        let _ = LspService::new(|c| {
+            client = Some(c);
+            Mock {
+                folder: String::new(),
+                log: Default::default(),
+            }
+        });
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let folders = WorkspaceFolders::new(client.unwrap(), move |_, folder| Mock {
+            folder: folder.map(|folder| folder.name.clone()).unwrap_or_default(),
+            log: log.clone(),
+        });
+        let params = lsp::InitializeParams {
+            workspace_folders: Some(names.iter().map(|name| folder(name)).collect()),
+            ..serde_json::from_value(json!({ "capabilities": {} })).unwrap()
+        };
+        let result = folders.initialize(params).await.unwrap();
+        (folders, result)
+    }
+
+    async fn hover(folders: &WorkspaceFolders<Mock>, uri: &str) -> String {
+        let params = lsp::HoverParams {
+            text_document_position_params: position(uri),
+            work_done_progress_params: Default::default(),
+        };
+        match folders.hover(params).await.unwrap().unwrap().contents {
+            lsp::HoverContents::Scalar(lsp::MarkedString::String(folder)) => folder,
+            contents => panic!("unexpected contents: {:?}", contents),
+        }
+    }
+
+    #[tokio::test]
+    async fn routes_by_folder() {
+        let (folders, result) = initialize(&["a", "a/b"]).await;
+        let workspace = result.capabilities.workspace.unwrap().workspace_folders.unwrap();
+        assert_eq!(workspace.supported, Some(true));
+        assert_eq!(workspace.change_notifications, Some(lsp::OneOf::Left(true)));
+        assert_eq!(
+            result.capabilities.hover_provider,
+            Some(lsp::HoverProviderCapability::Simple(true))
+        );
+
+        assert_eq!(hover(&folders, "file:///projects/a/view.lkml").await, "a");
+        assert_eq!(hover(&folders, "file:///projects/a/b/view.lkml").await, "b");
+        assert!(folders
+            .instance(&"file:///projects/ab/view.lkml".parse().unwrap())
+            .is_none());
+
+        let symbols = folders.symbol(Default::default()).await.unwrap().unwrap();
+        let names: Vec<_> = symbols.iter().map(|symbol| symbol.name.as_str()).collect();
+        assert_eq!(names, ["a", "b"]);
+    }
+
+    #[tokio::test]
+    async fn resolves_items() {
+        let (folders, _) = initialize(&["a", "b"]).await;
+
+        let params = lsp::CompletionParams {
+            text_document_position: position("file:///projects/b/view.lkml"),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+            context: None,
+        };
+        let items = match folders.completion(params).await.unwrap() {
+            Some(lsp::CompletionResponse::Array(items)) => items,
+            response => panic!("unexpected response: {:?}", response),
+        };
+        assert_eq!(items.len(), 1);
+        assert_ne!(items[0].data, Some(json!("b")));
+
+        let item = folders.completion_resolve(items[0].clone()).await.unwrap();
+        assert_eq!(item.detail.as_deref(), Some("b"));
+        assert_eq!(item.data, items[0].data);
+    }
+
+    #[tokio::test]
+    async fn executes_commands_once() {
+        let (folders, result) = initialize(&["a", "b"]).await;
+        let commands = result.capabilities.execute_command_provider.unwrap().commands;
+        assert_eq!(commands, ["a.run", "b.run"]);
+
+        let execute = |command: &str| {
+            folders.execute_command(lsp::ExecuteCommandParams {
+                command: command.into(),
+                arguments: Vec::new(),
+                work_done_progress_params: Default::default(),
+            })
+        };
+        assert_eq!(execute("b.run").await, Ok(Some(json!("b"))));
+        assert_eq!(execute("registered.later").await, Ok(Some(json!("a"))));
+    }
+
+    #[tokio::test]
+    async fn changes_folders() {
+        let (folders, _) = initialize(&["a", "b"]).await;
+
+        let params = lsp::DidChangeWorkspaceFoldersParams {
+            event: lsp::WorkspaceFoldersChangeEvent {
+                added: vec![folder("c")],
+                removed: vec![folder("a")],
+            },
+        };
+        folders.did_change_workspace_folders(params).await;
+        assert_eq!(folders.folders(), [folder("b"), folder("c")]);
+        assert_eq!(hover(&folders, "file:///projects/c/view.lkml").await, "c");
+
+        let params = lsp::HoverParams {
+            text_document_position_params: position("file:///projects/a/view.lkml"),
+            work_done_progress_params: Default::default(),
+        };
+        let error = folders.hover(params).await.unwrap_err();
+        assert_eq!(error.code, crate::jsonrpc::ErrorCode::InvalidParams);
+    }
+
+    #[tokio::test]
+    async fn reopens_documents() {
+        let (folders, _) = initialize(&["a"]).await;
+        let log = folders
+            .instance(&"file:///projects/a/view.lkml".parse().unwrap())
+            .unwrap()
+            .log
+            .clone();
+        let change = |added: &[&str], removed: &[&str]| {
+            folders.did_change_workspace_folders(lsp::DidChangeWorkspaceFoldersParams {
+                event: lsp::WorkspaceFoldersChangeEvent {
+                    added: added.iter().map(|name| folder(name)).collect(),
+                    removed: removed.iter().map(|name| folder(name)).collect(),
+                },
+            })
+        };
+
+        for uri in &["file:///projects/a/b/view.lkml", "file:///projects/c/view.lkml"] {
+            let item = lsp::TextDocumentItem::new(uri.parse().unwrap(), "lookml".into(), 0, String::new());
+            folders
+                .did_open(lsp::DidOpenTextDocumentParams { text_document: item })
+                .await;
+        }
+        assert_eq!(log.lock().unwrap().split_off(0), ["a opened /projects/a/b/view.lkml"]);
+
+        // Documents are moved in no particular order.
+        change(&["a/b", "c"], &[]).await;
+        let mut events = log.lock().unwrap().split_off(0);
+        events.sort();
+        assert_eq!(events, [
+            "a closed /projects/a/b/view.lkml",
+            "b opened /projects/a/b/view.lkml",
+            "c opened /projects/c/view.lkml",
+        ]);
+
+        change(&[], &["a", "a/b", "c"]).await;
+        assert_eq!(folders.folders(), []);
+        let mut events = log.lock().unwrap().split_off(0);
+        events.sort();
+        assert_eq!(events, [
+            " opened /projects/a/b/view.lkml",
+            " opened /projects/c/view.lkml"
+        ]);
+    }
+
+    #[tokio::test]
+    async fn replaces_instance_without_folder() {
+        let (folders, _) = initialize(&[]).await;
+        let uri: lsp::Url = "file:///projects/a/view.lkml".parse().unwrap();
+        let log = folders.instance(&uri).unwrap().log.clone();
+
+        let item = lsp::TextDocumentItem::new(uri.clone(), "lookml".into(), 0, String::new());
+        folders
+            .did_open(lsp::DidOpenTextDocumentParams { text_document: item })
+            .await;
+        assert_eq!(log.lock().unwrap().split_off(0), [" opened /projects/a/view.lkml"]);
+
+        let params = lsp::DidChangeWorkspaceFoldersParams {
+            event: lsp::WorkspaceFoldersChangeEvent {
+                added: vec![folder("a")],
+                removed: Vec::new(),
+            },
+        };
+        folders.did_change_workspace_folders(params).await;
+        assert_eq!(folders.folders(), [folder("a")]);
+        assert_eq!(log.lock().unwrap().split_off(0), ["a opened /projects/a/view.lkml"]);
+        assert_eq!(hover(&folders, "file:///projects/a/view.lkml").await, "a");
+
+        let symbols = folders.symbol(Default::default()).await.unwrap().unwrap();
+        let names: Vec<_> = symbols.iter().map(|symbol| symbol.name.as_str()).collect();
+        assert_eq!(names, ["a"]);
+    }
+}
@@ -0,0 +1,100 @@
+//! Workspace symbol resolution, introduced in LSP 3.17.
+//!
+//! Servers may answer [`workspace/symbol`] requests with symbols lacking a range, or other
+//! information which is expensive to compute, and let the client ask for it with the
+//! [`workspaceSymbol/resolve`] request once a symbol is selected, which is handled by
+//! [`LanguageServer::symbol_resolve`].
+//!
+//! These types are not part of `lsp-types` yet, and follow the [specification].
+//!
+//! [`workspace/symbol`]: https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#workspace_symbol
+//! [`workspaceSymbol/resolve`]: https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#workspace_symbolResolve
+//! [`LanguageServer::symbol_resolve`]: crate::LanguageServer::symbol_resolve
+//! [specification]: https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#workspace_symbol
+
+use lsp::{Location, SymbolKind, SymbolTag, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// A symbol found in the workspace.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceSymbol {
+    /// The name of the symbol.
+    pub name: String,
+    /// The kind of the symbol.
+    pub kind: SymbolKind,
+    /// Tags of the symbol.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<SymbolTag>>,
+    /// The name of the symbol containing this symbol, for display only.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container_name: Option<String>,
+    /// The location of the symbol, whose range may be left to [`workspaceSymbol/resolve`].
+    ///
+    /// [`workspaceSymbol/resolve`]: https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#workspace_symbolResolve
+    pub location: WorkspaceSymbolLocation,
+    /// Data preserved between a [`workspace/symbol`] request and a [`workspaceSymbol/resolve`]
+    /// request.
+    ///
+    /// [`workspace/symbol`]: https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#workspace_symbol
+    /// [`workspaceSymbol/resolve`]: https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#workspace_symbolResolve
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+/// The location of a [`WorkspaceSymbol`].
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum WorkspaceSymbolLocation {
+    /// The full location of the symbol.
+    Location(Location),
+    /// The document of the symbol only, if its range has not been resolved yet.
+    Document {
+        /// The URI of the document.
+        uri: Url,
+    },
+}
+
+/// The [`workspaceSymbol/resolve`] request.
+///
+/// [`workspaceSymbol/resolve`]: https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#workspace_symbolResolve
+#[derive(Debug)]
+pub enum WorkspaceSymbolResolve {}
+
+impl lsp::request::Request for WorkspaceSymbolResolve {
+    type Params = WorkspaceSymbol;
+    type Result = WorkspaceSymbol;
+
+    const METHOD: &'static str = "workspaceSymbol/resolve";
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn location() {
+        let symbol: WorkspaceSymbol = serde_json::from_value(json!({
+            "name": "orders",
+            "kind": 5,
+            "location": { "uri": "file:///orders.view.lkml" },
+            "data": 1,
+        }))
+        .unwrap();
+        let uri = "file:///orders.view.lkml".parse().unwrap();
+        assert_eq!(symbol.location, WorkspaceSymbolLocation::Document { uri });
+
+        let location = json!({
+            "uri": "file:///orders.view.lkml",
+            "range": { "start": { "line": 0, "character": 6 }, "end": { "line": 0, "character": 12 } },
+        });
+        let resolved = WorkspaceSymbol {
+            location: serde_json::from_value(location.clone()).unwrap(),
+            ..symbol
+        };
+        assert!(matches!(resolved.location, WorkspaceSymbolLocation::Location(_)));
+        assert_eq!(serde_json::to_value(&resolved).unwrap()["location"], location);
+    }
+}
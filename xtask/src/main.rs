@@ -304,8 +304,23 @@ FLAGS:
             cmd.args(&["test", "--lookml-ls", "--lib", "--tests"]);
             cmd.args(&["--package", "xtask"]);
             cmd.args(&["--package", "lspower"]);
+            cmd.args(&["--features", "process"]);
+            cmd.args(cargo_args.clone());
+            let tokio = cmd.status()?;
+
+            // Run the suite again without tokio, so that async-std and smol keep being covered.
+            let mut cmd = Command::new(metadata::cargo()?);
+            cmd.current_dir(metadata::project_root());
+            cmd.env("RUSTFLAGS", "-Dwarnings");
+            cmd.args(&["test", "--lib", "--tests"]);
+            cmd.args(&["--package", "lspower"]);
+            cmd.args(&["--no-default-features", "--features", "runtime-agnostic"]);
             cmd.args(cargo_args);
-            cmd.status()?;
+            let agnostic = cmd.status()?;
+
+            if !tokio.success() || !agnostic.success() {
+                return Err("tests failed".into());
+            }
 
             Ok(())
         }